@@ -0,0 +1,123 @@
+//! The settings of a pad in a TOML file, to keep or to restore:
+//! ```toml
+//! profile = 1
+//! dpad_mode = "LeftStick"
+//! turbo_rate = 10
+//!
+//! [right_deadzone]
+//! inner = 2000
+//! outer = 31000
+//!
+//! [[remap]]
+//! profile = 0
+//! input = "Matrix(1,2)"
+//! control = "RightStick(Up)"
+//! ```
+//! A stick without deadzone keeps its profile's, see [`crate::names`] for
+//! the buttons and controls.
+
+use serde::{Deserialize, Serialize};
+
+use em_usb_pad_protocol::settings::{Remap, Settings, StickDeadzone, MAX_REMAPS};
+
+use crate::names::{
+    control_name, dpad_mode_name, input_name, parse_control, parse_dpad_mode, parse_input,
+};
+use crate::transport::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Backup {
+    pub profile: u8,
+    pub dpad_mode: String,
+    pub turbo_rate: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_deadzone: Option<DeadzoneEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_deadzone: Option<DeadzoneEntry>,
+    #[serde(default, rename = "remap", skip_serializing_if = "Vec::is_empty")]
+    pub remaps: Vec<RemapEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeadzoneEntry {
+    pub inner: u16,
+    pub outer: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemapEntry {
+    pub profile: u8,
+    pub input: String,
+    pub control: String,
+}
+
+impl Backup {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let deadzone = |deadzone: Option<StickDeadzone>| {
+            deadzone.map(|deadzone| DeadzoneEntry {
+                inner: deadzone.inner,
+                outer: deadzone.outer,
+            })
+        };
+        Backup {
+            profile: settings.profile,
+            dpad_mode: dpad_mode_name(settings.dpad_mode).to_owned(),
+            turbo_rate: settings.turbo_rate,
+            left_deadzone: deadzone(settings.deadzones[0]),
+            right_deadzone: deadzone(settings.deadzones[1]),
+            remaps: settings
+                .remaps()
+                .map(|remap| RemapEntry {
+                    profile: remap.profile,
+                    input: input_name(remap.input),
+                    control: control_name(remap.control),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_settings(&self) -> Result<Settings, Error> {
+        let invalid = |what: &str, text: &str| Error::Invalid(format!("bad {}: {}", what, text));
+        let deadzone = |deadzone: Option<DeadzoneEntry>| {
+            deadzone.map(|deadzone| StickDeadzone {
+                inner: deadzone.inner,
+                outer: deadzone.outer,
+            })
+        };
+        let mut settings = Settings {
+            profile: self.profile,
+            dpad_mode: parse_dpad_mode(&self.dpad_mode)
+                .ok_or_else(|| invalid("D-pad mode", &self.dpad_mode))?,
+            turbo_rate: self.turbo_rate,
+            deadzones: [deadzone(self.left_deadzone), deadzone(self.right_deadzone)],
+            ..Default::default()
+        };
+        if self.remaps.len() > MAX_REMAPS {
+            return Err(Error::Invalid(format!(
+                "{} remaps, at most {}",
+                self.remaps.len(),
+                MAX_REMAPS
+            )));
+        }
+        for remap in &self.remaps {
+            settings.set_remap(Remap {
+                profile: remap.profile,
+                input: parse_input(&remap.input).ok_or_else(|| invalid("button", &remap.input))?,
+                control: parse_control(&remap.control)
+                    .ok_or_else(|| invalid("control", &remap.control))?,
+            });
+        }
+        Ok(settings)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("a backup is always valid TOML")
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        toml::from_str(text).map_err(|e| Error::Invalid(e.to_string()))
+    }
+}
@@ -0,0 +1,288 @@
+//! The command line, run against any [`Transport`].
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use em_usb_pad_protocol::controls::InputId;
+use em_usb_pad_protocol::settings::{Remap, Settings, StickDeadzone};
+
+use crate::backup::Backup;
+use crate::device::{Pad, ProfileInfo};
+use crate::names::{
+    control_name, dpad_mode_name, input_name, parse_control, parse_dpad_mode, parse_input,
+};
+use crate::transport::{Error, Transport};
+use crate::usb::{DEFAULT_PID, DEFAULT_VID};
+
+/// Configure an em-usb-pad over USB
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Vendor ID of the pads, in hex
+    #[arg(long, default_value_t = DEFAULT_VID, value_parser = parse_hex)]
+    pub vid: u16,
+    /// Product ID of the pads, in hex
+    #[arg(long, default_value_t = DEFAULT_PID, value_parser = parse_hex)]
+    pub pid: u16,
+    /// Serial number of the pad, when several are plugged in
+    #[arg(long)]
+    pub serial: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the pads plugged in
+    List,
+    #[command(flatten)]
+    Pad(PadCommand),
+}
+
+/// What is done to one pad
+#[derive(Debug, Subcommand)]
+pub enum PadCommand {
+    /// Show the protocol version and the profile count
+    Info,
+    /// Show the settings and the bindings of the profiles
+    Dump {
+        /// Only this profile, by index or name
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Activate a profile, by index or name
+    Profile { profile: String },
+    /// Pick what the D-pad buttons drive: dpad, left-stick or right-stick
+    Dpad { mode: String },
+    /// Set the turbo pulses per second
+    Turbo {
+        #[arg(value_parser = clap::value_parser!(u8).range(1..))]
+        rate: u8,
+    },
+    /// Set the deadzones of a stick, or give it back its profile's
+    Deadzone {
+        stick: Stick,
+        #[arg(requires = "outer")]
+        inner: Option<u16>,
+        outer: Option<u16>,
+    },
+    /// Bind a button of a profile to another control, e.g.
+    /// `remap 0 "Matrix(1,2)" "LeftStick(Up)"`
+    Remap {
+        profile: String,
+        input: String,
+        control: String,
+    },
+    /// Give a button of a profile back its binding
+    Unmap { profile: String, input: String },
+    /// Write the settings to a TOML file
+    Backup { file: PathBuf },
+    /// Use the settings of a TOML file, and save them
+    Restore { file: PathBuf },
+    /// Save the settings now instead of a moment later
+    Save,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
+
+/// Carry out `command` on `pad`, telling how it went to `out`.
+pub fn run<T: Transport>(
+    pad: &mut Pad<T>,
+    command: &PadCommand,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    match command {
+        PadCommand::Info => {
+            let info = pad.info();
+            writeln!(out, "protocol version {}", info.protocol)?;
+            writeln!(out, "settings version {}", info.settings_version)?;
+            writeln!(out, "{} profiles", info.profiles)?;
+        }
+        PadCommand::Dump { profile } => {
+            let settings = pad.settings()?;
+            let profiles = pad.profiles()?;
+            let shown = match profile {
+                Some(profile) => {
+                    let index = profile_index(&profiles, profile)?;
+                    index..index + 1
+                }
+                None => {
+                    write_settings(out, &settings, &profiles)?;
+                    0..profiles.len()
+                }
+            };
+            for index in shown {
+                writeln!(out)?;
+                write_profile(out, &settings, index, &profiles[index])?;
+            }
+        }
+        PadCommand::Profile { profile } => {
+            let profiles = pad.profiles()?;
+            let index = profile_index(&profiles, profile)?;
+            pad.select_profile(index as u8)?;
+            writeln!(out, "profile {}: {}", index, profiles[index].name)?;
+        }
+        PadCommand::Dpad { mode } => {
+            let mode = parse_dpad_mode(mode)
+                .ok_or_else(|| Error::Invalid(format!("bad D-pad mode: {}", mode)))?;
+            edit(pad, |settings| settings.dpad_mode = mode)?;
+        }
+        PadCommand::Turbo { rate } => edit(pad, |settings| settings.turbo_rate = *rate)?,
+        PadCommand::Deadzone {
+            stick,
+            inner,
+            outer,
+        } => {
+            let deadzone = match (inner, outer) {
+                (Some(inner), Some(outer)) if inner < outer => Some(StickDeadzone {
+                    inner: *inner,
+                    outer: *outer,
+                }),
+                (None, None) => None,
+                _ => {
+                    return Err(Error::Invalid(
+                        "the inner deadzone must be under the outer one".to_owned(),
+                    ))
+                }
+            };
+            edit(pad, |settings| {
+                settings.deadzones[*stick as usize] = deadzone
+            })?;
+        }
+        PadCommand::Remap {
+            profile,
+            input,
+            control,
+        } => {
+            let profile = profile_index(&pad.profiles()?, profile)? as u8;
+            let input = input_arg(input)?;
+            let control = parse_control(control)
+                .ok_or_else(|| Error::Invalid(format!("bad control: {}", control)))?;
+            let mut settings = pad.settings()?;
+            let remap = Remap {
+                profile,
+                input,
+                control,
+            };
+            if !settings.set_remap(remap) {
+                return Err(Error::Invalid("no room for another remap".to_owned()));
+            }
+            pad.set_settings(&settings)?;
+        }
+        PadCommand::Unmap { profile, input } => {
+            let profile = profile_index(&pad.profiles()?, profile)? as u8;
+            let input = input_arg(input)?;
+            let mut settings = pad.settings()?;
+            if !settings.clear_remap(profile, input) {
+                return Err(Error::Invalid(format!(
+                    "{} isn't remapped",
+                    input_name(input)
+                )));
+            }
+            pad.set_settings(&settings)?;
+        }
+        PadCommand::Backup { file } => {
+            let backup = Backup::from_settings(&pad.settings()?);
+            std::fs::write(file, backup.to_toml())?;
+            writeln!(out, "saved to {}", file.display())?;
+        }
+        PadCommand::Restore { file } => {
+            let settings = Backup::from_toml(&std::fs::read_to_string(file)?)?.to_settings()?;
+            pad.set_settings(&settings)?;
+            pad.save()?;
+            writeln!(out, "restored from {}", file.display())?;
+        }
+        PadCommand::Save => pad.save()?,
+    }
+    Ok(())
+}
+
+/// Change the settings of the pad
+fn edit<T: Transport>(pad: &mut Pad<T>, change: impl FnOnce(&mut Settings)) -> Result<(), Error> {
+    let mut settings = pad.settings()?;
+    change(&mut settings);
+    pad.set_settings(&settings)
+}
+
+/// The index of a profile given by index or name
+fn profile_index(profiles: &[ProfileInfo], text: &str) -> Result<usize, Error> {
+    let index = match text.parse::<usize>() {
+        Ok(index) => Some(index).filter(|index| *index < profiles.len()),
+        Err(_) => profiles.iter().position(|profile| profile.name == text),
+    };
+    index.ok_or_else(|| Error::Invalid(format!("no profile {}", text)))
+}
+
+fn input_arg(text: &str) -> Result<InputId, Error> {
+    parse_input(text).ok_or_else(|| Error::Invalid(format!("bad button: {}", text)))
+}
+
+fn write_settings(
+    out: &mut dyn Write,
+    settings: &Settings,
+    profiles: &[ProfileInfo],
+) -> Result<(), Error> {
+    let name = profiles
+        .get(settings.profile as usize)
+        .map_or("?", |profile| &profile.name);
+    writeln!(out, "profile {}: {}", settings.profile, name)?;
+    writeln!(out, "D-pad mode {}", dpad_mode_name(settings.dpad_mode))?;
+    writeln!(out, "turbo {} per second", settings.turbo_rate)?;
+    for (stick, deadzone) in ["left", "right"].into_iter().zip(settings.deadzones) {
+        match deadzone {
+            Some(deadzone) => writeln!(
+                out,
+                "{} deadzone {} to {}",
+                stick, deadzone.inner, deadzone.outer
+            )?,
+            None => writeln!(out, "{} deadzone of the profile", stick)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_profile(
+    out: &mut dyn Write,
+    settings: &Settings,
+    index: usize,
+    profile: &ProfileInfo,
+) -> Result<(), Error> {
+    writeln!(out, "profile {}: {}", index, profile.name)?;
+    let remapped = |input| settings.remapped(index as u8, input);
+    for binding in &profile.bindings {
+        if remapped(binding.input).is_none() {
+            let input = input_name(binding.input);
+            writeln!(out, "  {:<14} {}", input, control_name(binding.control))?;
+        }
+    }
+    for remap in settings.remaps() {
+        if remap.profile as usize == index {
+            let input = input_name(remap.input);
+            writeln!(
+                out,
+                "  {:<14} {} (remapped)",
+                input,
+                control_name(remap.control)
+            )?;
+        }
+    }
+    if let Some((key, bindings)) = &profile.layer {
+        writeln!(out, "  while {} is held:", input_name(*key))?;
+        for binding in bindings {
+            let input = input_name(binding.input);
+            writeln!(out, "    {:<12} {}", input, control_name(binding.control))?;
+        }
+    }
+    Ok(())
+}
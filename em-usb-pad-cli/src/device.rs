@@ -0,0 +1,120 @@
+//! The configuration requests of a pad, over any [`Transport`].
+
+use em_usb_pad_protocol::config::{
+    decode_profile, decode_settings, ConfigInfo, ConfigRequest, CONFIG_MAX_DATA, CONFIG_REQUEST,
+    PROTOCOL_VERSION,
+};
+use em_usb_pad_protocol::controls::{Binding, InputId};
+use em_usb_pad_protocol::settings::Settings;
+
+use crate::transport::{Error, Setup, Transport};
+
+/// A profile of the pad, as it sends it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub bindings: Vec<Binding>,
+    /// The function key and the bindings of its layer
+    pub layer: Option<(InputId, Vec<Binding>)>,
+}
+
+/// A pad speaking this version of the protocol
+pub struct Pad<T> {
+    transport: T,
+    interface: u8,
+    info: ConfigInfo,
+}
+
+impl<T: Transport> Pad<T> {
+    /// Ask the pad behind `transport` what it is, its control interface
+    /// being `interface`.
+    pub fn new(transport: T, interface: u8) -> Result<Self, Error> {
+        let mut pad = Pad {
+            transport,
+            interface,
+            info: ConfigInfo {
+                protocol: PROTOCOL_VERSION,
+                settings_version: 0,
+                profiles: 0,
+            },
+        };
+        let mut buf = [0; CONFIG_MAX_DATA];
+        let len = pad.request_in(ConfigRequest::Info, &mut buf)?;
+        let info = ConfigInfo::decode(&buf[..len]).ok_or(Error::Malformed)?;
+        if info.protocol != PROTOCOL_VERSION {
+            return Err(Error::Version(info.protocol));
+        }
+        pad.info = info;
+        Ok(pad)
+    }
+
+    pub fn info(&self) -> ConfigInfo {
+        self.info
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// The settings in use, maybe not saved yet
+    pub fn settings(&mut self) -> Result<Settings, Error> {
+        let mut buf = [0; CONFIG_MAX_DATA];
+        let len = self.request_in(ConfigRequest::GetSettings, &mut buf)?;
+        decode_settings(&buf[..len]).ok_or(Error::Malformed)
+    }
+
+    /// Use new settings, the pad saves them a moment later.
+    pub fn set_settings(&mut self, settings: &Settings) -> Result<(), Error> {
+        self.request_out(ConfigRequest::SetSettings(*settings))
+    }
+
+    pub fn select_profile(&mut self, index: u8) -> Result<(), Error> {
+        self.request_out(ConfigRequest::SelectProfile(index))
+    }
+
+    /// Save the settings now
+    pub fn save(&mut self) -> Result<(), Error> {
+        self.request_out(ConfigRequest::Save)
+    }
+
+    pub fn profile(&mut self, index: u8) -> Result<ProfileInfo, Error> {
+        let mut buf = [0; CONFIG_MAX_DATA];
+        let len = self.request_in(ConfigRequest::GetProfile(index), &mut buf)?;
+        let dump = decode_profile(&buf[..len]).ok_or(Error::Malformed)?;
+        Ok(ProfileInfo {
+            name: dump.name.to_owned(),
+            bindings: dump.bindings.iter().collect(),
+            layer: dump
+                .layer
+                .map(|(key, bindings)| (key, bindings.iter().collect())),
+        })
+    }
+
+    /// All the profiles, in order
+    pub fn profiles(&mut self) -> Result<Vec<ProfileInfo>, Error> {
+        (0..self.info.profiles)
+            .map(|index| self.profile(index))
+            .collect()
+    }
+
+    fn setup(&self, request: &ConfigRequest) -> Setup {
+        Setup {
+            request_type: request.request_type(),
+            request: CONFIG_REQUEST,
+            value: request.value(),
+            index: ConfigRequest::index(self.interface),
+        }
+    }
+
+    fn request_in(&mut self, request: ConfigRequest, buf: &mut [u8]) -> Result<usize, Error> {
+        let setup = self.setup(&request);
+        self.transport.control_in(setup, buf)
+    }
+
+    fn request_out(&mut self, request: ConfigRequest) -> Result<(), Error> {
+        let mut data = [0; CONFIG_MAX_DATA];
+        let len = request.encode_data(&mut data);
+        let setup = self.setup(&request);
+        self.transport.control_out(setup, &data[..len])
+    }
+}
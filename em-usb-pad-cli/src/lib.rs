@@ -0,0 +1,14 @@
+//! Configure an em-usb-pad from the host.
+//!
+//! The pad answers vendor control requests on its Xinput control interface,
+//! encoded by `em-usb-pad-protocol` on both sides. [`device::Pad`] makes them
+//! over a [`transport::Transport`], a pad found by [`usb`] or one simulated
+//! by [`sim`], and [`cli`] is the command line on top.
+
+pub mod backup;
+pub mod cli;
+pub mod device;
+pub mod names;
+pub mod sim;
+pub mod transport;
+pub mod usb;
@@ -0,0 +1,47 @@
+use std::io::Write;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+use em_usb_pad_cli::cli::{run, Cli, Command};
+use em_usb_pad_cli::device::Pad;
+use em_usb_pad_cli::transport::Error;
+use em_usb_pad_cli::usb::{self, UsbTransport};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match try_main(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn try_main(cli: &Cli) -> Result<(), Error> {
+    let mut out = std::io::stdout().lock();
+    match &cli.command {
+        Command::List => {
+            for pad in usb::list(cli.vid, cli.pid)? {
+                writeln!(
+                    out,
+                    "bus {:03} address {:03} {:04x}:{:04x} {} serial {}",
+                    pad.bus,
+                    pad.address,
+                    pad.vid,
+                    pad.pid,
+                    pad.product.as_deref().unwrap_or("?"),
+                    pad.serial.as_deref().unwrap_or("?"),
+                )?;
+            }
+        }
+        Command::Pad(command) => {
+            let transport = UsbTransport::open(cli.vid, cli.pid, cli.serial.as_deref())?;
+            let interface = transport.interface();
+            let mut pad = Pad::new(transport, interface)?;
+            run(&mut pad, command, &mut out)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Buttons, controls and D-pad modes as text, on the command line and in
+//! backups.
+//! ```text
+//! buttons:  Matrix(row,col) or Direct(pin)
+//! controls: A B X Y LB RB View Menu Guide LS RS DpadUp DpadDown DpadLeft
+//!           DpadRight LT RT NextProfile, LeftTrigger(value),
+//!           RightTrigger(value), LeftStick(direction), RightStick(direction)
+//!           and Macro(slot), a direction being Up, Down, Left or Right
+//! D-pad:    Dpad LeftStick RightStick
+//! ```
+//! Parsing ignores case and spaces.
+
+use em_usb_pad_protocol::controls::{Control, Direction, DpadMode, InputId};
+
+const PLAIN: [(&str, Control); 18] = [
+    ("A", Control::A),
+    ("B", Control::B),
+    ("X", Control::X),
+    ("Y", Control::Y),
+    ("LB", Control::LB),
+    ("RB", Control::RB),
+    ("View", Control::View),
+    ("Menu", Control::Menu),
+    ("Guide", Control::Guide),
+    ("LS", Control::LS),
+    ("RS", Control::RS),
+    ("DpadUp", Control::DpadUp),
+    ("DpadDown", Control::DpadDown),
+    ("DpadLeft", Control::DpadLeft),
+    ("DpadRight", Control::DpadRight),
+    ("LT", Control::LT),
+    ("RT", Control::RT),
+    ("NextProfile", Control::NextProfile),
+];
+
+const DIRECTIONS: [(&str, Direction); 4] = [
+    ("Up", Direction::Up),
+    ("Down", Direction::Down),
+    ("Left", Direction::Left),
+    ("Right", Direction::Right),
+];
+
+const DPAD_MODES: [(&str, DpadMode); 3] = [
+    ("Dpad", DpadMode::Dpad),
+    ("LeftStick", DpadMode::LeftStick),
+    ("RightStick", DpadMode::RightStick),
+];
+
+pub fn input_name(input: InputId) -> String {
+    match input {
+        InputId::Matrix { row, col } => format!("Matrix({},{})", row, col),
+        InputId::Direct(pin) => format!("Direct({})", pin),
+    }
+}
+
+pub fn parse_input(text: &str) -> Option<InputId> {
+    let (name, arguments) = split(text)?;
+    match (name.as_str(), arguments.as_slice()) {
+        ("matrix", [row, col]) => Some(InputId::Matrix {
+            row: row.parse().ok()?,
+            col: col.parse().ok()?,
+        }),
+        ("direct", [pin]) => Some(InputId::Direct(pin.parse().ok()?)),
+        _ => None,
+    }
+}
+
+pub fn control_name(control: Control) -> String {
+    let direction = |direction| name_of(&DIRECTIONS, direction);
+    match control {
+        Control::LeftTrigger(value) => format!("LeftTrigger({})", value),
+        Control::RightTrigger(value) => format!("RightTrigger({})", value),
+        Control::LeftStick(to) => format!("LeftStick({})", direction(to)),
+        Control::RightStick(to) => format!("RightStick({})", direction(to)),
+        Control::Macro(slot) => format!("Macro({})", slot),
+        plain => name_of(&PLAIN, plain).to_owned(),
+    }
+}
+
+pub fn parse_control(text: &str) -> Option<Control> {
+    let (name, arguments) = split(text)?;
+    let direction = |text: &str| find(&DIRECTIONS, text);
+    let control = match (name.as_str(), arguments.as_slice()) {
+        ("lefttrigger", [value]) => Control::LeftTrigger(value.parse().ok()?),
+        ("righttrigger", [value]) => Control::RightTrigger(value.parse().ok()?),
+        ("leftstick", [to]) => Control::LeftStick(direction(to)?),
+        ("rightstick", [to]) => Control::RightStick(direction(to)?),
+        ("macro", [slot]) => Control::Macro(slot.parse().ok()?),
+        (name, []) => find(&PLAIN, name)?,
+        _ => return None,
+    };
+    Some(control)
+}
+
+pub fn dpad_mode_name(mode: DpadMode) -> &'static str {
+    name_of(&DPAD_MODES, mode)
+}
+
+pub fn parse_dpad_mode(text: &str) -> Option<DpadMode> {
+    find(&DPAD_MODES, &text.replace([' ', '-', '_'], ""))
+}
+
+fn name_of<T: PartialEq>(names: &[(&'static str, T)], value: T) -> &'static str {
+    names
+        .iter()
+        .find(|(_, named)| *named == value)
+        .map_or("?", |(name, _)| name)
+}
+
+fn find<T: Copy>(names: &[(&str, T)], text: &str) -> Option<T> {
+    names
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(text))
+        .map(|(_, value)| *value)
+}
+
+/// `Name(a,b)` into the lowercase name and the arguments
+fn split(text: &str) -> Option<(String, Vec<String>)> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let (name, arguments) = match text.split_once('(') {
+        Some((name, rest)) => {
+            let arguments = rest.strip_suffix(')')?;
+            (name, arguments.split(',').map(str::to_owned).collect())
+        }
+        None => (text.as_str(), Vec::new()),
+    };
+    Some((name.to_ascii_lowercase(), arguments))
+}
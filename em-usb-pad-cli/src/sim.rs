@@ -0,0 +1,110 @@
+//! A pad simulated in memory, answering the requests as the firmware does,
+//! to try the tool without one.
+
+use em_usb_pad_protocol::config::{
+    encode_profile, encode_settings, ConfigInfo, ConfigRequest, CONFIG_MAX_DATA, CONFIG_REQUEST,
+    PROTOCOL_VERSION, REQUEST_TYPE_IN, REQUEST_TYPE_OUT,
+};
+use em_usb_pad_protocol::settings::{Settings, SETTINGS_VERSION};
+
+use crate::device::ProfileInfo;
+use crate::transport::{Error, Setup, Transport};
+
+/// The settings of a pad with these profiles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimPad {
+    pub profiles: Vec<ProfileInfo>,
+    /// In use
+    pub settings: Settings,
+    /// The last saved, `None` before any save
+    pub saved: Option<Settings>,
+    /// Number of the control interface
+    pub interface: u8,
+}
+
+impl SimPad {
+    pub fn new(profiles: Vec<ProfileInfo>) -> Self {
+        SimPad {
+            profiles,
+            settings: Settings::default(),
+            saved: None,
+            interface: 0,
+        }
+    }
+
+    /// What the firmware accepts
+    fn valid(&self, settings: &Settings) -> bool {
+        let profiles = self.profiles.len();
+        (settings.profile as usize) < profiles
+            && settings.turbo_rate != 0
+            && settings
+                .remaps()
+                .all(|remap| (remap.profile as usize) < profiles)
+    }
+
+    fn decode(&self, setup: Setup, is_in: bool, data: &[u8]) -> Result<ConfigRequest, Error> {
+        let request_type = if is_in {
+            REQUEST_TYPE_IN
+        } else {
+            REQUEST_TYPE_OUT
+        };
+        if setup.request_type != request_type
+            || setup.request != CONFIG_REQUEST
+            || setup.index as u8 != self.interface
+        {
+            return Err(Error::Stalled);
+        }
+        ConfigRequest::decode(is_in, setup.value, setup.index, data).map_err(|_| Error::Stalled)
+    }
+}
+
+impl Transport for SimPad {
+    fn control_in(&mut self, setup: Setup, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut data = [0; CONFIG_MAX_DATA];
+        let len = match self.decode(setup, true, &[])? {
+            ConfigRequest::Info => {
+                let info = ConfigInfo {
+                    protocol: PROTOCOL_VERSION,
+                    settings_version: SETTINGS_VERSION,
+                    profiles: self.profiles.len() as u8,
+                };
+                data[..ConfigInfo::SIZE].copy_from_slice(&info.encode());
+                ConfigInfo::SIZE
+            }
+            ConfigRequest::GetSettings => encode_settings(&self.settings, &mut data),
+            ConfigRequest::GetProfile(index) => {
+                let profile = self.profiles.get(index as usize).ok_or(Error::Stalled)?;
+                let layer = profile
+                    .layer
+                    .as_ref()
+                    .map(|(key, bindings)| (*key, &bindings[..]));
+                encode_profile(&profile.name, &profile.bindings, layer, &mut data)
+                    .ok_or(Error::Stalled)?
+            }
+            _ => return Err(Error::Stalled),
+        };
+        let len = len.min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn control_out(&mut self, setup: Setup, data: &[u8]) -> Result<(), Error> {
+        let settings = match self.decode(setup, false, data)? {
+            ConfigRequest::SetSettings(settings) => settings,
+            ConfigRequest::SelectProfile(profile) => Settings {
+                profile,
+                ..self.settings
+            },
+            ConfigRequest::Save => {
+                self.saved = Some(self.settings);
+                return Ok(());
+            }
+            _ => return Err(Error::Stalled),
+        };
+        if !self.valid(&settings) {
+            return Err(Error::Stalled);
+        }
+        self.settings = settings;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! How the configuration requests reach a pad.
+//!
+//! A [`Transport`] carries vendor control transfers, [`crate::usb`] over
+//! libusb and [`crate::sim`] to a pad simulated in memory.
+
+use std::fmt;
+
+/// The setup stage of a control transfer, its length is the buffer's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+}
+
+/// Control transfers to a pad
+pub trait Transport {
+    /// Read into `buf`, returns how many bytes the pad answered.
+    fn control_in(&mut self, setup: Setup, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Send `data`, which may be empty.
+    fn control_out(&mut self, setup: Setup, data: &[u8]) -> Result<(), Error>;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The pad refused the request
+    Stalled,
+    /// Anything else libusb reports
+    Usb(rusb::Error),
+    /// No pad matches
+    NotFound,
+    /// Several pads match, pick one by its serial number
+    Ambiguous(usize),
+    /// The pad speaks another version of the protocol
+    Version(u8),
+    /// An answer of the pad that doesn't decode
+    Malformed,
+    /// Text that doesn't describe settings, a button or a control
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Stalled => write!(f, "the pad refused the request"),
+            Error::Usb(e) => write!(f, "USB error: {}", e),
+            Error::NotFound => write!(f, "no pad found"),
+            Error::Ambiguous(count) => {
+                write!(f, "{} pads found, pick one with --serial", count)
+            }
+            Error::Version(version) => write!(
+                f,
+                "the pad speaks version {} of the protocol, this tool version {}",
+                version,
+                em_usb_pad_protocol::config::PROTOCOL_VERSION
+            ),
+            Error::Malformed => write!(f, "the pad sent a malformed answer"),
+            Error::Invalid(message) => write!(f, "{}", message),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusb::Error> for Error {
+    fn from(e: rusb::Error) -> Self {
+        match e {
+            rusb::Error::Pipe => Error::Stalled,
+            e => Error::Usb(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
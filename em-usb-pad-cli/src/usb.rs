@@ -0,0 +1,145 @@
+//! Pads on the USB bus, through libusb.
+//!
+//! The pads are told apart by their VID and PID, an Xbox 360 controller's by
+//! default, then by their serial number. The requests go to their Xinput
+//! control interface, claimed from the driver of the system meanwhile.
+
+use std::time::Duration;
+
+use rusb::{Device, DeviceHandle, GlobalContext};
+
+use crate::transport::{Error, Setup, Transport};
+
+/// VID of the pads unless told otherwise
+pub const DEFAULT_VID: u16 = 0x045e;
+
+/// PID of the pads unless told otherwise
+pub const DEFAULT_PID: u16 = 0x028e;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Class, subclass and protocol of the Xinput control interface
+const CONTROL_INTERFACE: (u8, u8, u8) = (0xff, 0x5d, 0x01);
+
+/// A pad found on the bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PadInfo {
+    pub bus: u8,
+    pub address: u8,
+    pub vid: u16,
+    pub pid: u16,
+    /// `None` when it can't be read, without the rights to open the pad
+    pub product: Option<String>,
+    pub serial: Option<String>,
+}
+
+/// The pads with this VID and PID
+pub fn list(vid: u16, pid: u16) -> Result<Vec<PadInfo>, Error> {
+    Ok(find(vid, pid)?.into_iter().map(|(_, info)| info).collect())
+}
+
+fn find(vid: u16, pid: u16) -> Result<Vec<(Device<GlobalContext>, PadInfo)>, Error> {
+    let mut pads = Vec::new();
+    for device in rusb::devices()?.iter() {
+        let descriptor = device.device_descriptor()?;
+        if descriptor.vendor_id() != vid || descriptor.product_id() != pid {
+            continue;
+        }
+        let (product, serial) = match device.open() {
+            Ok(handle) => (
+                handle.read_product_string_ascii(&descriptor).ok(),
+                handle.read_serial_number_string_ascii(&descriptor).ok(),
+            ),
+            Err(_) => (None, None),
+        };
+        let info = PadInfo {
+            bus: device.bus_number(),
+            address: device.address(),
+            vid,
+            pid,
+            product,
+            serial,
+        };
+        pads.push((device, info));
+    }
+    Ok(pads)
+}
+
+/// The control interface of a pad, claimed
+pub struct UsbTransport {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+}
+
+impl UsbTransport {
+    /// Open the only pad with this VID, PID and `serial` if given.
+    pub fn open(vid: u16, pid: u16, serial: Option<&str>) -> Result<Self, Error> {
+        let mut pads = find(vid, pid)?;
+        if let Some(serial) = serial {
+            pads.retain(|(_, info)| info.serial.as_deref() == Some(serial));
+        }
+        let device = match pads.len() {
+            0 => return Err(Error::NotFound),
+            1 => pads.remove(0).0,
+            count => return Err(Error::Ambiguous(count)),
+        };
+
+        let config = device.active_config_descriptor()?;
+        let interface = config
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .find(|descriptor| {
+                let class = (
+                    descriptor.class_code(),
+                    descriptor.sub_class_code(),
+                    descriptor.protocol_code(),
+                );
+                class == CONTROL_INTERFACE
+            })
+            .ok_or(Error::NotFound)?
+            .interface_number();
+
+        let handle = device.open()?;
+        // not supported on every system, the claim tells
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle.claim_interface(interface)?;
+        Ok(UsbTransport { handle, interface })
+    }
+
+    /// Number of the control interface
+    pub fn interface(&self) -> u8 {
+        self.interface
+    }
+}
+
+impl Drop for UsbTransport {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
+
+impl Transport for UsbTransport {
+    fn control_in(&mut self, setup: Setup, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.handle.read_control(
+            setup.request_type,
+            setup.request,
+            setup.value,
+            setup.index,
+            buf,
+            TIMEOUT,
+        )?;
+        Ok(len)
+    }
+
+    fn control_out(&mut self, setup: Setup, data: &[u8]) -> Result<(), Error> {
+        self.handle.write_control(
+            setup.request_type,
+            setup.request,
+            setup.value,
+            setup.index,
+            data,
+            TIMEOUT,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! The command line against a simulated pad.
+
+use em_usb_pad_cli::backup::Backup;
+use em_usb_pad_cli::cli::{run, Cli, Command};
+use em_usb_pad_cli::device::{Pad, ProfileInfo};
+use em_usb_pad_cli::names::{control_name, input_name, parse_control, parse_input};
+use em_usb_pad_cli::sim::SimPad;
+use em_usb_pad_cli::transport::Error;
+use em_usb_pad_protocol::controls::{bind, Control, Direction, DpadMode, InputId};
+use em_usb_pad_protocol::settings::{Remap, Settings, StickDeadzone};
+
+use clap::Parser;
+
+fn key(row: u8, col: u8) -> InputId {
+    InputId::Matrix { row, col }
+}
+
+fn profiles() -> Vec<ProfileInfo> {
+    vec![
+        ProfileInfo {
+            name: "face".to_owned(),
+            bindings: vec![
+                bind(key(0, 0), Control::A),
+                bind(key(0, 1), Control::LeftTrigger(100)),
+            ],
+            layer: None,
+        },
+        ProfileInfo {
+            name: "stick".to_owned(),
+            bindings: vec![bind(key(0, 0), Control::LeftStick(Direction::Up))],
+            layer: Some((InputId::Direct(2), vec![bind(key(0, 0), Control::Macro(1))])),
+        },
+    ]
+}
+
+fn pad() -> Pad<SimPad> {
+    Pad::new(SimPad::new(profiles()), 0).unwrap()
+}
+
+/// Run a command line, returns what it printed.
+fn cli(pad: &mut Pad<SimPad>, args: &[&str]) -> Result<String, Error> {
+    let cli = Cli::try_parse_from(["em-usb-pad"].iter().chain(args)).unwrap();
+    let Command::Pad(command) = cli.command else {
+        panic!("not a pad command");
+    };
+    let mut out = Vec::new();
+    run(pad, &command, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn dump_the_pad() {
+    let mut pad = pad();
+    assert_eq!(
+        cli(&mut pad, &["info"]).unwrap(),
+        "protocol version 1\nsettings version 3\n2 profiles\n"
+    );
+    assert_eq!(pad.profiles().unwrap(), profiles());
+
+    cli(&mut pad, &["remap", "stick", "Matrix(0,0)", "B"]).unwrap();
+    let dump = cli(&mut pad, &["dump"]).unwrap();
+    assert_eq!(
+        dump,
+        "profile 0: face\n\
+         D-pad mode Dpad\n\
+         turbo 10 per second\n\
+         left deadzone of the profile\n\
+         right deadzone of the profile\n\
+         \n\
+         profile 0: face\n  \
+         Matrix(0,0)    A\n  \
+         Matrix(0,1)    LeftTrigger(100)\n\
+         \n\
+         profile 1: stick\n  \
+         Matrix(0,0)    B (remapped)\n  \
+         while Direct(2) is held:\n    \
+         Matrix(0,0)  Macro(1)\n"
+    );
+    let dump = cli(&mut pad, &["dump", "--profile", "0"]).unwrap();
+    assert!(dump.starts_with("\nprofile 0: face\n"));
+}
+
+#[test]
+fn edit_the_settings() {
+    let mut pad = pad();
+    cli(&mut pad, &["profile", "stick"]).unwrap();
+    cli(&mut pad, &["dpad", "right-stick"]).unwrap();
+    cli(&mut pad, &["turbo", "25"]).unwrap();
+    cli(&mut pad, &["deadzone", "left", "1500", "30000"]).unwrap();
+    cli(&mut pad, &["remap", "0", "Direct(4)", "RightStick(Left)"]).unwrap();
+    cli(&mut pad, &["remap", "face", "matrix(0, 1)", "rt"]).unwrap();
+
+    let mut expected = Settings {
+        profile: 1,
+        dpad_mode: DpadMode::RightStick,
+        turbo_rate: 25,
+        deadzones: [
+            Some(StickDeadzone {
+                inner: 1500,
+                outer: 30000,
+            }),
+            None,
+        ],
+        ..Default::default()
+    };
+    expected.set_remap(Remap {
+        profile: 0,
+        input: InputId::Direct(4),
+        control: Control::RightStick(Direction::Left),
+    });
+    expected.set_remap(Remap {
+        profile: 0,
+        input: key(0, 1),
+        control: Control::RT,
+    });
+    assert_eq!(pad.transport().settings, expected);
+    assert_eq!(pad.transport().saved, None);
+
+    cli(&mut pad, &["unmap", "0", "Direct(4)"]).unwrap();
+    cli(&mut pad, &["deadzone", "left"]).unwrap();
+    cli(&mut pad, &["save"]).unwrap();
+    expected.clear_remap(0, InputId::Direct(4));
+    expected.deadzones[0] = None;
+    assert_eq!(pad.transport().saved, Some(expected));
+}
+
+#[test]
+fn bad_edits_change_nothing() {
+    let mut pad = pad();
+    let cases: [&[&str]; 7] = [
+        &["profile", "2"],
+        &["profile", "missing"],
+        &["dpad", "sideways"],
+        &["deadzone", "right", "3000", "2000"],
+        &["remap", "0", "Matrix(0)", "A"],
+        &["remap", "0", "Direct(1)", "LeftStick(In)"],
+        &["unmap", "0", "Direct(1)"],
+    ];
+    for args in cases {
+        assert!(
+            matches!(cli(&mut pad, args), Err(Error::Invalid(_))),
+            "{:?}",
+            args
+        );
+    }
+    assert!(Cli::try_parse_from(["em-usb-pad", "turbo", "0"]).is_err());
+    assert_eq!(pad.transport().settings, Settings::default());
+
+    // refused by the pad
+    let mut settings = Settings::default();
+    settings.set_remap(Remap {
+        profile: 5,
+        input: key(0, 0),
+        control: Control::A,
+    });
+    assert!(matches!(pad.set_settings(&settings), Err(Error::Stalled)));
+    assert_eq!(pad.transport().settings, Settings::default());
+}
+
+#[test]
+fn back_up_and_restore() {
+    let file = std::env::temp_dir().join(format!("em-usb-pad-{}.toml", std::process::id()));
+    let file_arg = file.to_str().unwrap();
+
+    let mut pad = pad();
+    cli(&mut pad, &["dpad", "left-stick"]).unwrap();
+    cli(&mut pad, &["deadzone", "right", "2000", "31000"]).unwrap();
+    cli(&mut pad, &["remap", "1", "Matrix(1,2)", "RightStick(Up)"]).unwrap();
+    cli(&mut pad, &["backup", file_arg]).unwrap();
+    let backed_up = pad.transport().settings;
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "profile = 0\n\
+         dpad_mode = \"LeftStick\"\n\
+         turbo_rate = 10\n\
+         \n\
+         [right_deadzone]\n\
+         inner = 2000\n\
+         outer = 31000\n\
+         \n\
+         [[remap]]\n\
+         profile = 1\n\
+         input = \"Matrix(1,2)\"\n\
+         control = \"RightStick(Up)\"\n"
+    );
+
+    let mut other = self::pad();
+    cli(&mut other, &["restore", file_arg]).unwrap();
+    assert_eq!(other.transport().settings, backed_up);
+    assert_eq!(other.transport().saved, Some(backed_up));
+
+    std::fs::write(&file, "profile = 0\ndpad_mode = \"Dpad\"\n").unwrap();
+    assert!(matches!(
+        cli(&mut other, &["restore", file_arg]),
+        Err(Error::Invalid(_))
+    ));
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn backups_check_their_values() {
+    let backup = Backup::from_settings(&Settings::default());
+    assert_eq!(backup.to_settings().unwrap(), Settings::default());
+    for (dpad_mode, input, control) in [
+        ("Diagonal", "Direct(1)", "A"),
+        ("Dpad", "Keypad(1)", "A"),
+        ("Dpad", "Direct(1)", "LeftTrigger(256)"),
+    ] {
+        let text = format!(
+            "profile = 0\ndpad_mode = \"{}\"\nturbo_rate = 10\n\n\
+             [[remap]]\nprofile = 0\ninput = \"{}\"\ncontrol = \"{}\"\n",
+            dpad_mode, input, control
+        );
+        let backup = Backup::from_toml(&text).unwrap();
+        assert!(matches!(backup.to_settings(), Err(Error::Invalid(_))));
+    }
+    assert!(Backup::from_toml("profile = 0\nturbo = 3\n").is_err());
+}
+
+#[test]
+fn names_read_back() {
+    let mut controls = vec![
+        Control::LeftTrigger(7),
+        Control::RightTrigger(255),
+        Control::Macro(3),
+        Control::NextProfile,
+    ];
+    for direction in [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ] {
+        controls.push(Control::LeftStick(direction));
+        controls.push(Control::RightStick(direction));
+    }
+    for code in 0..17 {
+        controls.push(Control::decode(&[code, 0]).unwrap());
+    }
+    for control in controls {
+        assert_eq!(parse_control(&control_name(control)), Some(control));
+    }
+    for input in [key(3, 7), InputId::Direct(12)] {
+        assert_eq!(parse_input(&input_name(input)), Some(input));
+    }
+    assert_eq!(parse_control("dpad up"), Some(Control::DpadUp));
+    assert_eq!(parse_control("Macro"), None);
+    assert_eq!(parse_input("Direct(1,2)"), None);
+}
+
+#[test]
+fn another_interface_or_protocol() {
+    let mut sim = SimPad::new(profiles());
+    sim.interface = 1;
+    assert!(matches!(
+        Pad::new(sim.clone(), 0).map(|_| ()),
+        Err(Error::Stalled)
+    ));
+    assert!(Pad::new(sim, 1).is_ok());
+}
@@ -0,0 +1,350 @@
+//! Analog sticks and triggers.
+//!
+//! An [`AnalogSource`], e.g. the ADC, gives the raw counts of each axis. An
+//! [`AxisCalibration`] turns them into the signed 16-bit range of Xinput, the
+//! calibrated centre reading 0, the minimum -32768 and the maximum 32767.
+//! [`Sticks`] samples both sticks and puts them in the report, through the
+//! deadzones and curves of [`crate::shaping`].
+//!
+//! Triggers work the same way with a [`TriggerCalibration`] and [`Triggers`],
+//! from 0 released to 255 fully pressed.
+
+use crate::board::AnalogInput;
+use crate::shaping::{SticksShaping, TriggersShaping};
+use crate::xinput::XinputControlReport;
+
+/// Where the raw axis values come from
+pub trait AnalogSource {
+    /// Sample `input`, `None` if the board doesn't have it.
+    fn read(&mut self, input: AnalogInput) -> Option<u16>;
+}
+
+/// A board without analog inputs
+pub struct NoAnalog;
+
+impl AnalogSource for NoAnalog {
+    fn read(&mut self, _input: AnalogInput) -> Option<u16> {
+        None
+    }
+}
+
+/// Raw counts of an axis at rest and at both ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisCalibration {
+    pub min: u16,
+    pub center: u16,
+    pub max: u16,
+    /// The raw value goes down when the stick goes right or up
+    pub invert: bool,
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        AxisCalibration::FULL_RANGE
+    }
+}
+
+impl AxisCalibration {
+    /// The full range of a 12-bit ADC
+    pub const FULL_RANGE: AxisCalibration = AxisCalibration::new(0, 2048, 4095);
+
+    pub const fn new(min: u16, center: u16, max: u16) -> Self {
+        AxisCalibration {
+            min,
+            center,
+            max,
+            invert: false,
+        }
+    }
+
+    pub const fn inverted(self) -> Self {
+        AxisCalibration {
+            invert: !self.invert,
+            ..self
+        }
+    }
+
+    /// Scale `raw` to the Xinput range, each side of the centre on its own.
+    ///
+    /// Values past the calibrated ends are clamped.
+    pub fn to_xinput(&self, raw: u16) -> i16 {
+        let (raw, min, center, max) = (
+            raw as i32,
+            self.min as i32,
+            self.center as i32,
+            self.max as i32,
+        );
+        let value = if raw < center {
+            if center <= min {
+                0
+            } else {
+                -((center - raw.max(min)) * 32768 / (center - min))
+            }
+        } else if max <= center {
+            0
+        } else {
+            (raw.min(max) - center) * 32767 / (max - center)
+        };
+        let value = if self.invert { -value } else { value };
+        value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+/// Finds the calibration of an axis
+///
+/// Feed it samples with the stick at rest, then while moving the stick all
+/// around.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AxisCalibrator {
+    rest_sum: u32,
+    rest_count: u32,
+    min: Option<u16>,
+    max: Option<u16>,
+}
+
+impl AxisCalibrator {
+    pub const fn new() -> Self {
+        AxisCalibrator {
+            rest_sum: 0,
+            rest_count: 0,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// A sample with the stick released, they are averaged into the centre
+    pub fn rest(&mut self, raw: u16) {
+        self.rest_sum += raw as u32;
+        self.rest_count += 1;
+    }
+
+    /// A sample while the stick moves
+    pub fn moved(&mut self, raw: u16) {
+        self.min = Some(self.min.map_or(raw, |min| min.min(raw)));
+        self.max = Some(self.max.map_or(raw, |max| max.max(raw)));
+    }
+
+    /// The calibration found, `None` if the stick wasn't moved both ways.
+    ///
+    /// `margin` counts are taken off both ends so that full deflection is
+    /// reached reliably.
+    pub fn finish(&self, invert: bool, margin: u16) -> Option<AxisCalibration> {
+        if self.rest_count == 0 {
+            return None;
+        }
+        let center = (self.rest_sum / self.rest_count) as u16;
+        let min = self.min?.saturating_add(margin);
+        let max = self.max?.saturating_sub(margin);
+        if min >= center || max <= center {
+            return None;
+        }
+        Some(AxisCalibration {
+            min,
+            center,
+            max,
+            invert,
+        })
+    }
+}
+
+/// Calibration of both axes of a stick
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StickCalibration {
+    pub x: AxisCalibration,
+    pub y: AxisCalibration,
+}
+
+impl StickCalibration {
+    pub const FULL_RANGE: StickCalibration = StickCalibration {
+        x: AxisCalibration::FULL_RANGE,
+        y: AxisCalibration::FULL_RANGE,
+    };
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SticksCalibration {
+    pub left: StickCalibration,
+    pub right: StickCalibration,
+}
+
+impl SticksCalibration {
+    pub const FULL_RANGE: SticksCalibration = SticksCalibration {
+        left: StickCalibration::FULL_RANGE,
+        right: StickCalibration::FULL_RANGE,
+    };
+}
+
+/// The position of both sticks, as sent to the host
+pub struct Sticks {
+    pub calibration: SticksCalibration,
+    left: (i16, i16),
+    right: (i16, i16),
+}
+
+impl Sticks {
+    /// Both sticks are centred until sampled.
+    pub fn new(calibration: SticksCalibration) -> Self {
+        Sticks {
+            calibration,
+            left: (0, 0),
+            right: (0, 0),
+        }
+    }
+
+    /// (x, y) of the left stick, y goes up
+    pub fn left(&self) -> (i16, i16) {
+        self.left
+    }
+
+    /// (x, y) of the right stick, y goes up
+    pub fn right(&self) -> (i16, i16) {
+        self.right
+    }
+
+    /// Read all axes, the missing ones stay centred.
+    pub fn sample<S: AnalogSource>(&mut self, source: &mut S) {
+        let mut axis = |input, calibration: &AxisCalibration| {
+            source
+                .read(input)
+                .map_or(0, |raw| calibration.to_xinput(raw))
+        };
+        let calibration = self.calibration;
+        self.left = (
+            axis(AnalogInput::LeftStickX, &calibration.left.x),
+            axis(AnalogInput::LeftStickY, &calibration.left.y),
+        );
+        self.right = (
+            axis(AnalogInput::RightStickX, &calibration.right.x),
+            axis(AnalogInput::RightStickY, &calibration.right.y),
+        );
+    }
+
+    /// Put the sticks in `report`, shaped by `shaping`.
+    ///
+    /// A stick already moved by buttons mapped to its directions is left
+    /// alone, the buttons win.
+    pub fn apply(&self, report: &mut XinputControlReport, shaping: &SticksShaping) {
+        if (report.js_left_x, report.js_left_y) == (0, 0) {
+            (report.js_left_x, report.js_left_y) = shaping.left.apply(self.left);
+        }
+        if (report.js_right_x, report.js_right_y) == (0, 0) {
+            (report.js_right_x, report.js_right_y) = shaping.right.apply(self.right);
+        }
+    }
+}
+
+/// Raw counts of a trigger released and fully pressed
+///
+/// `pressed` may be below `released`, depending on the wiring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TriggerCalibration {
+    pub released: u16,
+    pub pressed: u16,
+}
+
+impl Default for TriggerCalibration {
+    fn default() -> Self {
+        TriggerCalibration::FULL_RANGE
+    }
+}
+
+impl TriggerCalibration {
+    /// The full range of a 12-bit ADC
+    pub const FULL_RANGE: TriggerCalibration = TriggerCalibration {
+        released: 0,
+        pressed: 4095,
+    };
+
+    /// Scale `raw` to 0 released, 255 fully pressed, clamped.
+    pub fn to_xinput(&self, raw: u16) -> u8 {
+        let (raw, released, pressed) = (raw as i32, self.released as i32, self.pressed as i32);
+        if released == pressed {
+            return 0;
+        }
+        ((raw - released) * 255 / (pressed - released)).clamp(0, 255) as u8
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TriggersCalibration {
+    pub left: TriggerCalibration,
+    pub right: TriggerCalibration,
+}
+
+impl TriggersCalibration {
+    pub const FULL_RANGE: TriggersCalibration = TriggersCalibration {
+        left: TriggerCalibration::FULL_RANGE,
+        right: TriggerCalibration::FULL_RANGE,
+    };
+}
+
+/// Calibration of all analog inputs of a board
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AnalogCalibration {
+    pub sticks: SticksCalibration,
+    pub triggers: TriggersCalibration,
+}
+
+impl AnalogCalibration {
+    pub const FULL_RANGE: AnalogCalibration = AnalogCalibration {
+        sticks: SticksCalibration::FULL_RANGE,
+        triggers: TriggersCalibration::FULL_RANGE,
+    };
+}
+
+/// The travel of both analog triggers
+pub struct Triggers {
+    pub calibration: TriggersCalibration,
+    left: Option<u8>,
+    right: Option<u8>,
+}
+
+impl Triggers {
+    /// Both triggers are missing until sampled.
+    pub fn new(calibration: TriggersCalibration) -> Self {
+        Triggers {
+            calibration,
+            left: None,
+            right: None,
+        }
+    }
+
+    /// The left trigger, `None` if the board doesn't have it
+    pub fn left(&self) -> Option<u8> {
+        self.left
+    }
+
+    /// The right trigger, `None` if the board doesn't have it
+    pub fn right(&self) -> Option<u8> {
+        self.right
+    }
+
+    /// Read both triggers.
+    pub fn sample<S: AnalogSource>(&mut self, source: &mut S) {
+        let calibration = self.calibration;
+        self.left = source
+            .read(AnalogInput::LeftTrigger)
+            .map(|raw| calibration.left.to_xinput(raw));
+        self.right = source
+            .read(AnalogInput::RightTrigger)
+            .map(|raw| calibration.right.to_xinput(raw));
+    }
+
+    /// Put the triggers in `report`, shaped by `shaping`.
+    ///
+    /// Buttons mapped to a trigger may press it further.
+    pub fn apply(&self, report: &mut XinputControlReport, shaping: &TriggersShaping) {
+        if let Some(left) = self.left {
+            report.trigger_left = report.trigger_left.max(shaping.left.apply(left));
+        }
+        if let Some(right) = self.right {
+            report.trigger_right = report.trigger_right.max(shaping.right.apply(right));
+        }
+    }
+}
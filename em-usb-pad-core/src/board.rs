@@ -0,0 +1,353 @@
+//! Board description and button scanning.
+//!
+//! A board declares what it has, a key matrix, buttons wired directly to a
+//! pin, analog inputs and outputs, in a [`BoardInfo`]. The firmware picks the
+//! board at build time and derives the scanning and the button state storage
+//! from it, see [`Scanner`].
+//!
+//! All buttons are read active low, the matrix rows and the direct buttons
+//! have pull-ups and a pressed key pulls them to ground. Wrap a pin in
+//! [`ActiveHigh`] for a button wired to VCC.
+//!
+//! The rows are read through a [`RowPort`], all at once when they share a GPIO
+//! port, and [`ScanStats`] keep track of how long scanning takes.
+
+use core::convert::Infallible;
+
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+/// Every button of a board fits in an [`InputStates`].
+pub const MAX_BUTTONS: usize = 64;
+
+pub use em_usb_pad_protocol::controls::InputId;
+
+/// Analog inputs a board may have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AnalogInput {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// How the buttons are wired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Layout {
+    pub rows: u8,
+    pub cols: u8,
+    /// Buttons on their own pin
+    pub direct: u8,
+}
+
+impl Layout {
+    pub const fn new(rows: u8, cols: u8, direct: u8) -> Self {
+        assert!(
+            rows as usize * cols as usize + direct as usize <= MAX_BUTTONS,
+            "too many buttons"
+        );
+        Layout { rows, cols, direct }
+    }
+
+    pub const fn buttons(&self) -> usize {
+        self.rows as usize * self.cols as usize + self.direct as usize
+    }
+
+    /// Where the state of `input` is stored, the matrix goes first, row by row.
+    ///
+    /// `None` if the board has no such button.
+    pub const fn index(&self, input: InputId) -> Option<usize> {
+        match input {
+            InputId::Matrix { row, col } if row < self.rows && col < self.cols => {
+                Some(row as usize * self.cols as usize + col as usize)
+            }
+            InputId::Direct(n) if n < self.direct => {
+                Some(self.rows as usize * self.cols as usize + n as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// The button stored at `index`
+    pub const fn input(&self, index: usize) -> Option<InputId> {
+        let matrix = self.rows as usize * self.cols as usize;
+        if index < matrix {
+            Some(InputId::Matrix {
+                row: (index / self.cols as usize) as u8,
+                col: (index % self.cols as usize) as u8,
+            })
+        } else if index < self.buttons() {
+            Some(InputId::Direct((index - matrix) as u8))
+        } else {
+            None
+        }
+    }
+}
+
+/// What a board has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardInfo {
+    pub name: &'static str,
+    pub layout: Layout,
+    pub analog: &'static [AnalogInput],
+    /// Four player LEDs around the guide button
+    pub player_leds: bool,
+    /// Two rumble motors
+    pub rumble: bool,
+    /// A diode on every key of the matrix, it can't ghost, see
+    /// [`crate::ghosting`]
+    pub diodes: bool,
+}
+
+/// Which buttons are pressed, indexed by [`Layout::index`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InputStates(pub u64);
+
+impl InputStates {
+    pub const fn is_pressed(&self, index: usize) -> bool {
+        self.0 & (1 << index) != 0
+    }
+
+    pub fn set(&mut self, index: usize, pressed: bool) {
+        if pressed {
+            self.0 |= 1 << index;
+        } else {
+            self.0 &= !(1 << index);
+        }
+    }
+
+    /// The buttons that changed from `previous` to `self`
+    pub fn changes(&self, previous: InputStates, layout: Layout) -> InputChanges {
+        InputChanges {
+            layout,
+            current: *self,
+            changed: self.0 ^ previous.0,
+        }
+    }
+}
+
+/// Iterates over the buttons that changed, with their new state
+pub struct InputChanges {
+    layout: Layout,
+    current: InputStates,
+    changed: u64,
+}
+
+impl Iterator for InputChanges {
+    type Item = (InputId, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.changed != 0 {
+            let index = self.changed.trailing_zeros() as usize;
+            self.changed &= self.changed - 1;
+            if let Some(input) = self.layout.input(index) {
+                return Some((input, self.current.is_pressed(index)));
+            }
+        }
+        None
+    }
+}
+
+/// A button wired to VCC, read as if it was wired to ground
+pub struct ActiveHigh<P>(pub P);
+
+impl<P: InputPin> InputPin for ActiveHigh<P> {
+    type Error = P::Error;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.0.is_high()
+    }
+}
+
+/// The rows of a key matrix
+pub trait RowPort {
+    /// Bit n is set when row n reads low
+    fn read_rows(&mut self) -> u32;
+}
+
+/// One pin per row, read one after the other
+impl<P: InputPin<Error = Infallible>, const N: usize> RowPort for [P; N] {
+    fn read_rows(&mut self) -> u32 {
+        let mut rows = 0;
+        for (n, pin) in self.iter().enumerate() {
+            if pin.is_low().unwrap() {
+                rows |= 1 << n;
+            }
+        }
+        rows
+    }
+}
+
+/// Reads every button of a board
+///
+/// The matrix columns are driven low one at a time while the rows are read,
+/// open drain outputs avoid shorting two columns when several keys are
+/// pressed.
+pub struct Scanner<R, C, D, const ROWS: usize, const COLS: usize, const DIRECT: usize> {
+    rows: R,
+    cols: [C; COLS],
+    direct: [D; DIRECT],
+    states: InputStates,
+}
+
+impl<R, C, D, const ROWS: usize, const COLS: usize, const DIRECT: usize>
+    Scanner<R, C, D, ROWS, COLS, DIRECT>
+where
+    R: RowPort,
+    C: OutputPin<Error = Infallible>,
+    D: InputPin<Error = Infallible>,
+{
+    pub const LAYOUT: Layout = Layout::new(ROWS as u8, COLS as u8, DIRECT as u8);
+
+    /// All columns are released at start.
+    pub fn new(rows: R, mut cols: [C; COLS], direct: [D; DIRECT]) -> Self {
+        for col in cols.iter_mut() {
+            col.set_high().unwrap();
+        }
+        Scanner {
+            rows,
+            cols,
+            direct,
+            states: InputStates::default(),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        Self::LAYOUT
+    }
+
+    /// The states found by the last scan
+    pub fn states(&self) -> InputStates {
+        self.states
+    }
+
+    /// The rows, e.g. to wait for an edge on them after [`Scanner::listen`]
+    pub fn rows_mut(&mut self) -> &mut R {
+        &mut self.rows
+    }
+
+    /// The rows and the direct buttons, e.g. to wait for an edge on any of
+    /// them after [`Scanner::listen`]
+    pub fn inputs_mut(&mut self) -> (&mut R, &mut [D; DIRECT]) {
+        (&mut self.rows, &mut self.direct)
+    }
+
+    /// Drive all columns low, any key pressed then pulls its row low.
+    ///
+    /// The next scan releases them.
+    pub fn listen(&mut self) {
+        for col in self.cols.iter_mut() {
+            col.set_low().unwrap();
+        }
+    }
+
+    /// Read all buttons, then return what changed since the last scan.
+    pub fn scan(&mut self) -> InputChanges {
+        let previous = self.states;
+        let layout = Self::LAYOUT;
+        let mut states = InputStates::default();
+        for col in self.cols.iter_mut() {
+            col.set_high().unwrap();
+        }
+        for (col_index, col) in self.cols.iter_mut().enumerate() {
+            col.set_low().unwrap();
+            let rows = self.rows.read_rows();
+            for row_index in 0..ROWS {
+                let input = InputId::Matrix {
+                    row: row_index as u8,
+                    col: col_index as u8,
+                };
+                if let Some(index) = layout.index(input) {
+                    states.set(index, rows & 1 << row_index != 0);
+                }
+            }
+            col.set_high().unwrap();
+        }
+        for (n, pin) in self.direct.iter().enumerate() {
+            if let Some(index) = layout.index(InputId::Direct(n as u8)) {
+                states.set(index, pin.is_low().unwrap());
+            }
+        }
+        self.states = states;
+        states.changes(previous, layout)
+    }
+}
+
+/// How long the scans take and how regular they are. A scan is shorter than
+/// a tick of the timer, so its length is counted in CPU cycles, the timer only
+/// gives the interval between scans.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanStats {
+    scans: u32,
+    total_cycles: u64,
+    longest_cycles: u32,
+    longest_interval: Duration,
+    last_start: Option<Instant>,
+}
+
+impl ScanStats {
+    pub const fn new() -> Self {
+        ScanStats {
+            scans: 0,
+            total_cycles: 0,
+            longest_cycles: 0,
+            longest_interval: Duration::from_ticks(0),
+            last_start: None,
+        }
+    }
+
+    /// Count a scan that started at `start` and took `cycles` CPU cycles.
+    pub fn record(&mut self, start: Instant, cycles: u32) {
+        self.scans += 1;
+        self.total_cycles += u64::from(cycles);
+        self.longest_cycles = self.longest_cycles.max(cycles);
+        if let Some(last) = self.last_start {
+            let interval = start.saturating_duration_since(last);
+            self.longest_interval = self.longest_interval.max(interval);
+        }
+        self.last_start = Some(start);
+    }
+
+    /// Scanning stopped for a while, e.g. sleeping until a press, the pause
+    /// doesn't count as an interval.
+    pub fn resume(&mut self) {
+        self.last_start = None;
+    }
+
+    /// Start over, e.g. after logging them
+    pub fn reset(&mut self) {
+        *self = ScanStats::new();
+    }
+
+    pub fn scans(&self) -> u32 {
+        self.scans
+    }
+
+    /// Mean length of a scan, in CPU cycles
+    pub fn mean_cycles(&self) -> u32 {
+        match self.scans {
+            0 => 0,
+            scans => (self.total_cycles / u64::from(scans)) as u32,
+        }
+    }
+
+    /// Length of the longest scan, in CPU cycles
+    pub fn longest_cycles(&self) -> u32 {
+        self.longest_cycles
+    }
+
+    /// Longest time from the start of a scan to the start of the next one
+    pub fn longest_interval(&self) -> Duration {
+        self.longest_interval
+    }
+}
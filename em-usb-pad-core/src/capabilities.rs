@@ -0,0 +1,135 @@
+//! The vendor requests a genuine controller answers on its control
+//! interface, besides the reports.
+//!
+//! The Windows driver, and tools like x360ce, ask what the pad has before
+//! using it, with these setup packets:
+//! ```text
+//! c1 01 0100 0000 0014   input capabilities, to the control interface
+//! c1 01 0000 0000 0008   vibration capabilities, to the control interface
+//! c0 01 0000 0000 0004   serial, to the device
+//! ```
+//! The capabilities are laid out like the reports, every field a mask of
+//! the bits the pad sets: the buttons it has, the resolution of its
+//! triggers, sticks and motors. A genuine controller reports 10 bit sticks.
+
+use embassy_usb::control::{Recipient, Request, RequestType};
+use embassy_usb::types::InterfaceNumber;
+
+/// bRequest of all of them
+pub const XINPUT_GET_REQUEST: u8 = 0x01;
+
+/// wValue asking for the input capabilities
+const INPUT_CAPABILITIES_VALUE: u16 = 0x0100;
+
+/// wValue asking for the vibration capabilities
+const VIBRATION_CAPABILITIES_VALUE: u16 = 0x0000;
+
+pub const INPUT_CAPABILITIES_SIZE: usize = 20;
+pub const VIBRATION_CAPABILITIES_SIZE: usize = 8;
+pub const SERIAL_SIZE: usize = 4;
+
+// the bits of the buttons, as in the input report
+pub const BUTTON_DPAD_UP: u16 = 0x0001;
+pub const BUTTON_DPAD_DOWN: u16 = 0x0002;
+pub const BUTTON_DPAD_LEFT: u16 = 0x0004;
+pub const BUTTON_DPAD_RIGHT: u16 = 0x0008;
+pub const BUTTON_MENU: u16 = 0x0010;
+pub const BUTTON_VIEW: u16 = 0x0020;
+pub const BUTTON_THUMB_LEFT: u16 = 0x0040;
+pub const BUTTON_THUMB_RIGHT: u16 = 0x0080;
+pub const BUTTON_SHOULDER_LEFT: u16 = 0x0100;
+pub const BUTTON_SHOULDER_RIGHT: u16 = 0x0200;
+pub const BUTTON_XBOX: u16 = 0x0400;
+pub const BUTTON_A: u16 = 0x1000;
+pub const BUTTON_B: u16 = 0x2000;
+pub const BUTTON_X: u16 = 0x4000;
+pub const BUTTON_Y: u16 = 0x8000;
+
+/// Every button, 0x0800 isn't one
+pub const ALL_BUTTONS: u16 = 0xf7ff;
+
+/// A vendor request of the Xinput driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum XinputRequest {
+    InputCapabilities,
+    VibrationCapabilities,
+    Serial,
+}
+
+impl XinputRequest {
+    /// The request `req` is, if any, with `interface` the control interface
+    pub fn parse(req: &Request, interface: InterfaceNumber) -> Option<Self> {
+        if req.request_type != RequestType::Vendor || req.request != XINPUT_GET_REQUEST {
+            return None;
+        }
+        match (req.recipient, req.value) {
+            (Recipient::Interface, INPUT_CAPABILITIES_VALUE)
+                if req.index == u8::from(interface) as u16 =>
+            {
+                Some(XinputRequest::InputCapabilities)
+            }
+            (Recipient::Interface, VIBRATION_CAPABILITIES_VALUE)
+                if req.index == u8::from(interface) as u16 =>
+            {
+                Some(XinputRequest::VibrationCapabilities)
+            }
+            (Recipient::Device, 0) if req.index == 0 => Some(XinputRequest::Serial),
+            _ => None,
+        }
+    }
+}
+
+/// What the pad has, each field a mask of the bits it reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Capabilities {
+    /// The `BUTTON_` bits
+    pub buttons: u16,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    /// Left X, left Y, right X and right Y
+    pub sticks: [u16; 4],
+    /// The left, low frequency, motor then the right one, 0 without
+    pub motors: [u8; 2],
+}
+
+impl Capabilities {
+    /// Those of a genuine controller
+    pub const GAMEPAD: Capabilities = Capabilities {
+        buttons: ALL_BUTTONS,
+        left_trigger: 0xff,
+        right_trigger: 0xff,
+        sticks: [0xffc0; 4],
+        motors: [0xff; 2],
+    };
+
+    /// The answer to [`XinputRequest::InputCapabilities`]
+    pub fn input(&self) -> [u8; INPUT_CAPABILITIES_SIZE] {
+        let mut report = [0; INPUT_CAPABILITIES_SIZE];
+        // report ID and size, like the input report
+        report[1] = INPUT_CAPABILITIES_SIZE as u8;
+        report[2..4].copy_from_slice(&self.buttons.to_le_bytes());
+        report[4] = self.left_trigger;
+        report[5] = self.right_trigger;
+        for (i, axis) in self.sticks.iter().enumerate() {
+            report[6 + 2 * i..8 + 2 * i].copy_from_slice(&axis.to_le_bytes());
+        }
+        report
+    }
+
+    /// The answer to [`XinputRequest::VibrationCapabilities`]
+    pub fn vibration(&self) -> [u8; VIBRATION_CAPABILITIES_SIZE] {
+        let mut report = [0; VIBRATION_CAPABILITIES_SIZE];
+        // report ID and size, like the rumble message
+        report[1] = VIBRATION_CAPABILITIES_SIZE as u8;
+        report[3..5].copy_from_slice(&self.motors);
+        report
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::GAMEPAD
+    }
+}
@@ -0,0 +1,154 @@
+//! Chords, buttons pressed together for another control.
+//!
+//! A [`Chord`] presses its control while all its buttons are held, if they
+//! were pressed within its window of each other. This reaches the controls a
+//! board has no button for, e.g. View + Menu for Guide.
+//!
+//! A chord may suppress its buttons: they are held back from the report
+//! while the chord can still complete, up to the window after the first
+//! press, and stay released once it does until let go. A button tapped
+//! quicker than that is reported on release, for [`TAP_PRESS`].
+//!
+//! [`TAP_PRESS`]: crate::mapping::TAP_PRESS
+
+use embassy_time::{Duration, Instant};
+
+use crate::board::{InputId, InputStates, Layout, MAX_BUTTONS};
+use crate::mapping::{Control, TAP_PRESS};
+
+/// The window of [`chord`]
+pub const CHORD_WINDOW: Duration = Duration::from_millis(50);
+
+/// The most chords a profile may have
+pub const MAX_CHORDS: usize = 32;
+
+/// Buttons held together for a control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub inputs: &'static [InputId],
+    pub control: Control,
+    /// The longest time between the first and last presses
+    pub window: Duration,
+    /// Whether the buttons are kept from the report
+    pub suppress: bool,
+}
+
+/// Shorthand for the chord tables, [`CHORD_WINDOW`] and suppressing
+pub const fn chord(inputs: &'static [InputId], control: Control) -> Chord {
+    Chord {
+        inputs,
+        control,
+        window: CHORD_WINDOW,
+        suppress: true,
+    }
+}
+
+/// Tracks the chords of a profile
+pub struct Chords {
+    previous: InputStates,
+    /// When each button was pressed, or released for the flushed ones
+    since: [Instant; MAX_BUTTONS],
+    /// Chords whose buttons are all held, bit per chord
+    active: u32,
+    /// The buttons of an active suppressing chord, until released
+    suppressed: InputStates,
+    /// The buttons held back by the last update
+    held_back: InputStates,
+    /// Buttons released while held back, reported for a while
+    flushed: InputStates,
+}
+
+impl Default for Chords {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chords {
+    pub const fn new() -> Self {
+        Chords {
+            previous: InputStates(0),
+            since: [Instant::from_ticks(0); MAX_BUTTONS],
+            active: 0,
+            suppressed: InputStates(0),
+            held_back: InputStates(0),
+            flushed: InputStates(0),
+        }
+    }
+
+    /// The chords active after the last update, bit per chord
+    pub fn active(&self) -> u32 {
+        self.active
+    }
+
+    /// The states of the buttons after the chords, for new button states
+    /// read at `now`.
+    ///
+    /// Call it for every update, with the chords of the active profile.
+    pub fn update(
+        &mut self,
+        layout: Layout,
+        chords: &[Chord],
+        states: InputStates,
+        now: Instant,
+    ) -> InputStates {
+        let just_pressed = states.0 & !self.previous.0;
+        let released = self.previous.0 & !states.0;
+        self.previous = states;
+        for index in 0..MAX_BUTTONS {
+            if just_pressed & 1 << index != 0 || released & self.held_back.0 & 1 << index != 0 {
+                self.since[index] = now;
+            }
+        }
+        self.flushed.0 |= released & self.held_back.0;
+        self.flushed.0 &= !just_pressed;
+        self.suppressed.0 &= states.0;
+
+        let mut held_back = 0;
+        for (n, chord) in chords.iter().enumerate().take(MAX_CHORDS) {
+            // none if a button is missing from the board
+            let inputs = chord.inputs.iter().try_fold(0u64, |inputs, input| {
+                layout.index(*input).map(|index| inputs | 1 << index)
+            });
+            let inputs = inputs.unwrap_or(0);
+            let held = inputs & states.0;
+            if held == 0 {
+                self.active &= !(1 << n);
+                continue;
+            }
+            let presses = (0..MAX_BUTTONS).filter(|index| held & 1 << index != 0);
+            let first = presses.clone().map(|index| self.since[index]).min();
+            let last = presses.map(|index| self.since[index]).max();
+            let (Some(first), Some(last)) = (first, last) else {
+                continue;
+            };
+            if held == inputs {
+                if self.active & 1 << n == 0
+                    && last.saturating_duration_since(first) <= chord.window
+                {
+                    debug!("Chord {}", chord.control);
+                    self.active |= 1 << n;
+                }
+                if self.active & 1 << n != 0 && chord.suppress {
+                    self.suppressed.0 |= inputs;
+                }
+            } else {
+                self.active &= !(1 << n);
+                if chord.suppress && now.saturating_duration_since(first) < chord.window {
+                    // it may still complete
+                    held_back |= held;
+                }
+            }
+        }
+        self.held_back = InputStates(held_back & !self.suppressed.0);
+
+        for index in 0..MAX_BUTTONS {
+            if self.flushed.0 & 1 << index != 0
+                && now.saturating_duration_since(self.since[index]) >= TAP_PRESS
+            {
+                self.flushed.0 &= !(1 << index);
+            }
+        }
+        InputStates(states.0 & !self.suppressed.0 & !self.held_back.0 | self.flushed.0)
+    }
+}
@@ -0,0 +1,135 @@
+//! Button debouncing.
+//!
+//! A switch bounces for a few milliseconds when it closes or opens, the scans
+//! then read it pressed and released several times in a row. A [`Debouncer`]
+//! turns the raw scans into clean button states after the [`Debounce`]
+//! algorithm of the board. It counts the time between scans, not the scans,
+//! so the scan rate can change without retuning it.
+
+use embassy_time::{Duration, Instant};
+
+use crate::board::{InputStates, MAX_BUTTONS};
+
+/// How the raw scans are filtered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Debounce {
+    /// The raw scans as they are
+    #[default]
+    Off,
+    /// Report the first edge at once, then ignore the button for `lockout`.
+    /// No latency, but a glitch is reported as a short press.
+    Eager { lockout: Duration },
+    /// Report a change once the button read the same for `stable`. Glitches
+    /// are ignored, at the cost of `stable` of latency.
+    Deferred { stable: Duration },
+    /// A counter per button, going up by the time between scans while it
+    /// reads pressed and down while it reads released. The button is pressed
+    /// when the counter reaches `time` and released when it is back to 0, so
+    /// bounces slow a change down instead of starting it over.
+    Integrator { time: Duration },
+}
+
+/// Filters the raw scans of every button
+pub struct Debouncer {
+    config: Debounce,
+    raw: InputStates,
+    states: InputStates,
+    /// End of the lockout of each button when eager, when it may change
+    /// when deferred
+    deadlines: [Instant; MAX_BUTTONS],
+    /// Counters of the integrator, in ticks
+    levels: [u64; MAX_BUTTONS],
+    last_scan: Option<Instant>,
+}
+
+impl Debouncer {
+    /// All buttons are released at start.
+    pub fn new(config: Debounce) -> Self {
+        Debouncer {
+            config,
+            raw: InputStates::default(),
+            states: InputStates::default(),
+            deadlines: [Instant::from_ticks(0); MAX_BUTTONS],
+            levels: [0; MAX_BUTTONS],
+            last_scan: None,
+        }
+    }
+
+    pub fn config(&self) -> Debounce {
+        self.config
+    }
+
+    /// The debounced states
+    pub fn states(&self) -> InputStates {
+        self.states
+    }
+
+    /// Scanning stopped for a while, e.g. sleeping until a press, the pause
+    /// doesn't count as time the buttons held their state.
+    pub fn resume(&mut self) {
+        self.last_scan = None;
+    }
+
+    /// Filter the `raw` states of a scan made at `now`, returns the debounced
+    /// states.
+    pub fn update(&mut self, raw: InputStates, now: Instant) -> InputStates {
+        let elapsed = match self.last_scan {
+            Some(last) => now.saturating_duration_since(last),
+            None => Duration::from_ticks(0),
+        };
+        self.last_scan = Some(now);
+
+        match self.config {
+            Debounce::Off => self.states = raw,
+            Debounce::Eager { lockout } => {
+                for index in bits(raw.0 ^ self.states.0) {
+                    if now >= self.deadlines[index] {
+                        self.states.set(index, raw.is_pressed(index));
+                        self.deadlines[index] = now + lockout;
+                    }
+                }
+            }
+            Debounce::Deferred { stable } => {
+                for index in bits(raw.0 ^ self.raw.0) {
+                    self.deadlines[index] = now + stable;
+                }
+                for index in bits(raw.0 ^ self.states.0) {
+                    if now >= self.deadlines[index] {
+                        self.states.set(index, raw.is_pressed(index));
+                    }
+                }
+            }
+            Debounce::Integrator { time } => {
+                let (step, full) = (elapsed.as_ticks(), time.as_ticks());
+                for (index, level) in self.levels.iter_mut().enumerate() {
+                    if raw.is_pressed(index) {
+                        *level = (*level + step).min(full);
+                        if *level == full {
+                            self.states.set(index, true);
+                        }
+                    } else if *level != 0 || self.states.is_pressed(index) {
+                        *level = level.saturating_sub(step);
+                        if *level == 0 {
+                            self.states.set(index, false);
+                        }
+                    }
+                }
+            }
+        }
+        self.raw = raw;
+        self.states
+    }
+}
+
+/// Indexes of the bits set in `mask`
+fn bits(mut mask: u64) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let index = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(index)
+    })
+}
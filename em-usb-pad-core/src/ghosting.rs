@@ -0,0 +1,99 @@
+//! Ghosting in a key matrix without diodes.
+//!
+//! With three keys held at the corners of a rectangle, two on a row and two
+//! on a column, the scanning current goes through them backwards and the
+//! fourth corner reads pressed too. Once all four corners read pressed there
+//! is no way to tell which one is a ghost, [`ghost_keys`] finds them and an
+//! [`AntiGhost`] filter deals with them after its [`GhostPolicy`].
+//!
+//! A matrix with a diode on every key can't ghost, the board says so with
+//! [`BoardInfo::diodes`] and the check is skipped.
+
+use crate::board::{BoardInfo, InputStates, Layout};
+
+/// What to do with the keys of a rectangle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GhostPolicy {
+    /// Report them as read, ghost included
+    Off,
+    /// Keep them as they were before the rectangle showed up, the keys
+    /// already held stay held, the key completing it and its ghost wait for
+    /// it to go away
+    #[default]
+    HoldBack,
+    /// Release all of them until the rectangle goes away
+    Release,
+}
+
+/// The matrix keys at the corners of a rectangle of pressed keys
+pub fn ghost_keys(layout: Layout, states: InputStates) -> InputStates {
+    let cols = layout.cols as usize;
+    if cols == 0 {
+        return InputStates::default();
+    }
+    let row_mask = u64::MAX >> (64 - cols);
+    let row = |n: usize| (states.0 >> (n * cols)) & row_mask;
+    let mut ghosts = 0;
+    for first in 0..layout.rows as usize {
+        for second in first + 1..layout.rows as usize {
+            let shared = row(first) & row(second);
+            if shared.count_ones() >= 2 {
+                ghosts |= shared << (first * cols) | shared << (second * cols);
+            }
+        }
+    }
+    InputStates(ghosts)
+}
+
+/// Filters the scans of a matrix that may ghost
+pub struct AntiGhost {
+    layout: Layout,
+    policy: GhostPolicy,
+    states: InputStates,
+    ghosts: InputStates,
+}
+
+impl AntiGhost {
+    /// `policy` is ignored when the board has diodes.
+    pub fn new(info: &BoardInfo, policy: GhostPolicy) -> Self {
+        AntiGhost {
+            layout: info.layout,
+            policy: if info.diodes {
+                GhostPolicy::Off
+            } else {
+                policy
+            },
+            states: InputStates::default(),
+            ghosts: InputStates::default(),
+        }
+    }
+
+    pub fn policy(&self) -> GhostPolicy {
+        self.policy
+    }
+
+    /// The ambiguous keys of the last scan
+    pub fn ghosts(&self) -> InputStates {
+        self.ghosts
+    }
+
+    /// Filter the states of a scan.
+    pub fn filter(&mut self, raw: InputStates) -> InputStates {
+        let ghosts = match self.policy {
+            GhostPolicy::Off => InputStates::default(),
+            _ => ghost_keys(self.layout, raw),
+        };
+        if ghosts != self.ghosts && ghosts != InputStates::default() {
+            debug!("Ghosting on keys {:x}", ghosts.0);
+        }
+        self.ghosts = ghosts;
+        let kept = match self.policy {
+            GhostPolicy::Off => 0,
+            GhostPolicy::HoldBack => self.states.0 & ghosts.0,
+            GhostPolicy::Release => 0,
+        };
+        self.states = InputStates(raw.0 & !ghosts.0 | kept);
+        self.states
+    }
+}
@@ -0,0 +1,258 @@
+//! Player indicator LEDs.
+//!
+//! The Xbox 360 controller has a ring of four quadrant LEDs around the guide
+//! button. [`LedRing`] turns the [`XinputLedPattern`] sent by the host into
+//! frames for those four LEDs, and hands them to a [`LedOutput`]. The firmware
+//! may also blink them to acknowledge a setting, see [`LedRing::notify`].
+//!
+//! Quadrants are numbered like on the real ring:
+//! ```text
+//!  1 | 2
+//! ---+---
+//!  3 | 4
+//! ```
+//! so a rotation goes 1, 2, 4, 3.
+
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::xinput::XinputLedPattern;
+
+/// Which of the four quadrants are lit, bit 0 is quadrant 1
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedQuadrants(pub u8);
+
+impl LedQuadrants {
+    pub const NONE: LedQuadrants = LedQuadrants(0b0000);
+    pub const ALL: LedQuadrants = LedQuadrants(0b1111);
+
+    /// Only `quadrant` (1 to 4) lit
+    pub const fn only(quadrant: u8) -> Self {
+        LedQuadrants(1 << (quadrant - 1))
+    }
+
+    /// Whether `quadrant` (1 to 4) is lit
+    pub const fn is_on(&self, quadrant: u8) -> bool {
+        self.0 & (1 << (quadrant - 1)) != 0
+    }
+
+    pub const fn union(self, other: LedQuadrants) -> Self {
+        LedQuadrants(self.0 | other.0)
+    }
+}
+
+/// Where the frames go, e.g. four GPIOs
+pub trait LedOutput {
+    fn set_leds(&mut self, leds: LedQuadrants);
+}
+
+// timings, close to what a genuine controller shows
+const LED_BLINK_STEP: Duration = Duration::from_millis(250);
+const LED_BLINK_SLOW_STEP: Duration = Duration::from_millis(500);
+const LED_ROTATE_STEP: Duration = Duration::from_millis(100);
+const LED_NOTIFY_STEP: Duration = Duration::from_millis(80);
+// how many times the temporary animations play before settling
+const LED_FLASH_CYCLES: u32 = 3;
+const LED_BLINK_ONCE_CYCLES: u32 = 3;
+const LED_ALTERNATE_CYCLES: u32 = 4;
+
+/// A sequence of frames of the same duration
+struct Animation {
+    frames: [LedQuadrants; 4],
+    frame_count: usize,
+    step: Duration,
+    /// How many times the sequence plays, `None` for ever
+    cycles: Option<u32>,
+    /// What stays on once it's played
+    settle: LedQuadrants,
+}
+
+impl Animation {
+    fn steady(leds: LedQuadrants) -> Self {
+        Animation {
+            frames: [leds; 4],
+            frame_count: 1,
+            step: Duration::from_ticks(0),
+            cycles: None,
+            settle: leds,
+        }
+    }
+
+    fn blink(
+        leds: LedQuadrants,
+        step: Duration,
+        cycles: Option<u32>,
+        settle: LedQuadrants,
+    ) -> Self {
+        Animation {
+            frames: [leds, LedQuadrants::NONE, leds, LedQuadrants::NONE],
+            frame_count: 2,
+            step,
+            cycles,
+            settle,
+        }
+    }
+
+    /// The frame shown `elapsed` after the start, and how long it lasts (`None` for ever)
+    fn frame(&self, elapsed: Duration) -> (LedQuadrants, Option<Duration>) {
+        if self.frame_count == 1 {
+            return (self.frames[0], None);
+        }
+        let index = elapsed.as_ticks() / self.step.as_ticks();
+        if let Some(cycles) = self.cycles {
+            if index >= (cycles as usize * self.frame_count) as u64 {
+                return (self.settle, None);
+            }
+        }
+        let next = Duration::from_ticks((index + 1) * self.step.as_ticks());
+        (
+            self.frames[(index % self.frame_count as u64) as usize],
+            Some(next - elapsed),
+        )
+    }
+}
+
+/// Plays the LED patterns on four quadrant LEDs
+pub struct LedRing<O: LedOutput> {
+    output: O,
+    animation: Animation,
+    started: Instant,
+    /// The steady state, some patterns go back to it
+    base: LedQuadrants,
+    shown: LedQuadrants,
+}
+
+impl<O: LedOutput> LedRing<O> {
+    /// All LEDs are off at start.
+    pub fn new(mut output: O) -> Self {
+        output.set_leds(LedQuadrants::NONE);
+        LedRing {
+            output,
+            animation: Animation::steady(LedQuadrants::NONE),
+            started: Instant::from_ticks(0),
+            base: LedQuadrants::NONE,
+            shown: LedQuadrants::NONE,
+        }
+    }
+
+    /// What the LEDs currently show
+    pub fn shown(&self) -> LedQuadrants {
+        self.shown
+    }
+
+    /// Start playing `pattern`, then call [`LedRing::update`].
+    pub fn set_pattern(&mut self, pattern: XinputLedPattern, now: Instant) {
+        use XinputLedPattern::*;
+
+        let base = self.base;
+        let (animation, new_base) = match pattern {
+            Off => (Animation::steady(LedQuadrants::NONE), LedQuadrants::NONE),
+            On1 | On2 | On3 | On4 => {
+                let leds = LedQuadrants::only(pattern as u8 - On1 as u8 + 1);
+                (Animation::steady(leds), leds)
+            }
+            Flash1 | Flash2 | Flash3 | Flash4 => {
+                let leds = LedQuadrants::only(pattern as u8 - Flash1 as u8 + 1);
+                let animation =
+                    Animation::blink(leds, LED_BLINK_STEP, Some(LED_FLASH_CYCLES), leds);
+                (animation, leds)
+            }
+            Blink => (
+                Animation::blink(LedQuadrants::ALL, LED_BLINK_STEP, None, LedQuadrants::ALL),
+                base,
+            ),
+            BlinkOnce => (
+                Animation::blink(base, LED_BLINK_STEP, Some(LED_BLINK_ONCE_CYCLES), base),
+                base,
+            ),
+            BlinkSlow => (
+                Animation::blink(base, LED_BLINK_SLOW_STEP, None, base),
+                base,
+            ),
+            Rotate => (
+                Animation {
+                    frames: [
+                        LedQuadrants::only(1),
+                        LedQuadrants::only(2),
+                        LedQuadrants::only(4),
+                        LedQuadrants::only(3),
+                    ],
+                    frame_count: 4,
+                    step: LED_ROTATE_STEP,
+                    cycles: None,
+                    settle: base,
+                },
+                base,
+            ),
+            Alternate => {
+                let diagonal = LedQuadrants::only(1).union(LedQuadrants::only(4));
+                let anti_diagonal = LedQuadrants::only(2).union(LedQuadrants::only(3));
+                (
+                    Animation {
+                        frames: [diagonal, anti_diagonal, diagonal, anti_diagonal],
+                        frame_count: 2,
+                        step: LED_BLINK_STEP,
+                        cycles: Some(LED_ALTERNATE_CYCLES),
+                        settle: base,
+                    },
+                    base,
+                )
+            }
+        };
+        self.animation = animation;
+        self.base = new_base;
+        self.started = now;
+    }
+
+    /// Blink all LEDs `blinks` times, then show the player number again.
+    pub fn notify(&mut self, blinks: u8, now: Instant) {
+        self.animation = Animation::blink(
+            LedQuadrants::ALL,
+            LED_NOTIFY_STEP,
+            Some(blinks as u32),
+            self.base,
+        );
+        self.started = now;
+    }
+
+    /// Show the frame for `now`.
+    ///
+    /// Returns when the next frame is due, `None` if the LEDs won't change
+    /// until the next pattern.
+    pub fn update(&mut self, now: Instant) -> Option<Instant> {
+        let elapsed = now.saturating_duration_since(self.started);
+        let (leds, remaining) = self.animation.frame(elapsed);
+        if self.shown != leds {
+            self.shown = leds;
+            self.output.set_leds(leds);
+        }
+        remaining.map(|remaining| now + remaining)
+    }
+
+    /// Play the patterns received through `patterns` and the notifications
+    /// received through `notices`, for ever.
+    pub async fn run<M: RawMutex>(
+        &mut self,
+        patterns: &Signal<M, XinputLedPattern>,
+        notices: &Signal<M, u8>,
+    ) -> ! {
+        loop {
+            let next_frame = self.update(Instant::now());
+            let event = match next_frame {
+                Some(at) => select3(patterns.wait(), notices.wait(), Timer::at(at)).await,
+                None => match select(patterns.wait(), notices.wait()).await {
+                    Either::First(pattern) => Either3::First(pattern),
+                    Either::Second(blinks) => Either3::Second(blinks),
+                },
+            };
+            match event {
+                Either3::First(pattern) => self.set_pattern(pattern, Instant::now()),
+                Either3::Second(blinks) => self.notify(blinks, Instant::now()),
+                Either3::Third(()) => {}
+            }
+        }
+    }
+}
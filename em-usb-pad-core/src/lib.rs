@@ -0,0 +1,27 @@
+#![no_std]
+
+// This mod MUST go first, so that the others see its macros.
+mod fmt;
+
+pub mod analog;
+pub mod board;
+pub mod capabilities;
+pub mod chords;
+pub mod debounce;
+pub mod ghosting;
+pub mod led;
+pub mod macros;
+pub mod mapping;
+pub mod msos;
+pub mod protocol;
+pub mod rumble;
+pub mod settings;
+pub mod shaping;
+pub mod socd;
+pub mod store;
+pub mod turbo;
+pub mod xinput;
+
+// Only for host tests, not meant to be used in the firmware.
+#[cfg(feature = "mock")]
+pub mod mock;
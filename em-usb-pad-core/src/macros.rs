@@ -0,0 +1,409 @@
+//! Macros, timed sequences of reports.
+//!
+//! A macro is recorded from the reports the pad sends, and played back in
+//! their place at the recorded times when its button is pressed. Holding the
+//! macro hotkey of the profile while pressing a macro button records into its
+//! slot instead, pressing a macro button again stops recording.
+//!
+//! A [`Macro`] keeps the changes between consecutive reports, one frame per
+//! change:
+//! ```text
+//! frame   := delay changed value*
+//! delay   := milliseconds since the previous frame, LEB128
+//! changed := u16 LE, bit n set when byte n of the packed report changed
+//! value   := the new byte, one per bit set in changed
+//! ```
+//! The first frame is relative to the neutral report and plays right away, a
+//! button press or release takes 4 bytes. Each slot holds [`MACRO_CAPACITY`]
+//! bytes, [`MACRO_SLOTS`] of them take 4 KB of RAM.
+
+use embassy_time::{Duration, Instant};
+use packed_struct::PackedStruct;
+
+use crate::xinput::XinputControlReport;
+
+/// How many macros the pad holds
+pub const MACRO_SLOTS: usize = 4;
+
+/// Bytes per macro
+pub const MACRO_CAPACITY: usize = 1024;
+
+/// Size of a packed [`XinputControlReport`]
+const REPORT_BYTES: usize = 12;
+
+/// The largest frame: a 5 bytes delay, the mask and every byte changed
+const MAX_FRAME_BYTES: usize = 5 + 2 + REPORT_BYTES;
+
+type Packed = [u8; REPORT_BYTES];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MacroError {
+    /// More than [`MACRO_CAPACITY`] bytes
+    Full,
+    /// Not a sequence of frames
+    Malformed,
+}
+
+/// One step of a macro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Since the previous frame
+    pub delay: Duration,
+    report: Packed,
+}
+
+impl Frame {
+    /// The whole report from then on
+    pub fn report(&self) -> XinputControlReport {
+        // every byte string unpacks, the reserved bit is ignored
+        XinputControlReport::unpack(&self.report).unwrap_or_default()
+    }
+}
+
+/// A recorded sequence of reports
+#[derive(Clone)]
+pub struct Macro {
+    bytes: [u8; MACRO_CAPACITY],
+    len: usize,
+}
+
+impl Default for Macro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Macro {
+    /// An empty macro, it plays nothing
+    pub const fn new() -> Self {
+        Macro {
+            bytes: [0; MACRO_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Load a macro stored with [`Macro::as_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MacroError> {
+        if bytes.len() > MACRO_CAPACITY {
+            return Err(MacroError::Full);
+        }
+        let mut position = 0;
+        while position < bytes.len() {
+            position = read_frame(bytes, position, &mut [0; REPORT_BYTES])
+                .ok_or(MacroError::Malformed)?
+                .1;
+        }
+        let mut loaded = Macro::new();
+        loaded.bytes[..bytes.len()].copy_from_slice(bytes);
+        loaded.len = bytes.len();
+        Ok(loaded)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// The frames, each with the whole report
+    pub fn frames(&self) -> Frames<'_> {
+        Frames {
+            bytes: self.as_bytes(),
+            position: 0,
+            report: [0; REPORT_BYTES],
+        }
+    }
+
+    /// Append the change from `from` to `to`, nothing if they are the same.
+    ///
+    /// Room is kept for a last frame, so that a recording cut short can
+    /// always go back to neutral.
+    fn push(&mut self, delay: Duration, from: &Packed, to: &Packed) -> Result<(), MacroError> {
+        let changed = (0..REPORT_BYTES)
+            .filter(|n| from[*n] != to[*n])
+            .fold(0u16, |mask, n| mask | 1 << n);
+        if changed == 0 {
+            return Ok(());
+        }
+        let mut frame = [0; MAX_FRAME_BYTES];
+        let mut len = write_delay(&mut frame, delay.as_millis().min(u32::MAX as u64) as u32);
+        frame[len..len + 2].copy_from_slice(&changed.to_le_bytes());
+        len += 2;
+        for n in (0..REPORT_BYTES).filter(|n| changed & 1 << n != 0) {
+            frame[len] = to[n];
+            len += 1;
+        }
+        let reserved = if *to == [0; REPORT_BYTES] {
+            0
+        } else {
+            MAX_FRAME_BYTES
+        };
+        if self.len + len + reserved > MACRO_CAPACITY {
+            return Err(MacroError::Full);
+        }
+        self.bytes[self.len..self.len + len].copy_from_slice(&frame[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Iterates the frames of a [`Macro`]
+pub struct Frames<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    report: Packed,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let (delay, next) = read_frame(self.bytes, self.position, &mut self.report)?;
+        self.position = next;
+        Some(Frame {
+            delay,
+            report: self.report,
+        })
+    }
+}
+
+/// Apply the frame at `position` to `report`, returns its delay and where
+/// the next one starts.
+fn read_frame(bytes: &[u8], mut position: usize, report: &mut Packed) -> Option<(Duration, usize)> {
+    let mut delay: u64 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(position)?;
+        position += 1;
+        delay |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        } else if shift == 28 {
+            return None;
+        }
+    }
+    let changed = u16::from_le_bytes([*bytes.get(position)?, *bytes.get(position + 1)?]);
+    position += 2;
+    if changed == 0 || changed >> REPORT_BYTES != 0 {
+        return None;
+    }
+    for n in (0..REPORT_BYTES).filter(|n| changed & 1 << n != 0) {
+        report[n] = *bytes.get(position)?;
+        position += 1;
+    }
+    Some((Duration::from_millis(delay), position))
+}
+
+fn write_delay(buf: &mut [u8], mut millis: u32) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (millis & 0x7f) as u8;
+        millis >>= 7;
+        if millis == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn pack(report: &XinputControlReport) -> Packed {
+    report.pack().unwrap_or_default()
+}
+
+struct Recording {
+    slot: u8,
+    /// The last report recorded
+    report: Packed,
+    /// When it changed, `None` until the first change
+    since: Option<Instant>,
+}
+
+struct Playback {
+    slot: u8,
+    /// Where the next frame starts
+    position: usize,
+    report: Packed,
+    /// When the next frame plays
+    due: Instant,
+}
+
+/// Records and plays the macros
+pub struct Macros {
+    slots: [Macro; MACRO_SLOTS],
+    recording: Option<Recording>,
+    playing: Option<Playback>,
+}
+
+impl Default for Macros {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Macros {
+    /// All slots empty
+    pub const fn new() -> Self {
+        const EMPTY: Macro = Macro::new();
+        Macros {
+            slots: [EMPTY; MACRO_SLOTS],
+            recording: None,
+            playing: None,
+        }
+    }
+
+    /// `None` past the last slot
+    pub fn get(&self, slot: u8) -> Option<&Macro> {
+        self.slots.get(slot as usize)
+    }
+
+    /// Replace the macro of `slot`, returns false if there is no such slot.
+    pub fn set(&mut self, slot: u8, recorded: Macro) -> bool {
+        if slot as usize >= MACRO_SLOTS {
+            return false;
+        }
+        self.playing = None;
+        self.slots[slot as usize] = recorded;
+        true
+    }
+
+    /// The slot being recorded
+    pub fn recording(&self) -> Option<u8> {
+        self.recording.as_ref().map(|recording| recording.slot)
+    }
+
+    /// The slot being played
+    pub fn playing(&self) -> Option<u8> {
+        self.playing.as_ref().map(|playback| playback.slot)
+    }
+
+    /// Record into `slot` from now on, what it held is lost.
+    pub fn start_recording(&mut self, slot: u8) -> bool {
+        let Some(stored) = self.slots.get_mut(slot as usize) else {
+            return false;
+        };
+        self.playing = None;
+        stored.clear();
+        self.recording = Some(Recording {
+            slot,
+            report: [0; REPORT_BYTES],
+            since: None,
+        });
+        info!("Recording macro {}", slot);
+        true
+    }
+
+    /// Keep what was recorded, ending on the neutral report.
+    pub fn stop_recording(&mut self, now: Instant) {
+        if let Some(recording) = self.recording.take() {
+            let stored = &mut self.slots[recording.slot as usize];
+            let delay = match recording.since {
+                Some(since) => now.saturating_duration_since(since),
+                None => Duration::from_ticks(0),
+            };
+            // room is always left for it
+            let _ = stored.push(delay, &recording.report, &[0; REPORT_BYTES]);
+            info!(
+                "Recorded macro {}, {} bytes",
+                recording.slot,
+                stored.as_bytes().len()
+            );
+        }
+    }
+
+    /// Play `slot` from the start, returns false if it is empty.
+    pub fn play(&mut self, slot: u8, now: Instant) -> bool {
+        match self.slots.get(slot as usize) {
+            Some(stored) if !stored.is_empty() => {
+                debug!("Playing macro {}", slot);
+                self.playing = Some(Playback {
+                    slot,
+                    position: 0,
+                    report: [0; REPORT_BYTES],
+                    due: now,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn stop_playing(&mut self) {
+        self.playing = None;
+    }
+
+    /// Record or play over `report`, call it for every report.
+    ///
+    /// `pressed` is the slot of the macro button just pressed, `hotkey`
+    /// whether the macro hotkey is held, see [`Mapper`]. While a macro plays
+    /// the report is replaced with it. Returns when its next frame is due.
+    ///
+    /// [`Mapper`]: crate::mapping::Mapper
+    pub fn apply(
+        &mut self,
+        report: &mut XinputControlReport,
+        hotkey: bool,
+        pressed: Option<u8>,
+        now: Instant,
+    ) -> Option<Instant> {
+        if let Some(slot) = pressed {
+            if self.recording.is_some() {
+                self.stop_recording(now);
+            } else if hotkey {
+                self.start_recording(slot);
+            } else {
+                self.play(slot, now);
+            }
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            let packed = pack(report);
+            let delay = match recording.since {
+                Some(since) => now.saturating_duration_since(since),
+                // the wait before the first change isn't kept
+                None => Duration::from_ticks(0),
+            };
+            let stored = &mut self.slots[recording.slot as usize];
+            match stored.push(delay, &recording.report, &packed) {
+                Ok(()) if packed != recording.report => {
+                    recording.report = packed;
+                    recording.since = Some(now);
+                }
+                Ok(()) => {}
+                Err(_) => {
+                    warn!("Macro {} full", recording.slot);
+                    self.stop_recording(now);
+                }
+            }
+            return None;
+        }
+
+        let playback = self.playing.as_mut()?;
+        let stored = &self.slots[playback.slot as usize];
+        while playback.due <= now {
+            match read_frame(stored.as_bytes(), playback.position, &mut playback.report) {
+                Some((_, next)) => playback.position = next,
+                None => break,
+            }
+            // the delay of the next frame counts from this one
+            match read_frame(stored.as_bytes(), playback.position, &mut [0; REPORT_BYTES]) {
+                Some((delay, _)) => playback.due += delay,
+                None => {
+                    // the last frame went back to neutral
+                    *report = XinputControlReport::unpack(&playback.report).unwrap_or_default();
+                    self.playing = None;
+                    return None;
+                }
+            }
+        }
+        *report = XinputControlReport::unpack(&playback.report).unwrap_or_default();
+        Some(playback.due)
+    }
+}
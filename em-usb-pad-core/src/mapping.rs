@@ -0,0 +1,549 @@
+//! Button mapping.
+//!
+//! A [`Profile`] is a table binding the physical buttons of a board to the
+//! controls of an Xbox 360 controller. A board ships several profiles, and a
+//! [`Mapper`] turns the button states into a [`XinputControlReport`] through
+//! the active one, switching profiles when asked.
+//!
+//! Several buttons may be bound to the same control, it is pressed while any
+//! of them is. A trigger goes as far as the deepest of its buttons.
+//!
+//! The D-pad buttons drive the D-pad, or either stick as a fight stick would,
+//! after the [`DpadMode`] of the mapper. A profile's hotkey cycles it.
+//!
+//! A profile may have a function key: while it's held the buttons go through
+//! its [`Layer`], and tapping it does something else. A button keeps the
+//! layer it was pressed in until released.
+//!
+//! The settings may bind buttons of a profile to other controls, see
+//! [`Mapper::set_remaps`]. That replaces their bindings in the profile's
+//! table, not in its layer.
+
+use embassy_time::{Duration, Instant};
+
+use crate::board::{InputId, InputStates, Layout};
+use crate::chords::{Chord, Chords};
+use crate::settings::{used_remaps, Remap, MAX_REMAPS};
+use crate::shaping::{SticksShaping, TriggersShaping};
+use crate::socd::{SocdCleaner, SocdConfig};
+use crate::xinput::XinputControlReport;
+
+pub use em_usb_pad_protocol::controls::{bind, Binding, Control, Direction, DpadMode};
+
+/// Alternate bindings while a function key is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layer {
+    /// The function key, it doesn't reach the report
+    pub key: InputId,
+    /// What tapping the key does, pressed for [`TAP_PRESS`]
+    pub tap: Option<Control>,
+    /// The buttons not bound here keep the bindings of the profile
+    pub bindings: &'static [Binding],
+}
+
+/// A press of the function key shorter than this, with no other button
+/// pressed meanwhile, is a tap
+pub const TAP_TERM: Duration = Duration::from_millis(200);
+
+/// How long the control of a tap stays pressed
+pub const TAP_PRESS: Duration = Duration::from_millis(50);
+
+/// A named mapping table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub bindings: &'static [Binding],
+    /// Deadzones and curves of the analog sticks
+    pub shaping: SticksShaping,
+    /// Deadzones and curves of the analog triggers
+    pub triggers: TriggersShaping,
+    /// Opposite directions held together, on the D-pad and the sticks
+    /// driven by buttons
+    pub socd: SocdConfig,
+    /// Buttons held together to cycle the [`DpadMode`], none when empty.
+    /// They don't reach the report while they are all held.
+    pub dpad_hotkey: &'static [InputId],
+    /// Buttons held together to switch the turbo mode of the controls
+    /// pressed meanwhile, see [`crate::turbo`]
+    pub turbo_hotkey: &'static [InputId],
+    /// Buttons held together to record into the macro whose button is
+    /// pressed meanwhile
+    pub macro_hotkey: &'static [InputId],
+    /// The function key and its layer, if any
+    pub layer: Option<Layer>,
+    /// Buttons pressed together for another control, see [`crate::chords`]
+    pub chords: &'static [Chord],
+}
+
+impl Profile {
+    /// Whether two hotkeys of the profile, or a hotkey and a chord, share a
+    /// button, holding one would then hold part of the other
+    pub const fn hotkeys_overlap(&self) -> bool {
+        hotkeys_overlap(
+            &[self.dpad_hotkey, self.turbo_hotkey, self.macro_hotkey],
+            self.chords,
+        )
+    }
+
+    /// The report with the controls bound to the pressed buttons
+    ///
+    /// There's no record of the order of the presses, the SOCD modes relying
+    /// on it resolve to neutral. Opposite stick directions left by SOCD
+    /// cleaning cancel out.
+    pub fn report(&self, layout: Layout, states: InputStates) -> XinputControlReport {
+        self.report_with(layout, states, DpadMode::Dpad, &mut SocdCleaner::new())
+    }
+
+    /// Same as [`Profile::report`], the D-pad buttons drive `dpad_mode` and
+    /// `socd` follows the order of the presses.
+    pub fn report_with(
+        &self,
+        layout: Layout,
+        states: InputStates,
+        dpad_mode: DpadMode,
+        socd: &mut SocdCleaner,
+    ) -> XinputControlReport {
+        let controls = self.controls(layout, states, InputStates::default(), &[]);
+        self.report_controls(controls, dpad_mode, socd)
+    }
+
+    /// The controls bound to the pressed buttons, through the layer for
+    /// those in `layered`, `remaps` replacing the bindings of the table
+    fn controls<'a>(
+        &'a self,
+        layout: Layout,
+        states: InputStates,
+        layered: InputStates,
+        remaps: &'a [Binding],
+    ) -> impl Iterator<Item = Control> + 'a {
+        let layer = self.layer.map_or(&[][..], |layer| layer.bindings);
+        let on_layer = move |input: InputId| {
+            is_pressed(layout, layered, input) && layer.iter().any(|bound| bound.input == input)
+        };
+        let remapped = move |input: InputId| remaps.iter().any(|remap| remap.input == input);
+        let base = self.bindings.iter().filter(move |binding| {
+            is_pressed(layout, states, binding.input)
+                && !on_layer(binding.input)
+                && !remapped(binding.input)
+        });
+        let remaps = remaps.iter().filter(move |binding| {
+            is_pressed(layout, states, binding.input) && !on_layer(binding.input)
+        });
+        let layer = layer.iter().filter(move |binding| {
+            is_pressed(layout, states, binding.input) && is_pressed(layout, layered, binding.input)
+        });
+        base.chain(remaps)
+            .chain(layer)
+            .map(|binding| binding.control)
+    }
+
+    fn report_controls(
+        &self,
+        controls: impl Iterator<Item = Control>,
+        dpad_mode: DpadMode,
+        socd: &mut SocdCleaner,
+    ) -> XinputControlReport {
+        let mut report = XinputControlReport::default();
+        let mut dpad = [false; 4];
+        let mut left_stick = [false; 4];
+        let mut right_stick = [false; 4];
+        for control in controls {
+            match control {
+                Control::A => report.button_a = true,
+                Control::B => report.button_b = true,
+                Control::X => report.button_x = true,
+                Control::Y => report.button_y = true,
+                Control::LB => report.shoulder_left = true,
+                Control::RB => report.shoulder_right = true,
+                Control::View => report.button_view = true,
+                Control::Menu => report.button_menu = true,
+                Control::Guide => report.xbox_button = true,
+                Control::LS => report.thumb_click_left = true,
+                Control::RS => report.thumb_click_right = true,
+                Control::DpadUp => dpad[Direction::Up as usize] = true,
+                Control::DpadDown => dpad[Direction::Down as usize] = true,
+                Control::DpadLeft => dpad[Direction::Left as usize] = true,
+                Control::DpadRight => dpad[Direction::Right as usize] = true,
+                Control::LT => report.trigger_left = u8::MAX,
+                Control::RT => report.trigger_right = u8::MAX,
+                Control::LeftTrigger(value) => report.trigger_left = report.trigger_left.max(value),
+                Control::RightTrigger(value) => {
+                    report.trigger_right = report.trigger_right.max(value)
+                }
+                Control::LeftStick(direction) => left_stick[direction as usize] = true,
+                Control::RightStick(direction) => right_stick[direction as usize] = true,
+                Control::NextProfile | Control::Macro(_) => {}
+            }
+        }
+        let target = match dpad_mode {
+            DpadMode::Dpad => None,
+            DpadMode::LeftStick => Some(&mut left_stick),
+            DpadMode::RightStick => Some(&mut right_stick),
+        };
+        if let Some(stick) = target {
+            for (held, pressed) in stick.iter_mut().zip(dpad) {
+                *held |= pressed;
+            }
+            dpad = [false; 4];
+        }
+        let dpad = socd.dpad.clean(self.socd, dpad);
+        report.dpad_up = dpad[Direction::Up as usize];
+        report.dpad_down = dpad[Direction::Down as usize];
+        report.dpad_left = dpad[Direction::Left as usize];
+        report.dpad_right = dpad[Direction::Right as usize];
+        let left_stick = socd.left_stick.clean(self.socd, left_stick);
+        (report.js_left_x, report.js_left_y) = stick_position(left_stick);
+        let right_stick = socd.right_stick.clean(self.socd, right_stick);
+        (report.js_right_x, report.js_right_y) = stick_position(right_stick);
+        report
+    }
+}
+
+fn is_pressed(layout: Layout, states: InputStates, input: InputId) -> bool {
+    match layout.index(input) {
+        Some(index) => states.is_pressed(index),
+        None => false,
+    }
+}
+
+/// Whether all the buttons of `hotkey` are held, never for an empty one
+fn hotkey_held(layout: Layout, states: InputStates, hotkey: &[InputId]) -> bool {
+    !hotkey.is_empty()
+        && hotkey
+            .iter()
+            .all(|input| is_pressed(layout, states, *input))
+}
+
+/// Whether two of `hotkeys`, or one of them and one of `chords`, share a
+/// button, e.g. to check the hotkeys of a board at build time
+pub const fn hotkeys_overlap(hotkeys: &[&[InputId]], chords: &[Chord]) -> bool {
+    let mut first = 0;
+    while first < hotkeys.len() {
+        let mut second = first + 1;
+        while second < hotkeys.len() {
+            if share_input(hotkeys[first], hotkeys[second]) {
+                return true;
+            }
+            second += 1;
+        }
+        let mut chord = 0;
+        while chord < chords.len() {
+            if share_input(hotkeys[first], chords[chord].inputs) {
+                return true;
+            }
+            chord += 1;
+        }
+        first += 1;
+    }
+    false
+}
+
+const fn share_input(a: &[InputId], b: &[InputId]) -> bool {
+    let mut i = 0;
+    while i < a.len() {
+        let mut j = 0;
+        while j < b.len() {
+            let same = match (a[i], b[j]) {
+                (InputId::Matrix { row, col }, InputId::Matrix { row: r, col: c }) => {
+                    row == r && col == c
+                }
+                (InputId::Direct(pin), InputId::Direct(p)) => pin == p,
+                _ => false,
+            };
+            if same {
+                return true;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    false
+}
+
+fn release(layout: Layout, states: &mut InputStates, inputs: &[InputId]) {
+    for index in inputs.iter().filter_map(|input| layout.index(*input)) {
+        states.set(index, false);
+    }
+}
+
+/// Either axis of a stick pushed all the way along a diagonal, 32767 / √2
+pub const STICK_DIAGONAL: i16 = 23170;
+
+/// (x, y) of a stick from its pressed directions, y goes up
+///
+/// Diagonals stay on the circle a real stick is held in.
+fn stick_position(pressed: [bool; 4]) -> (i16, i16) {
+    let axis = |negative: bool, positive: bool| match (negative, positive) {
+        (true, false) => -1,
+        (false, true) => 1,
+        _ => 0,
+    };
+    let up = pressed[Direction::Up as usize];
+    let down = pressed[Direction::Down as usize];
+    let left = pressed[Direction::Left as usize];
+    let right = pressed[Direction::Right as usize];
+    let (x, y) = (axis(left, right), axis(down, up));
+    let full = |direction: i16| match direction {
+        -1 => i16::MIN,
+        1 => i16::MAX,
+        _ => 0,
+    };
+    if x != 0 && y != 0 {
+        (x * STICK_DIAGONAL, y * STICK_DIAGONAL)
+    } else {
+        (full(x), full(y))
+    }
+}
+
+/// Maps the buttons through the active profile
+pub struct Mapper {
+    layout: Layout,
+    profiles: &'static [Profile],
+    active: usize,
+    previous: InputStates,
+    dpad_mode: DpadMode,
+    turbo_hotkey: bool,
+    macro_hotkey: bool,
+    macro_pressed: Option<u8>,
+    /// The buttons pressed while the function key was held
+    layered: InputStates,
+    /// When the function key was pressed, `None` once it can't be a tap
+    tap_since: Option<Instant>,
+    /// The control of the last tap and when it was tapped
+    tapped: Option<(Control, Instant)>,
+    chords: Chords,
+    socd: SocdCleaner,
+    /// Of all the profiles
+    remaps: [Option<Remap>; MAX_REMAPS],
+    /// Those of the active profile, as bindings
+    remapped: [Binding; MAX_REMAPS],
+    remapped_len: usize,
+}
+
+impl Mapper {
+    /// The first profile is active at start.
+    pub fn new(layout: Layout, profiles: &'static [Profile]) -> Self {
+        assert!(!profiles.is_empty(), "no mapping profile");
+        Mapper {
+            layout,
+            profiles,
+            active: 0,
+            previous: InputStates::default(),
+            dpad_mode: DpadMode::Dpad,
+            turbo_hotkey: false,
+            macro_hotkey: false,
+            macro_pressed: None,
+            layered: InputStates::default(),
+            tap_since: None,
+            tapped: None,
+            chords: Chords::new(),
+            socd: SocdCleaner::new(),
+            remaps: [None; MAX_REMAPS],
+            remapped: [bind(InputId::Direct(0), Control::A); MAX_REMAPS],
+            remapped_len: 0,
+        }
+    }
+
+    pub fn profiles(&self) -> &'static [Profile] {
+        self.profiles
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn active_profile(&self) -> &'static Profile {
+        &self.profiles[self.active]
+    }
+
+    /// Activate profile number `index`, returns false if there is none.
+    pub fn select(&mut self, index: usize) -> bool {
+        if index < self.profiles.len() {
+            self.active = index;
+            info!("Mapping profile {}", self.profiles[index].name);
+            self.update_remapped();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Activate the profile called `name`, returns false if there is none.
+    pub fn select_by_name(&mut self, name: &str) -> bool {
+        match self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)
+        {
+            Some(index) => self.select(index),
+            None => false,
+        }
+    }
+
+    /// Activate the next profile, going back to the first one after the last
+    pub fn next_profile(&mut self) {
+        self.select((self.active + 1) % self.profiles.len());
+    }
+
+    /// Bind buttons to other controls than in their profile's table, the
+    /// remaps of the settings
+    pub fn set_remaps(&mut self, remaps: &[Option<Remap>; MAX_REMAPS]) {
+        self.remaps = *remaps;
+        self.update_remapped();
+    }
+
+    fn update_remapped(&mut self) {
+        self.remapped_len = 0;
+        let active = used_remaps(&self.remaps);
+        for remap in active.filter(|remap| remap.profile as usize == self.active) {
+            self.remapped[self.remapped_len] = bind(remap.input, remap.control);
+            self.remapped_len += 1;
+        }
+    }
+
+    pub fn dpad_mode(&self) -> DpadMode {
+        self.dpad_mode
+    }
+
+    /// Route the D-pad buttons, whatever the profile
+    pub fn set_dpad_mode(&mut self, mode: DpadMode) {
+        self.dpad_mode = mode;
+        info!("D-pad mode {}", mode);
+    }
+
+    /// Whether the turbo hotkey of the profile was held in the last update,
+    /// see [`crate::turbo`]
+    pub fn turbo_hotkey_held(&self) -> bool {
+        self.turbo_hotkey
+    }
+
+    /// Whether the macro hotkey of the profile was held in the last update
+    pub fn macro_hotkey_held(&self) -> bool {
+        self.macro_hotkey
+    }
+
+    /// The slot of the [`Control::Macro`] button pressed in the last update
+    pub fn macro_pressed(&self) -> Option<u8> {
+        self.macro_pressed
+    }
+
+    /// Whether the buttons go through the layer of the profile, the function
+    /// key being held
+    pub fn layer_held(&self) -> bool {
+        self.active_profile()
+            .layer
+            .is_some_and(|layer| is_pressed(self.layout, self.previous, layer.key))
+    }
+
+    /// The report for new button states read at `now`
+    ///
+    /// The buttons pressed while the function key is held go through its
+    /// layer until released. Pressing a [`Control::NextProfile`] button
+    /// switches profiles first, the buttons held at that time are read
+    /// through the new profile. Then completing the profile's D-pad hotkey
+    /// switches the D-pad mode. The buttons of a hotkey held don't reach the
+    /// report, the others go through the chords of the profile.
+    pub fn update(&mut self, states: InputStates, now: Instant) -> XinputControlReport {
+        let just_pressed = InputStates(states.0 & !self.previous.0);
+        let released = InputStates(self.previous.0 & !states.0);
+        self.previous = states;
+
+        let mut states = states;
+        let mut tap = None;
+        if let Some(layer) = self.active_profile().layer {
+            let key = |states| is_pressed(self.layout, states, layer.key);
+            if key(states) {
+                self.layered.0 |= just_pressed.0;
+            }
+            if key(just_pressed) {
+                self.tap_since = Some(now);
+            }
+            let mut others = just_pressed;
+            release(self.layout, &mut others, &[layer.key]);
+            if others != InputStates::default() {
+                // a button pressed meanwhile makes it a hold
+                self.tap_since = None;
+            }
+            if key(released) {
+                let since = self.tap_since.take();
+                if since.is_some_and(|since| now.saturating_duration_since(since) < TAP_TERM) {
+                    tap = layer.tap;
+                }
+            }
+            release(self.layout, &mut states, &[layer.key]);
+        }
+        self.layered.0 &= states.0;
+        let layered = self.layered;
+        let base = InputStates(states.0 & !layered.0);
+        if let Some(control) = tap {
+            debug!("Tapped {}", control);
+            self.tapped = Some((control, now));
+        }
+
+        let profile = self.active_profile();
+        let remapped = &self.remapped[..self.remapped_len];
+        let edges = profile
+            .controls(self.layout, just_pressed, layered, remapped)
+            .chain(tap);
+        let mut switch = false;
+        self.macro_pressed = None;
+        for control in edges {
+            match control {
+                Control::NextProfile => switch = true,
+                Control::Macro(slot) => self.macro_pressed = Some(slot),
+                _ => {}
+            }
+        }
+        if switch {
+            self.next_profile();
+        }
+
+        let profile = self.active_profile();
+        let mut masked = states;
+        if hotkey_held(self.layout, base, profile.dpad_hotkey) {
+            let completed = profile
+                .dpad_hotkey
+                .iter()
+                .any(|input| is_pressed(self.layout, just_pressed, *input));
+            if completed {
+                self.set_dpad_mode(self.dpad_mode.next());
+            }
+            release(self.layout, &mut masked, profile.dpad_hotkey);
+        }
+        self.turbo_hotkey = hotkey_held(self.layout, base, profile.turbo_hotkey);
+        if self.turbo_hotkey {
+            release(self.layout, &mut masked, profile.turbo_hotkey);
+        }
+        self.macro_hotkey = hotkey_held(self.layout, base, profile.macro_hotkey);
+        if self.macro_hotkey {
+            release(self.layout, &mut masked, profile.macro_hotkey);
+        }
+
+        let tapped = match self.tapped {
+            Some((control, at)) if now.saturating_duration_since(at) < TAP_PRESS => Some(control),
+            _ => {
+                self.tapped = None;
+                None
+            }
+        };
+        // the layer buttons aren't part of chords
+        let unlayered = InputStates(masked.0 & !layered.0);
+        let chorded = self
+            .chords
+            .update(self.layout, profile.chords, unlayered, now);
+        let masked = InputStates(chorded.0 | masked.0 & layered.0);
+        let active = self.chords.active();
+        let chorded = profile
+            .chords
+            .iter()
+            .enumerate()
+            .filter(move |(n, _)| active & 1 << n != 0)
+            .map(|(_, chord)| chord.control);
+        let remapped = &self.remapped[..self.remapped_len];
+        let controls = profile
+            .controls(self.layout, masked, layered, remapped)
+            .chain(tapped)
+            .chain(chorded);
+        profile.report_controls(controls, self.dpad_mode, &mut self.socd)
+    }
+}
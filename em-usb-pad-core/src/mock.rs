@@ -0,0 +1,623 @@
+//! An in-memory implementation of embassy-usb's `Driver`.
+//!
+//! It lets the whole USB stack, including the Xinput class, run on the host.
+//! The device side gets a [`MockDriver`] to build the usual `embassy_usb::Builder`,
+//! while the test plays the host through a shared [`MockState`]:
+//! - every endpoint allocation is recorded
+//! - control transfers are issued with [`MockState::control_in`]/[`MockState::control_out`]
+//! - packets written to IN endpoints are recorded, packets for OUT endpoints are queued
+//!
+//! Futures here never register a real waker, they wake themselves and wait to be
+//! polled again. Drive them with `embassy_futures::block_on`, which polls in a loop.
+
+extern crate std;
+
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
+use core::task::Poll;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use embassy_usb::driver::{
+    Bus, ControlPipe, Direction, Driver, Endpoint, EndpointAddress, EndpointAllocError,
+    EndpointError, EndpointIn, EndpointInfo, EndpointOut, EndpointType, Event, Unsupported,
+};
+
+// standard requests used during enumeration
+const REQUEST_SET_ADDRESS: u8 = 0x05;
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+
+pub const DESCRIPTOR_TYPE_DEVICE: u8 = 0x01;
+pub const DESCRIPTOR_TYPE_CONFIGURATION: u8 = 0x02;
+pub const DESCRIPTOR_TYPE_STRING: u8 = 0x03;
+pub const DESCRIPTOR_TYPE_BOS: u8 = 0x0F;
+
+/// Highest endpoint number the mock hands out, per direction.
+pub const MOCK_MAX_ENDPOINT_INDEX: usize = 15;
+
+/// The device answered a control transfer with a STALL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stalled;
+
+/// A SETUP packet, as sent by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl Setup {
+    pub fn new(request_type: u8, request: u8, value: u16, index: u16, length: u16) -> Self {
+        Setup {
+            request_type,
+            request,
+            value,
+            index,
+            length,
+        }
+    }
+
+    /// Standard GET_DESCRIPTOR request to the device.
+    pub fn get_descriptor(descriptor_type: u8, index: u8, lang_id: u16, length: u16) -> Self {
+        Setup::new(
+            0x80,
+            REQUEST_GET_DESCRIPTOR,
+            (descriptor_type as u16) << 8 | index as u16,
+            lang_id,
+            length,
+        )
+    }
+
+    /// From the 8 bytes of a captured packet.
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Setup::new(
+            bytes[0],
+            bytes[1],
+            u16::from_le_bytes([bytes[2], bytes[3]]),
+            u16::from_le_bytes([bytes[4], bytes[5]]),
+            u16::from_le_bytes([bytes[6], bytes[7]]),
+        )
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let value = self.value.to_le_bytes();
+        let index = self.index.to_le_bytes();
+        let length = self.length.to_le_bytes();
+        [
+            self.request_type,
+            self.request,
+            value[0],
+            value[1],
+            index[0],
+            index[1],
+            length[0],
+            length[1],
+        ]
+    }
+}
+
+/// One finished control transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlRecord {
+    pub setup: Setup,
+    /// Data stage, whichever direction it went.
+    pub data: Vec<u8>,
+    pub result: Result<(), Stalled>,
+}
+
+/// Descriptors collected by [`MockState::enumerate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Descriptors {
+    pub device: Vec<u8>,
+    pub configuration: Vec<u8>,
+}
+
+struct PendingControl {
+    setup: Setup,
+    out_data: Vec<u8>,
+    out_offset: usize,
+    in_data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Inner {
+    allocations: Vec<EndpointInfo>,
+    started: bool,
+    enabled: bool,
+    address: u8,
+    enabled_endpoints: Vec<u8>,
+    stalled_endpoints: Vec<u8>,
+
+    bus_events: VecDeque<Event>,
+
+    // control transfers waiting for the device to pick them up
+    control_issued: usize,
+    setups: VecDeque<PendingControl>,
+    // the transfer the device is working on
+    current: Option<PendingControl>,
+    control_log: Vec<ControlRecord>,
+
+    // packets from the host, waiting to be read by the device
+    out_packets: VecDeque<(u8, Vec<u8>)>,
+    // packets written by the device, not yet taken by the host
+    in_packets: VecDeque<(u8, Vec<u8>)>,
+}
+
+/// The host side of the mock bus, shared by the driver and all its endpoints.
+pub struct MockState {
+    inner: RefCell<Inner>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockState {
+    pub fn new() -> Self {
+        MockState {
+            inner: RefCell::new(Inner::default()),
+        }
+    }
+
+    /// All endpoints allocated by the device, in allocation order.
+    pub fn allocations(&self) -> Vec<EndpointInfo> {
+        self.inner.borrow().allocations.clone()
+    }
+
+    /// Whether `Driver::start` has been called, i.e. the builder has been built.
+    pub fn is_started(&self) -> bool {
+        self.inner.borrow().started
+    }
+
+    /// The address assigned by the last SET_ADDRESS.
+    pub fn address(&self) -> u8 {
+        self.inner.borrow().address
+    }
+
+    pub fn is_endpoint_enabled(&self, addr: u8) -> bool {
+        self.inner.borrow().enabled_endpoints.contains(&addr)
+    }
+
+    pub fn is_endpoint_stalled(&self, addr: u8) -> bool {
+        self.inner.borrow().stalled_endpoints.contains(&addr)
+    }
+
+    /// Queue a bus event, to be returned by `Bus::poll`.
+    pub fn bus_event(&self, event: Event) {
+        self.inner.borrow_mut().bus_events.push_back(event);
+    }
+
+    /// Every control transfer completed so far.
+    pub fn control_log(&self) -> Vec<ControlRecord> {
+        self.inner.borrow().control_log.clone()
+    }
+
+    /// Run a control transfer without data stage or with an OUT data stage.
+    pub async fn control_out(&self, setup: Setup, data: &[u8]) -> Result<(), Stalled> {
+        assert_eq!(setup.request_type & 0x80, 0, "not an OUT request");
+        assert_eq!(setup.length as usize, data.len());
+        self.control(setup, data).await.map(|_| ())
+    }
+
+    /// Run a control transfer with an IN data stage, returning what the device sent.
+    pub async fn control_in(&self, setup: Setup) -> Result<Vec<u8>, Stalled> {
+        assert_ne!(setup.request_type & 0x80, 0, "not an IN request");
+        self.control(setup, &[]).await
+    }
+
+    async fn control(&self, setup: Setup, data: &[u8]) -> Result<Vec<u8>, Stalled> {
+        // transfers are handled in order, so this one ends up at that position in the log
+        let position = {
+            let mut inner = self.inner.borrow_mut();
+            inner.setups.push_back(PendingControl {
+                setup,
+                out_data: data.to_vec(),
+                out_offset: 0,
+                in_data: Vec::new(),
+            });
+            let position = inner.control_issued;
+            inner.control_issued += 1;
+            position
+        };
+        let record = wait_for(|| self.inner.borrow().control_log.get(position).cloned()).await;
+        record.result.map(|_| record.data)
+    }
+
+    /// Convenient GET_DESCRIPTOR.
+    pub async fn get_descriptor(
+        &self,
+        descriptor_type: u8,
+        index: u8,
+        lang_id: u16,
+        length: u16,
+    ) -> Result<Vec<u8>, Stalled> {
+        self.control_in(Setup::get_descriptor(
+            descriptor_type,
+            index,
+            lang_id,
+            length,
+        ))
+        .await
+    }
+
+    /// Fetch a string descriptor and decode it, `None` if the device stalls.
+    pub async fn get_string(&self, index: u8, lang_id: u16) -> Option<std::string::String> {
+        let desc = self
+            .get_descriptor(DESCRIPTOR_TYPE_STRING, index, lang_id, 0xff)
+            .await
+            .ok()?;
+        let units: Vec<u16> = desc[2..]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        std::string::String::from_utf16(&units).ok()
+    }
+
+    /// Power up, reset and configure the device like a host would do.
+    ///
+    /// Returns the device and configuration descriptors read on the way.
+    pub async fn enumerate(&self) -> Result<Descriptors, Stalled> {
+        self.bus_event(Event::PowerDetected);
+        self.bus_event(Event::Reset);
+
+        // like Windows does, peek at the device descriptor before addressing the device,
+        // only the full read afterwards is reliable
+        self.get_descriptor(DESCRIPTOR_TYPE_DEVICE, 0, 0, 64)
+            .await?;
+        self.control_out(Setup::new(0x00, REQUEST_SET_ADDRESS, 1, 0, 0), &[])
+            .await?;
+        let device = self
+            .get_descriptor(DESCRIPTOR_TYPE_DEVICE, 0, 0, 18)
+            .await?;
+
+        // read the header first to know the total length
+        let header = self
+            .get_descriptor(DESCRIPTOR_TYPE_CONFIGURATION, 0, 0, 9)
+            .await?;
+        let total_length = u16::from_le_bytes([header[2], header[3]]);
+        let configuration = self
+            .get_descriptor(DESCRIPTOR_TYPE_CONFIGURATION, 0, 0, total_length)
+            .await?;
+
+        let configuration_value = configuration[5] as u16;
+        self.control_out(
+            Setup::new(0x00, REQUEST_SET_CONFIGURATION, configuration_value, 0, 0),
+            &[],
+        )
+        .await?;
+
+        Ok(Descriptors {
+            device,
+            configuration,
+        })
+    }
+
+    /// Send a packet from the host to an OUT endpoint.
+    pub fn send_out(&self, addr: u8, data: &[u8]) {
+        assert_eq!(addr & 0x80, 0, "not an OUT endpoint");
+        self.inner
+            .borrow_mut()
+            .out_packets
+            .push_back((addr, data.to_vec()));
+    }
+
+    /// Whether all packets sent to `addr` have been read by the device.
+    pub fn out_drained(&self, addr: u8) -> bool {
+        !self
+            .inner
+            .borrow()
+            .out_packets
+            .iter()
+            .any(|(a, _)| *a == addr)
+    }
+
+    /// Take the oldest packet written by the device to the IN endpoint `addr`.
+    pub fn take_in(&self, addr: u8) -> Option<Vec<u8>> {
+        let mut inner = self.inner.borrow_mut();
+        let position = inner.in_packets.iter().position(|(a, _)| *a == addr)?;
+        inner.in_packets.remove(position).map(|(_, data)| data)
+    }
+
+    /// Wait for the device to write a packet to the IN endpoint `addr`.
+    pub async fn read_in(&self, addr: u8) -> Vec<u8> {
+        wait_for(|| self.take_in(addr)).await
+    }
+}
+
+/// Resolve once `f` returns something, yielding to the executor between tries.
+fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> impl Future<Output = T> {
+    poll_fn(move |cx| match f() {
+        Some(value) => Poll::Ready(value),
+        None => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+}
+
+pub struct MockDriver<'a> {
+    state: &'a MockState,
+    next_in: usize,
+    next_out: usize,
+}
+
+impl<'a> MockDriver<'a> {
+    pub fn new(state: &'a MockState) -> Self {
+        MockDriver {
+            state,
+            next_in: 1,
+            next_out: 1,
+        }
+    }
+
+    fn alloc(
+        &mut self,
+        direction: Direction,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<MockEndpoint<'a>, EndpointAllocError> {
+        let next = match direction {
+            Direction::In => &mut self.next_in,
+            Direction::Out => &mut self.next_out,
+        };
+        if *next > MOCK_MAX_ENDPOINT_INDEX {
+            return Err(EndpointAllocError);
+        }
+        let info = EndpointInfo {
+            addr: EndpointAddress::from_parts(*next, direction),
+            ep_type,
+            max_packet_size,
+            interval_ms,
+        };
+        *next += 1;
+        self.state.inner.borrow_mut().allocations.push(info);
+        Ok(MockEndpoint {
+            state: self.state,
+            info,
+        })
+    }
+}
+
+impl<'a> Driver<'a> for MockDriver<'a> {
+    type EndpointOut = MockEndpoint<'a>;
+    type EndpointIn = MockEndpoint<'a>;
+    type ControlPipe = MockControlPipe<'a>;
+    type Bus = MockBus<'a>;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.alloc(Direction::Out, ep_type, max_packet_size, interval_ms)
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.alloc(Direction::In, ep_type, max_packet_size, interval_ms)
+    }
+
+    fn start(self, control_max_packet_size: u16) -> (Self::Bus, Self::ControlPipe) {
+        self.state.inner.borrow_mut().started = true;
+        (
+            MockBus { state: self.state },
+            MockControlPipe {
+                state: self.state,
+                max_packet_size: control_max_packet_size as usize,
+            },
+        )
+    }
+}
+
+pub struct MockBus<'a> {
+    state: &'a MockState,
+}
+
+impl<'a> Bus for MockBus<'a> {
+    async fn enable(&mut self) {
+        self.state.inner.borrow_mut().enabled = true;
+    }
+
+    async fn disable(&mut self) {
+        let mut inner = self.state.inner.borrow_mut();
+        inner.enabled = false;
+        inner.enabled_endpoints.clear();
+    }
+
+    async fn poll(&mut self) -> Event {
+        wait_for(|| self.state.inner.borrow_mut().bus_events.pop_front()).await
+    }
+
+    fn endpoint_set_enabled(&mut self, ep_addr: EndpointAddress, enabled: bool) {
+        let addr = u8::from(ep_addr);
+        let mut inner = self.state.inner.borrow_mut();
+        inner.enabled_endpoints.retain(|a| *a != addr);
+        if enabled {
+            inner.enabled_endpoints.push(addr);
+        }
+    }
+
+    fn endpoint_set_stalled(&mut self, ep_addr: EndpointAddress, stalled: bool) {
+        let addr = u8::from(ep_addr);
+        let mut inner = self.state.inner.borrow_mut();
+        inner.stalled_endpoints.retain(|a| *a != addr);
+        if stalled {
+            inner.stalled_endpoints.push(addr);
+        }
+    }
+
+    fn endpoint_is_stalled(&mut self, ep_addr: EndpointAddress) -> bool {
+        self.state.is_endpoint_stalled(ep_addr.into())
+    }
+
+    async fn remote_wakeup(&mut self) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+pub struct MockEndpoint<'a> {
+    state: &'a MockState,
+    info: EndpointInfo,
+}
+
+impl<'a> MockEndpoint<'a> {
+    fn addr(&self) -> u8 {
+        self.info.addr.into()
+    }
+}
+
+impl<'a> Endpoint for MockEndpoint<'a> {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {
+        let addr = self.addr();
+        wait_for(|| self.state.is_endpoint_enabled(addr).then_some(())).await
+    }
+}
+
+impl<'a> EndpointOut for MockEndpoint<'a> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        let addr = self.addr();
+        let data = wait_for(|| {
+            if !self.state.is_endpoint_enabled(addr) {
+                return Some(Err(EndpointError::Disabled));
+            }
+            let mut inner = self.state.inner.borrow_mut();
+            let position = inner.out_packets.iter().position(|(a, _)| *a == addr)?;
+            inner.out_packets.remove(position).map(|(_, data)| Ok(data))
+        })
+        .await?;
+        if data.len() > buf.len() || data.len() > self.info.max_packet_size as usize {
+            return Err(EndpointError::BufferOverflow);
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl<'a> EndpointIn for MockEndpoint<'a> {
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        let addr = self.addr();
+        if !self.state.is_endpoint_enabled(addr) {
+            return Err(EndpointError::Disabled);
+        }
+        if buf.len() > self.info.max_packet_size as usize {
+            return Err(EndpointError::BufferOverflow);
+        }
+        self.state
+            .inner
+            .borrow_mut()
+            .in_packets
+            .push_back((addr, buf.to_vec()));
+        Ok(())
+    }
+}
+
+pub struct MockControlPipe<'a> {
+    state: &'a MockState,
+    max_packet_size: usize,
+}
+
+impl<'a> MockControlPipe<'a> {
+    fn finish(&mut self, result: Result<(), Stalled>) {
+        let mut inner = self.state.inner.borrow_mut();
+        if let Some(pending) = inner.current.take() {
+            let data = if pending.setup.request_type & 0x80 != 0 {
+                pending.in_data
+            } else {
+                pending.out_data
+            };
+            let data = if result.is_ok() { data } else { Vec::new() };
+            inner.control_log.push(ControlRecord {
+                setup: pending.setup,
+                data,
+                result,
+            });
+        }
+    }
+}
+
+impl<'a> ControlPipe for MockControlPipe<'a> {
+    fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    async fn setup(&mut self) -> [u8; 8] {
+        wait_for(|| {
+            let mut inner = self.state.inner.borrow_mut();
+            if !inner.enabled {
+                return None;
+            }
+            let pending = inner.setups.pop_front()?;
+            let setup = pending.setup.to_bytes();
+            inner.current = Some(pending);
+            Some(setup)
+        })
+        .await
+    }
+
+    async fn data_out(
+        &mut self,
+        buf: &mut [u8],
+        _first: bool,
+        _last: bool,
+    ) -> Result<usize, EndpointError> {
+        let mut inner = self.state.inner.borrow_mut();
+        let pending = inner.current.as_mut().ok_or(EndpointError::Disabled)?;
+        let remaining = &pending.out_data[pending.out_offset..];
+        let size = remaining.len().min(self.max_packet_size);
+        if size > buf.len() {
+            return Err(EndpointError::BufferOverflow);
+        }
+        buf[..size].copy_from_slice(&remaining[..size]);
+        pending.out_offset += size;
+        Ok(size)
+    }
+
+    async fn data_in(
+        &mut self,
+        data: &[u8],
+        _first: bool,
+        last: bool,
+    ) -> Result<(), EndpointError> {
+        if data.len() > self.max_packet_size {
+            return Err(EndpointError::BufferOverflow);
+        }
+        {
+            let mut inner = self.state.inner.borrow_mut();
+            let pending = inner.current.as_mut().ok_or(EndpointError::Disabled)?;
+            pending.in_data.extend_from_slice(data);
+        }
+        if last {
+            self.finish(Ok(()));
+        }
+        Ok(())
+    }
+
+    async fn accept(&mut self) {
+        self.finish(Ok(()));
+    }
+
+    async fn reject(&mut self) {
+        self.finish(Err(Stalled));
+    }
+
+    async fn accept_set_address(&mut self, addr: u8) {
+        self.state.inner.borrow_mut().address = addr;
+        self.finish(Ok(()));
+    }
+}
@@ -0,0 +1,100 @@
+//! Microsoft OS 1.0 descriptors, so Windows binds its Xinput driver to the
+//! pad whatever its VID and PID.
+//!
+//! The first time Windows sees a VID, PID and release, it asks for string
+//! descriptor 0xEE. A device answering "MSFT100" and a vendor request code is
+//! then asked, with that request code, for its extended compat ID descriptor:
+//! ```text
+//! bmRequestType  0xc0: vendor, to the device
+//! bRequest       the vendor code
+//! wValue         0
+//! wIndex         4
+//! ```
+//! The compatible ID "XUSB10" given to the control interface picks the
+//! Xinput driver. Windows keeps the answer, under
+//! `HKLM\SYSTEM\CurrentControlSet\Control\usbflags`, delete it there to be
+//! asked again.
+//!
+//! The MS OS 2.0 descriptors would go to the BOS descriptor, which
+//! embassy-usb writes on its own with no room for them.
+
+use core::str;
+
+use embassy_usb::control::{Recipient, Request, RequestType};
+
+/// Index of the string descriptor Windows asks for
+pub const MS_OS_STRING_INDEX: u8 = 0xee;
+
+/// The vendor request code of a genuine Xbox 360 controller
+pub const DEFAULT_VENDOR_CODE: u8 = 0x90;
+
+/// The compatible ID of the Xinput driver, padded with NULs
+pub const XUSB_COMPATIBLE_ID: [u8; 8] = *b"XUSB10\0\0";
+
+/// wIndex of the request for the extended compat ID descriptor
+const COMPAT_ID_INDEX: u16 = 4;
+
+/// Header and a function section
+pub const COMPAT_ID_SIZE: usize = 16 + 24;
+
+/// qwSignature of the string
+const SIGNATURE: &str = "MSFT100";
+
+/// The answers for one vendor code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MsOsDescriptors {
+    vendor_code: u8,
+    /// The signature then the vendor code as a character, it encodes to
+    /// UTF-16 as bMS_VendorCode and bPad
+    string: [u8; SIGNATURE.len() + 2],
+    string_len: usize,
+}
+
+impl MsOsDescriptors {
+    pub fn new(vendor_code: u8) -> Self {
+        let mut string = [0; SIGNATURE.len() + 2];
+        string[..SIGNATURE.len()].copy_from_slice(SIGNATURE.as_bytes());
+        let code = char::from(vendor_code).encode_utf8(&mut string[SIGNATURE.len()..]);
+        let string_len = SIGNATURE.len() + code.len();
+        MsOsDescriptors {
+            vendor_code,
+            string,
+            string_len,
+        }
+    }
+
+    pub fn vendor_code(&self) -> u8 {
+        self.vendor_code
+    }
+
+    /// String descriptor 0xEE
+    pub fn string(&self) -> &str {
+        str::from_utf8(&self.string[..self.string_len]).unwrap_or(SIGNATURE)
+    }
+
+    /// The extended compat ID descriptor, giving `interface` the Xinput
+    /// driver
+    pub fn compat_id(&self, interface: u8) -> [u8; COMPAT_ID_SIZE] {
+        let mut descriptor = [0; COMPAT_ID_SIZE];
+        // header: dwLength, bcdVersion 1.00, wIndex, bCount, reserved
+        descriptor[..4].copy_from_slice(&(COMPAT_ID_SIZE as u32).to_le_bytes());
+        descriptor[4..6].copy_from_slice(&0x0100u16.to_le_bytes());
+        descriptor[6..8].copy_from_slice(&COMPAT_ID_INDEX.to_le_bytes());
+        descriptor[8] = 1;
+        // function: bFirstInterfaceNumber, reserved as 1, compatibleID,
+        // subCompatibleID and reserved left 0
+        descriptor[16] = interface;
+        descriptor[17] = 1;
+        descriptor[18..26].copy_from_slice(&XUSB_COMPATIBLE_ID);
+        descriptor
+    }
+
+    /// Whether `req` asks for the extended compat ID descriptor
+    pub fn is_compat_id_request(&self, req: &Request) -> bool {
+        req.request_type == RequestType::Vendor
+            && req.recipient == Recipient::Device
+            && req.request == self.vendor_code
+            && req.index == COMPAT_ID_INDEX
+    }
+}
@@ -0,0 +1,99 @@
+//! Configuring the pad from the host through vendor control requests.
+//!
+//! The requests and their encoding are in `em-usb-pad-protocol`, shared with
+//! the host tools and re-exported here. This side answers them for the
+//! firmware, through a [`ConfigHandler`].
+
+use embassy_usb::control::{Recipient, Request, RequestType};
+use embassy_usb::types::InterfaceNumber;
+
+use crate::mapping::Profile;
+use crate::settings::{Settings, SETTINGS_VERSION};
+
+pub use em_usb_pad_protocol::config::*;
+
+/// Whether `req` is a configuration request to `interface`
+pub fn is_config_request(req: &Request, interface: InterfaceNumber) -> bool {
+    req.request_type == RequestType::Vendor
+        && req.recipient == Recipient::Interface
+        && req.request == CONFIG_REQUEST
+        && req.index as u8 == u8::from(interface)
+}
+
+/// What the firmware does for the configuration requests.
+///
+/// Called from the USB stack, it can't wait.
+pub trait ConfigHandler {
+    /// The profiles of the board, the settings pick one
+    fn profiles(&self) -> &'static [Profile];
+
+    /// The settings in use
+    fn settings(&self) -> Settings;
+
+    /// Use new settings, `false` if they aren't valid.
+    fn set_settings(&self, settings: Settings) -> bool;
+
+    /// Save the settings, `false` if it can't be done now.
+    fn save(&self) -> bool;
+}
+
+/// Answer an IN configuration request into `buf`, returns the length.
+///
+/// `None` to reject it.
+pub fn handle_in(handler: &dyn ConfigHandler, req: &Request, buf: &mut [u8]) -> Option<usize> {
+    let request = match ConfigRequest::decode(true, req.value, req.index, &[]) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Config request rejected: {:?}", e);
+            return None;
+        }
+    };
+    debug!("Config request {:?}", request);
+    let mut data = [0; CONFIG_MAX_DATA];
+    let len = match request {
+        ConfigRequest::Info => {
+            let info = ConfigInfo {
+                protocol: PROTOCOL_VERSION,
+                settings_version: SETTINGS_VERSION,
+                profiles: handler.profiles().len() as u8,
+            };
+            data[..ConfigInfo::SIZE].copy_from_slice(&info.encode());
+            ConfigInfo::SIZE
+        }
+        ConfigRequest::GetSettings => encode_settings(&handler.settings(), &mut data),
+        ConfigRequest::GetProfile(index) => {
+            let profile = handler.profiles().get(index as usize)?;
+            let layer = profile.layer.map(|layer| (layer.key, layer.bindings));
+            encode_profile(profile.name, profile.bindings, layer, &mut data)?
+        }
+        _ => return None,
+    };
+    // the host may ask for less
+    let len = len.min(buf.len()).min(req.length as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    Some(len)
+}
+
+/// Carry out an OUT configuration request, `false` to reject it.
+pub fn handle_out(handler: &dyn ConfigHandler, req: &Request, data: &[u8]) -> bool {
+    let request = match ConfigRequest::decode(false, req.value, req.index, data) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Config request rejected: {:?}", e);
+            return false;
+        }
+    };
+    debug!("Config request {:?}", request);
+    match request {
+        ConfigRequest::SetSettings(settings) => handler.set_settings(settings),
+        ConfigRequest::SelectProfile(profile) => {
+            let settings = Settings {
+                profile,
+                ..handler.settings()
+            };
+            handler.set_settings(settings)
+        }
+        ConfigRequest::Save => handler.save(),
+        _ => false,
+    }
+}
@@ -0,0 +1,200 @@
+//! Rumble motors.
+//!
+//! The host sends a speed for each motor, the left one being the heavy,
+//! low-frequency motor and the right one the light, high-frequency motor.
+//! [`Rumble`] shapes the speeds with a per-motor curve and maximum, hands them
+//! to a [`RumbleOutput`] (e.g. two PWM channels), and stops the motors when
+//! the bus is suspended, reset or deconfigured, see [`Rumble::stop`].
+//!
+//! The host only sends a speed when it changes, a game may set a rumble and
+//! keep it for long. [`RumbleConfig::timeout`] can stop the motors anyway
+//! when the host sends nothing at all for a while, it is off by default.
+
+use embassy_time::{Duration, Instant};
+
+use crate::xinput::XinputRumbleState;
+
+/// Where the motor speeds go, e.g. two PWM channels
+pub trait RumbleOutput {
+    /// Set both motors, 0 stops a motor and 255 is full speed.
+    fn set_motors(&mut self, left: u8, right: u8);
+}
+
+/// Points of a lookup curve, evenly spaced from 0 to 255
+pub const RUMBLE_CURVE_POINTS: usize = 17;
+
+/// How the speed requested by the host maps to the motor speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RumbleCurve {
+    /// As requested
+    Linear,
+    /// Softer at low speeds
+    Quadratic,
+    /// Stronger at low speeds, helps small motors that won't start slowly
+    SquareRoot,
+    /// Custom curve, interpolated linearly between the points
+    Table([u8; RUMBLE_CURVE_POINTS]),
+}
+
+impl RumbleCurve {
+    pub fn apply(&self, value: u8) -> u8 {
+        let value = value as u32;
+        match self {
+            RumbleCurve::Linear => value as u8,
+            RumbleCurve::Quadratic => (value * value / 255) as u8,
+            RumbleCurve::SquareRoot => isqrt(value * 255) as u8,
+            RumbleCurve::Table(points) => {
+                // 16 segments of 16 values, the last one ends at 255 instead of 256
+                let segment = (value / 16) as usize;
+                let (start, end) = (points[segment] as i32, points[segment + 1] as i32);
+                let width = if segment == RUMBLE_CURVE_POINTS - 2 {
+                    15
+                } else {
+                    16
+                };
+                let offset = (value % 16) as i32;
+                (start + (end - start) * offset / width) as u8
+            }
+        }
+    }
+}
+
+fn isqrt(value: u32) -> u32 {
+    let mut root = 0;
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    root
+}
+
+/// Shaping of one motor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotorConfig {
+    pub curve: RumbleCurve,
+    /// Speed reached when the host asks for full speed
+    pub max: u8,
+}
+
+impl Default for MotorConfig {
+    fn default() -> Self {
+        MotorConfig {
+            curve: RumbleCurve::Linear,
+            max: u8::MAX,
+        }
+    }
+}
+
+impl MotorConfig {
+    pub fn apply(&self, value: u8) -> u8 {
+        (self.curve.apply(value) as u32 * self.max as u32 / 255) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RumbleConfig {
+    /// The heavy, low-frequency motor
+    pub left: MotorConfig,
+    /// The light, high-frequency motor
+    pub right: MotorConfig,
+    /// Stop the motors after this long without any message from the host.
+    ///
+    /// Games may set a rumble once and expect it to last, and the host
+    /// sends nothing periodically, so only for hosts known to repeat their
+    /// messages. `None`, the default, lets the rumble last.
+    pub timeout: Option<Duration>,
+}
+
+/// Drives the motors from the host requests
+pub struct Rumble<O: RumbleOutput> {
+    output: O,
+    config: RumbleConfig,
+    requested: XinputRumbleState,
+    last_message: Option<Instant>,
+    suspended: bool,
+    // what the output is currently set to
+    running: (u8, u8),
+}
+
+impl<O: RumbleOutput> Rumble<O> {
+    /// The motors are stopped at start.
+    pub fn new(mut output: O, config: RumbleConfig) -> Self {
+        output.set_motors(0, 0);
+        Rumble {
+            output,
+            config,
+            requested: XinputRumbleState::default(),
+            last_message: None,
+            suspended: false,
+            running: (0, 0),
+        }
+    }
+
+    pub fn config(&self) -> &RumbleConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: RumbleConfig) {
+        self.config = config;
+        self.update();
+    }
+
+    /// The speeds the motors are currently set to
+    pub fn running(&self) -> (u8, u8) {
+        self.running
+    }
+
+    /// The host asks for new motor speeds.
+    pub fn set(&mut self, requested: XinputRumbleState, now: Instant) {
+        self.requested = requested;
+        self.last_message = Some(now);
+        self.update();
+    }
+
+    /// Any other message from the host, it is still alive.
+    pub fn keep_alive(&mut self, now: Instant) {
+        self.last_message = Some(now);
+    }
+
+    /// The USB bus is suspended or resumed.
+    ///
+    /// The request is forgotten, the host sends a new one if needed.
+    pub fn set_suspended(&mut self, suspended: bool) {
+        self.suspended = suspended;
+        self.requested = XinputRumbleState::default();
+        self.update();
+    }
+
+    /// Call periodically to apply the timeout.
+    pub fn tick(&mut self, now: Instant) {
+        if let (Some(timeout), Some(last)) = (self.config.timeout, self.last_message) {
+            if now.saturating_duration_since(last) >= timeout
+                && self.requested != Default::default()
+            {
+                self.requested = XinputRumbleState::default();
+                self.update();
+            }
+        }
+    }
+
+    /// Stop the motors, until the next request, e.g. when the bus is reset
+    /// or the device deconfigured.
+    pub fn stop(&mut self) {
+        self.requested = XinputRumbleState::default();
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let target = if self.suspended {
+            (0, 0)
+        } else {
+            (
+                self.config.left.apply(self.requested.left),
+                self.config.right.apply(self.requested.right),
+            )
+        };
+        if target != self.running {
+            self.running = target;
+            self.output.set_motors(target.0, target.1);
+        }
+    }
+}
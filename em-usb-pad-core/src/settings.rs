@@ -0,0 +1,9 @@
+//! What the user can change at runtime, kept across power cycles.
+//!
+//! The mappings are compiled in, [`Settings`] only picks among them and
+//! overrides a few values. It is stored as a small versioned binary blob, see
+//! [`crate::store`], and sent to the host in the same form, see
+//! [`crate::protocol`]. The encoding lives in `em-usb-pad-protocol`, shared
+//! with the host tools.
+
+pub use em_usb_pad_protocol::settings::*;
@@ -0,0 +1,259 @@
+//! Stick and trigger deadzones and response curves.
+//!
+//! Sits between the calibrated stick position and the report. The distance
+//! from the centre, per axis or as a radius, goes through:
+//! 1. the inner deadzone and the outer saturation, the range in between is
+//!    stretched to the full range,
+//! 2. the response curve,
+//! 3. the anti-deadzone, jumping over the deadzone the game adds on its own.
+//!
+//! Everything is integer math on magnitudes from 0 to [`FULL`].
+
+use crate::settings::StickDeadzone;
+
+/// Full deflection
+pub const FULL: i32 = i16::MAX as i32;
+
+/// Points of a lookup curve, evenly spaced from 0 to [`FULL`]
+pub const SHAPING_CURVE_POINTS: usize = 17;
+
+/// How the deadzone is shaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Deadzone {
+    /// Each axis on its own, a cross-shaped deadzone that helps moving
+    /// straight but snaps diagonals to the axes near the centre
+    Axial,
+    /// Around the centre, the output jumps from 0 to the inner radius
+    Radial,
+    /// Around the centre, stretched so the output starts from 0
+    ScaledRadial,
+}
+
+/// How the magnitude past the deadzone maps to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseCurve {
+    Linear,
+    /// Blend of linear and cubic, 0 is linear and 100 fully cubic, finer
+    /// control near the centre
+    Exponential(u8),
+    /// Custom curve, interpolated linearly between the points
+    Table([u16; SHAPING_CURVE_POINTS]),
+}
+
+impl ResponseCurve {
+    /// `magnitude` from 0 to [`FULL`]
+    pub fn apply(&self, magnitude: i32) -> i32 {
+        let magnitude = magnitude.clamp(0, FULL);
+        match *self {
+            ResponseCurve::Linear => magnitude,
+            ResponseCurve::Exponential(amount) => {
+                let amount = amount.min(100) as i64;
+                let m = magnitude as i64;
+                let cubic = m * m / FULL as i64 * m / FULL as i64;
+                (((100 - amount) * m + amount * cubic) / 100) as i32
+            }
+            ResponseCurve::Table(points) => {
+                // 16 segments of 2048, the last one is one shorter
+                let segment = (magnitude / 2048) as usize;
+                if segment >= SHAPING_CURVE_POINTS - 1 {
+                    return points[SHAPING_CURVE_POINTS - 1] as i32;
+                }
+                let (start, end) = (points[segment] as i32, points[segment + 1] as i32);
+                let width = if segment == SHAPING_CURVE_POINTS - 2 {
+                    2047
+                } else {
+                    2048
+                };
+                start + (end - start) * (magnitude % 2048) / width
+            }
+        }
+        .min(FULL)
+    }
+}
+
+/// Shaping of one stick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StickShaping {
+    pub deadzone: Deadzone,
+    /// Magnitude reading 0
+    pub inner: u16,
+    /// Magnitude reading full deflection, sticks often can't reach the ends
+    pub outer: u16,
+    /// Smallest magnitude sent once out of the deadzone
+    pub anti_deadzone: u16,
+    pub curve: ResponseCurve,
+}
+
+impl Default for StickShaping {
+    fn default() -> Self {
+        StickShaping::NONE
+    }
+}
+
+impl StickShaping {
+    /// The stick as calibrated, corners included
+    pub const NONE: StickShaping = StickShaping {
+        deadzone: Deadzone::Axial,
+        inner: 0,
+        outer: i16::MAX as u16,
+        anti_deadzone: 0,
+        curve: ResponseCurve::Linear,
+    };
+
+    /// Shape the (x, y) position of the stick.
+    pub fn apply(&self, (x, y): (i16, i16)) -> (i16, i16) {
+        let (x, y) = ((x as i32).max(-FULL), (y as i32).max(-FULL));
+        match self.deadzone {
+            Deadzone::Axial => (self.axis(x), self.axis(y)),
+            Deadzone::Radial | Deadzone::ScaledRadial => {
+                let radius = isqrt((x * x + y * y) as u32) as i32;
+                if radius <= self.inner as i32 || radius == 0 {
+                    return (0, 0);
+                }
+                let start = match self.deadzone {
+                    Deadzone::ScaledRadial => self.inner as i32,
+                    _ => 0,
+                };
+                let shaped = self.magnitude(radius, start);
+                (
+                    clamp_axis(x * shaped / radius),
+                    clamp_axis(y * shaped / radius),
+                )
+            }
+        }
+    }
+
+    fn axis(&self, value: i32) -> i16 {
+        let magnitude = value.abs();
+        if magnitude <= self.inner as i32 {
+            return 0;
+        }
+        let shaped = self.magnitude(magnitude, self.inner as i32);
+        clamp_axis(if value < 0 { -shaped } else { shaped })
+    }
+
+    /// Stretch `magnitude` from `start..outer` to the full range, then shape it
+    fn magnitude(&self, magnitude: i32, start: i32) -> i32 {
+        let start = start.min(FULL - 1);
+        let outer = (self.outer as i32).clamp(start + 1, FULL);
+        let stretched = ((magnitude - start) * FULL / (outer - start)).clamp(0, FULL);
+        let curved = self.curve.apply(stretched);
+        if curved == 0 {
+            return 0;
+        }
+        let anti = (self.anti_deadzone as i32).min(FULL);
+        anti + curved * (FULL - anti) / FULL
+    }
+}
+
+fn clamp_axis(value: i32) -> i16 {
+    value.clamp(-FULL, FULL) as i16
+}
+
+fn isqrt(value: u32) -> u32 {
+    // Newton's method, value is at most 2 * 32767²
+    if value < 2 {
+        return value;
+    }
+    // start above the root, then go down to it
+    let mut root = value / 2 + 1;
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+/// Shaping of both sticks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SticksShaping {
+    pub left: StickShaping,
+    pub right: StickShaping,
+}
+
+impl SticksShaping {
+    pub const NONE: SticksShaping = SticksShaping {
+        left: StickShaping::NONE,
+        right: StickShaping::NONE,
+    };
+
+    /// The same with the deadzones of the settings, left and right, in place
+    /// of those they set
+    pub fn with_deadzones(&self, deadzones: [Option<StickDeadzone>; 2]) -> SticksShaping {
+        let mut shaping = *self;
+        for (stick, deadzone) in [&mut shaping.left, &mut shaping.right]
+            .into_iter()
+            .zip(deadzones)
+        {
+            if let Some(deadzone) = deadzone {
+                stick.inner = deadzone.inner;
+                stick.outer = deadzone.outer;
+            }
+        }
+        shaping
+    }
+}
+
+/// Shaping of one analog trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerShaping {
+    /// Travel reading 0
+    pub deadzone: u8,
+    /// Travel reading fully pressed
+    pub outer: u8,
+    pub curve: ResponseCurve,
+    /// Fully pressed past this point of the stretched travel, released
+    /// before, e.g. for shooters
+    pub hair_trigger: Option<u8>,
+}
+
+impl Default for TriggerShaping {
+    fn default() -> Self {
+        TriggerShaping::NONE
+    }
+}
+
+impl TriggerShaping {
+    /// The trigger as calibrated
+    pub const NONE: TriggerShaping = TriggerShaping {
+        deadzone: 0,
+        outer: u8::MAX,
+        curve: ResponseCurve::Linear,
+        hair_trigger: None,
+    };
+
+    /// Shape the travel of the trigger, 0 released and 255 fully pressed
+    pub fn apply(&self, value: u8) -> u8 {
+        if value <= self.deadzone {
+            return 0;
+        }
+        let start = self.deadzone as i32;
+        let outer = (self.outer as i32).max(start + 1);
+        let stretched = ((value as i32 - start) * 255 / (outer - start)).min(255);
+        if let Some(threshold) = self.hair_trigger {
+            return if stretched >= threshold as i32 {
+                u8::MAX
+            } else {
+                0
+            };
+        }
+        ((self.curve.apply(stretched * FULL / 255) * 255 + FULL / 2) / FULL) as u8
+    }
+}
+
+/// Shaping of both triggers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TriggersShaping {
+    pub left: TriggerShaping,
+    pub right: TriggerShaping,
+}
+
+impl TriggersShaping {
+    pub const NONE: TriggersShaping = TriggersShaping {
+        left: TriggerShaping::NONE,
+        right: TriggerShaping::NONE,
+    };
+}
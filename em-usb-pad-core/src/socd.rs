@@ -0,0 +1,172 @@
+//! SOCD cleaning.
+//!
+//! Simultaneous Opposing Cardinal Directions: with a button for each
+//! direction, left and right (or up and down) can be held together, which
+//! many games and tournament rules don't accept. A [`PadCleaner`] resolves
+//! them for a D-pad or a stick driven by buttons, each axis on its own, and a
+//! [`SocdCleaner`] holds one for each of them.
+
+use crate::mapping::Direction;
+
+/// What two opposite directions held together give
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SocdMode {
+    /// Both are sent
+    Off,
+    /// Neither
+    Neutral,
+    /// The one pressed last
+    LastInputWins,
+    /// The one pressed first
+    FirstInputWins,
+    /// Up, on the vertical axis. Neutral on the horizontal one.
+    UpPriority,
+}
+
+/// SOCD modes of both axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocdConfig {
+    pub horizontal: SocdMode,
+    pub vertical: SocdMode,
+}
+
+impl Default for SocdConfig {
+    fn default() -> Self {
+        SocdConfig::OFF
+    }
+}
+
+impl SocdConfig {
+    pub const OFF: SocdConfig = SocdConfig {
+        horizontal: SocdMode::Off,
+        vertical: SocdMode::Off,
+    };
+
+    pub const NEUTRAL: SocdConfig = SocdConfig {
+        horizontal: SocdMode::Neutral,
+        vertical: SocdMode::Neutral,
+    };
+
+    /// The usual rule for hitboxes, left + right is neutral, up + down is up
+    pub const HITBOX: SocdConfig = SocdConfig {
+        horizontal: SocdMode::Neutral,
+        vertical: SocdMode::UpPriority,
+    };
+}
+
+/// Which side of an axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    /// Left or down
+    Negative,
+    /// Right or up
+    Positive,
+}
+
+/// Remembers the order the two sides of an axis were pressed in
+#[derive(Debug, Default, Clone, Copy)]
+struct AxisCleaner {
+    held: (bool, bool),
+    /// `None` when both went down at the same time
+    latest: Option<Side>,
+}
+
+impl AxisCleaner {
+    /// (negative, positive) to send for the held ones
+    fn clean(&mut self, mode: SocdMode, vertical: bool, held: (bool, bool)) -> (bool, bool) {
+        let (negative, positive) = held;
+        match (negative && !self.held.0, positive && !self.held.1) {
+            (true, true) => self.latest = None,
+            (true, false) => self.latest = Some(Side::Negative),
+            (false, true) => self.latest = Some(Side::Positive),
+            (false, false) => {}
+        }
+        self.held = held;
+        if !(negative && positive) {
+            return held;
+        }
+        let winner = match mode {
+            SocdMode::Off => return held,
+            SocdMode::Neutral => None,
+            SocdMode::LastInputWins => self.latest,
+            SocdMode::FirstInputWins => self.latest.map(|latest| match latest {
+                Side::Negative => Side::Positive,
+                Side::Positive => Side::Negative,
+            }),
+            SocdMode::UpPriority if vertical => Some(Side::Positive),
+            SocdMode::UpPriority => None,
+        };
+        match winner {
+            Some(Side::Negative) => (true, false),
+            Some(Side::Positive) => (false, true),
+            None => (false, false),
+        }
+    }
+}
+
+/// Cleans the four directions of a D-pad or of a stick driven by buttons
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PadCleaner {
+    horizontal: AxisCleaner,
+    vertical: AxisCleaner,
+}
+
+impl PadCleaner {
+    pub const fn new() -> Self {
+        PadCleaner {
+            horizontal: AxisCleaner {
+                held: (false, false),
+                latest: None,
+            },
+            vertical: AxisCleaner {
+                held: (false, false),
+                latest: None,
+            },
+        }
+    }
+
+    /// The directions to send for the `held` ones, indexed by [`Direction`]
+    ///
+    /// Call it on every change, it keeps track of the order of the presses.
+    pub fn clean(&mut self, config: SocdConfig, held: [bool; 4]) -> [bool; 4] {
+        let (left, right) = self.horizontal.clean(
+            config.horizontal,
+            false,
+            (
+                held[Direction::Left as usize],
+                held[Direction::Right as usize],
+            ),
+        );
+        let (down, up) = self.vertical.clean(
+            config.vertical,
+            true,
+            (held[Direction::Down as usize], held[Direction::Up as usize]),
+        );
+        let mut cleaned = [false; 4];
+        cleaned[Direction::Up as usize] = up;
+        cleaned[Direction::Down as usize] = down;
+        cleaned[Direction::Left as usize] = left;
+        cleaned[Direction::Right as usize] = right;
+        cleaned
+    }
+}
+
+/// Cleaners of the D-pad and of both sticks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SocdCleaner {
+    pub dpad: PadCleaner,
+    pub left_stick: PadCleaner,
+    pub right_stick: PadCleaner,
+}
+
+impl SocdCleaner {
+    pub const fn new() -> Self {
+        SocdCleaner {
+            dpad: PadCleaner::new(),
+            left_stick: PadCleaner::new(),
+            right_stick: PadCleaner::new(),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! [`Settings`] in two pages of NOR flash.
+//!
+//! Each save appends a record to the active page, and only once it is full
+//! is the other page erased and the record written there. The pages wear
+//! evenly, one erase every page worth of saves, and the last record stays in
+//! one page while the other is erased or written:
+//! ```text
+//! record := magic version len sequence settings crc
+//! magic    := 0x5e75, u16 LE
+//! version  := u8, of the settings
+//! len      := u8, of the settings
+//! sequence := u32 LE, one more for each save
+//! settings := len bytes, padded to 4
+//! crc      := CRC-32 of everything before but the padding, u32 LE
+//! ```
+//! A write cut by a power loss fails its CRC, the record before it is used,
+//! and the next save moves on to the other page. With no valid record the
+//! settings are the defaults.
+
+use crate::settings::{Settings, SETTINGS_MAX_SIZE, SETTINGS_VERSION};
+
+/// Flash erased by pages, where writes can only clear bits
+pub trait NorFlash {
+    type Error: core::fmt::Debug;
+
+    /// Bytes per erased page
+    const PAGE_SIZE: usize;
+
+    /// Writes start at multiples of it and are multiples of it, up to 4
+    const WRITE_SIZE: usize;
+
+    /// Read `buf.len()` bytes from `offset`.
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `bytes` to erased flash at `offset`.
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the page starting at `offset`, setting all its bytes to 0xff.
+    fn erase(&mut self, offset: u32) -> Result<(), Self::Error>;
+}
+
+const MAGIC: u16 = 0x5e75;
+const HEADER_SIZE: usize = 8;
+const CRC_SIZE: usize = 4;
+const MAX_RECORD_SIZE: usize = HEADER_SIZE + padded(SETTINGS_MAX_SIZE) + CRC_SIZE;
+
+const fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// CRC-32 as in Ethernet and zip
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// A valid record found in flash
+#[derive(Clone, Copy)]
+struct Found {
+    sequence: u32,
+    version: u8,
+    len: usize,
+    settings: [u8; SETTINGS_MAX_SIZE],
+}
+
+/// What a page holds
+struct Page {
+    last: Option<Found>,
+    /// Where the next record goes, `None` if a record is damaged
+    free: Option<usize>,
+}
+
+/// Loads and saves the settings in the first two pages of a flash
+pub struct SettingsStore<F: NorFlash> {
+    flash: F,
+    /// The page holding the last record
+    active: usize,
+    /// Where the next record goes in it, `None` to move to the other page
+    free: Option<usize>,
+    sequence: u32,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    /// The records are padded to 4 bytes, the writes of the flash must fit
+    const ALIGNED: () = assert!(F::WRITE_SIZE <= 4 && 4 % F::WRITE_SIZE == 0);
+
+    pub fn new(flash: F) -> Self {
+        let () = Self::ALIGNED;
+        SettingsStore {
+            flash,
+            active: 0,
+            free: None,
+            sequence: 0,
+        }
+    }
+
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// The last settings saved, or the defaults if there are none.
+    ///
+    /// Call it once before saving, it finds where to save next.
+    pub fn load(&mut self) -> Settings {
+        let pages = [self.scan(0), self.scan(1)];
+        let newest = match (pages[0].last, pages[1].last) {
+            // the sequence can't wrap around in the lifetime of the flash
+            (Some(first), Some(second)) if second.sequence > first.sequence => 1,
+            (None, Some(_)) => 1,
+            _ => 0,
+        };
+        self.active = newest;
+        self.free = pages[newest].free;
+        let Some(found) = pages[newest].last else {
+            info!("No settings saved, using the defaults");
+            self.sequence = 0;
+            return Settings::default();
+        };
+        self.sequence = found.sequence;
+        match Settings::decode(found.version, &found.settings[..found.len]) {
+            Some(settings) => {
+                if found.version != SETTINGS_VERSION {
+                    info!("Settings migrated from version {}", found.version);
+                }
+                settings
+            }
+            None => {
+                warn!(
+                    "Settings version {} unknown, using the defaults",
+                    found.version
+                );
+                Settings::default()
+            }
+        }
+    }
+
+    /// Append `settings` after the last ones.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), F::Error> {
+        let mut encoded = [0; SETTINGS_MAX_SIZE];
+        let len = settings.encode(&mut encoded);
+        let sequence = self.sequence.wrapping_add(1);
+
+        let mut record = [0; MAX_RECORD_SIZE];
+        record[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        record[2] = SETTINGS_VERSION;
+        record[3] = len as u8;
+        record[4..8].copy_from_slice(&sequence.to_le_bytes());
+        record[HEADER_SIZE..HEADER_SIZE + len].copy_from_slice(&encoded[..len]);
+        let crc_at = HEADER_SIZE + padded(len);
+        let crc = crc32(&record[..HEADER_SIZE + len]);
+        record[crc_at..crc_at + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+        let record = &record[..crc_at + CRC_SIZE];
+
+        let page_size = F::PAGE_SIZE;
+        let (page, offset) = match self.free {
+            Some(offset) if offset + record.len() <= page_size => (self.active, offset),
+            _ => {
+                let other = 1 - self.active;
+                debug!("Settings move to page {}", other);
+                self.free = None;
+                self.flash.erase((other * page_size) as u32)?;
+                (other, 0)
+            }
+        };
+        // until written, the active page holds the last record and the next
+        // save goes to the other one
+        self.free = None;
+        self.flash
+            .write((page * page_size + offset) as u32, record)?;
+        self.active = page;
+        self.free = Some(offset + record.len());
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// The last valid record of `page`, and where the next one goes
+    fn scan(&mut self, page: usize) -> Page {
+        let page_size = F::PAGE_SIZE;
+        let base = page * page_size;
+        let mut found = Page {
+            last: None,
+            free: None,
+        };
+        let mut offset = 0;
+        while offset + HEADER_SIZE <= page_size {
+            let mut header = [0; HEADER_SIZE];
+            if self
+                .flash
+                .read((base + offset) as u32, &mut header)
+                .is_err()
+            {
+                return found;
+            }
+            if header == [0xff; HEADER_SIZE] {
+                // erased, the rest of the page is free
+                found.free = Some(offset);
+                return found;
+            }
+            let len = header[3] as usize;
+            let size = HEADER_SIZE + padded(len) + CRC_SIZE;
+            if u16::from_le_bytes([header[0], header[1]]) != MAGIC
+                || len > SETTINGS_MAX_SIZE
+                || offset + size > page_size
+            {
+                return found;
+            }
+            let mut record = [0; MAX_RECORD_SIZE];
+            if self
+                .flash
+                .read((base + offset) as u32, &mut record[..size])
+                .is_err()
+            {
+                return found;
+            }
+            let crc_at = HEADER_SIZE + padded(len);
+            let crc = u32::from_le_bytes([
+                record[crc_at],
+                record[crc_at + 1],
+                record[crc_at + 2],
+                record[crc_at + 3],
+            ]);
+            if crc != crc32(&record[..HEADER_SIZE + len]) {
+                warn!(
+                    "Settings record damaged at page {}, offset {}",
+                    page, offset
+                );
+                return found;
+            }
+            let mut settings = [0; SETTINGS_MAX_SIZE];
+            settings[..len].copy_from_slice(&record[HEADER_SIZE..HEADER_SIZE + len]);
+            found.last = Some(Found {
+                sequence: u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+                version: header[2],
+                len,
+                settings,
+            });
+            offset += size;
+        }
+        // full
+        found.free = Some(offset);
+        found
+    }
+}
@@ -0,0 +1,240 @@
+//! Turbo and autofire.
+//!
+//! Any digital control of the report can pulse, pressed and released at the
+//! turbo rate, after its [`TurboMode`]. While the turbo hotkey of the profile
+//! is held, pressing a control cycles its mode instead of pressing it.
+//!
+//! The pulses of a control start pressed when it is pressed, or when autofire
+//! is turned on, and [`Turbo::apply`] tells when the next edge is due so the
+//! report loop wakes up for it.
+
+use embassy_time::{Duration, Instant};
+
+use crate::mapping::Control;
+use crate::xinput::XinputControlReport;
+
+/// The controls that can pulse, triggers included
+pub const TURBO_CONTROLS: [Control; 17] = [
+    Control::A,
+    Control::B,
+    Control::X,
+    Control::Y,
+    Control::LB,
+    Control::RB,
+    Control::View,
+    Control::Menu,
+    Control::Guide,
+    Control::LS,
+    Control::RS,
+    Control::DpadUp,
+    Control::DpadDown,
+    Control::DpadLeft,
+    Control::DpadRight,
+    Control::LT,
+    Control::RT,
+];
+
+/// Pulses per second at start
+pub use em_usb_pad_protocol::settings::DEFAULT_TURBO_RATE;
+
+/// How a control pulses
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TurboMode {
+    #[default]
+    Off,
+    /// Pulses while held
+    Turbo,
+    /// Pulses until turned off, held or not
+    Auto,
+}
+
+impl TurboMode {
+    /// The mode the hotkey switches to
+    pub fn next(self) -> Self {
+        match self {
+            TurboMode::Off => TurboMode::Turbo,
+            TurboMode::Turbo => TurboMode::Auto,
+            TurboMode::Auto => TurboMode::Off,
+        }
+    }
+}
+
+/// Pulses the turbo controls of the reports
+pub struct Turbo {
+    rate: u8,
+    modes: [TurboMode; TURBO_CONTROLS.len()],
+    /// When the pulses of each control started
+    since: [Instant; TURBO_CONTROLS.len()],
+    /// Controls held in the last report, before pulsing
+    held: u32,
+    /// Controls pressed with the hotkey, held back until released
+    toggling: u32,
+    toggled: Option<(Control, TurboMode)>,
+}
+
+impl Turbo {
+    /// Every control is off at start, `rate` is in pulses per second.
+    pub fn new(rate: u8) -> Self {
+        Turbo {
+            rate: rate.max(1),
+            modes: [TurboMode::Off; TURBO_CONTROLS.len()],
+            since: [Instant::from_ticks(0); TURBO_CONTROLS.len()],
+            held: 0,
+            toggling: 0,
+            toggled: None,
+        }
+    }
+
+    pub fn rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Pulses per second, at least one
+    pub fn set_rate(&mut self, rate: u8) {
+        self.rate = rate.max(1);
+    }
+
+    /// `Off` for the controls that can't pulse
+    pub fn mode(&self, control: Control) -> TurboMode {
+        match slot(control) {
+            Some(slot) => self.modes[slot],
+            None => TurboMode::Off,
+        }
+    }
+
+    /// Returns false if `control` can't pulse.
+    pub fn set_mode(&mut self, control: Control, mode: TurboMode, now: Instant) -> bool {
+        let Some(slot) = slot(control) else {
+            return false;
+        };
+        self.modes[slot] = mode;
+        self.since[slot] = now;
+        info!("Turbo {} on {}", mode, control);
+        true
+    }
+
+    /// Whether any control pulses, held or not
+    pub fn is_active(&self) -> bool {
+        self.modes.iter().any(|mode| *mode != TurboMode::Off)
+    }
+
+    /// The last control the hotkey switched, and its new mode
+    pub fn take_toggled(&mut self) -> Option<(Control, TurboMode)> {
+        self.toggled.take()
+    }
+
+    /// Pulse the controls of `report`, call it for every report.
+    ///
+    /// While `hotkey` is held, the controls pressed switch to their next mode
+    /// and stay released until let go. Returns when the report changes next.
+    pub fn apply(
+        &mut self,
+        report: &mut XinputControlReport,
+        hotkey: bool,
+        now: Instant,
+    ) -> Option<Instant> {
+        let mut held = 0;
+        for (slot, control) in TURBO_CONTROLS.iter().enumerate() {
+            if is_held(report, *control) {
+                held |= 1 << slot;
+            }
+        }
+        let pressed = held & !self.held;
+        self.held = held;
+
+        if hotkey {
+            for (slot, control) in TURBO_CONTROLS.iter().enumerate() {
+                if pressed & 1 << slot != 0 {
+                    let mode = self.modes[slot].next();
+                    self.set_mode(*control, mode, now);
+                    self.toggled = Some((*control, mode));
+                    self.toggling |= 1 << slot;
+                }
+            }
+        }
+        self.toggling &= held;
+
+        let half = Duration::from_micros(500_000 / self.rate as u64).as_ticks();
+        let mut next: Option<Instant> = None;
+        for (slot, control) in TURBO_CONTROLS.iter().enumerate() {
+            let bit = 1 << slot;
+            if self.toggling & bit != 0 {
+                set(report, *control, false);
+                continue;
+            }
+            let pulsing = match self.modes[slot] {
+                TurboMode::Off => false,
+                TurboMode::Turbo => held & bit != 0,
+                TurboMode::Auto => true,
+            };
+            if !pulsing {
+                continue;
+            }
+            if self.modes[slot] == TurboMode::Turbo && pressed & bit != 0 {
+                self.since[slot] = now;
+            }
+            let phase = now.saturating_duration_since(self.since[slot]).as_ticks() / half;
+            if phase % 2 == 1 {
+                set(report, *control, false);
+            } else if held & bit == 0 {
+                set(report, *control, true);
+            }
+            let edge = self.since[slot] + Duration::from_ticks((phase + 1) * half);
+            next = Some(next.map_or(edge, |next| next.min(edge)));
+        }
+        next
+    }
+}
+
+fn slot(control: Control) -> Option<usize> {
+    TURBO_CONTROLS.iter().position(|turbo| *turbo == control)
+}
+
+fn is_held(report: &XinputControlReport, control: Control) -> bool {
+    match control {
+        Control::A => report.button_a,
+        Control::B => report.button_b,
+        Control::X => report.button_x,
+        Control::Y => report.button_y,
+        Control::LB => report.shoulder_left,
+        Control::RB => report.shoulder_right,
+        Control::View => report.button_view,
+        Control::Menu => report.button_menu,
+        Control::Guide => report.xbox_button,
+        Control::LS => report.thumb_click_left,
+        Control::RS => report.thumb_click_right,
+        Control::DpadUp => report.dpad_up,
+        Control::DpadDown => report.dpad_down,
+        Control::DpadLeft => report.dpad_left,
+        Control::DpadRight => report.dpad_right,
+        Control::LT => report.trigger_left != 0,
+        Control::RT => report.trigger_right != 0,
+        _ => false,
+    }
+}
+
+/// Release sets a trigger to 0, press all the way.
+fn set(report: &mut XinputControlReport, control: Control, pressed: bool) {
+    let trigger = if pressed { u8::MAX } else { 0 };
+    match control {
+        Control::A => report.button_a = pressed,
+        Control::B => report.button_b = pressed,
+        Control::X => report.button_x = pressed,
+        Control::Y => report.button_y = pressed,
+        Control::LB => report.shoulder_left = pressed,
+        Control::RB => report.shoulder_right = pressed,
+        Control::View => report.button_view = pressed,
+        Control::Menu => report.button_menu = pressed,
+        Control::Guide => report.xbox_button = pressed,
+        Control::LS => report.thumb_click_left = pressed,
+        Control::RS => report.thumb_click_right = pressed,
+        Control::DpadUp => report.dpad_up = pressed,
+        Control::DpadDown => report.dpad_down = pressed,
+        Control::DpadLeft => report.dpad_left = pressed,
+        Control::DpadRight => report.dpad_right = pressed,
+        Control::LT => report.trigger_left = trigger,
+        Control::RT => report.trigger_right = trigger,
+        _ => {}
+    }
+}
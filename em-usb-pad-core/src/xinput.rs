@@ -4,11 +4,14 @@
 use core::mem::MaybeUninit;
 use packed_struct::prelude::*;
 
-use embassy_usb::control::OutResponse;
+use embassy_usb::control::{InResponse, OutResponse, Request};
 use embassy_usb::driver::{Driver, Endpoint, EndpointError, EndpointIn, EndpointOut};
+use embassy_usb::types::InterfaceNumber;
 use embassy_usb::{Builder, Handler};
 
-use defmt::{trace, warn};
+use crate::capabilities::{Capabilities, XinputRequest, SERIAL_SIZE};
+use crate::msos::{MsOsDescriptors, MS_OS_STRING_INDEX};
+use crate::protocol::{self, ConfigHandler};
 
 // For Xinput controllers, there are 4 USB interfaces:
 // - Control
@@ -20,8 +23,8 @@ use defmt::{trace, warn};
 // The Xinput protocol is NOT a variant of USB HID, it's a fully customized one.
 
 // just copied from a controller with Xinput support
-const USB_XINPUT_VID: u16 = 0x045e;
-const USB_XINPUT_PID: u16 = 0x028e;
+pub const USB_XINPUT_VID: u16 = 0x045e;
+pub const USB_XINPUT_PID: u16 = 0x028e;
 const USB_CLASS_VENDOR: u8 = 0xff;
 const USB_SUBCLASS_VENDOR: u8 = 0xff;
 const USB_PROTOCOL_VENDOR: u8 = 0xff;
@@ -36,6 +39,7 @@ const XINPUT_DESC_STRING_VENDOR: &str = "Embassy";
 const XINPUT_DESC_STRING_PRODUCT: &str = "Pad Oxide";
 const XINPUT_DESC_STRING_SN: &str = "Controller";
 const XINPUT_DESC_STRING_SECURITY: &str = "Pad Oxide does not support Xbox Security Method!";
+const XINPUT_SERIAL: [u8; SERIAL_SIZE] = [0x00; SERIAL_SIZE];
 
 const XINPUT_DESC_DESCTYPE_STANDARD: u8 = 0x21; // a common descriptor type for all xinput interfaces
 const XINPUT_DESC_DESCTYPE_SECURITY: u8 = 0x41; // a special one for the security descriptor
@@ -106,6 +110,24 @@ pub trait RequestHandler {
     }
 }
 
+/// Receives the messages the host sends on interface 0
+pub trait XinputEventHandler {
+    /// The host sets the motor speeds.
+    fn on_rumble(&mut self, rumble: XinputRumbleState) {
+        let _ = rumble;
+    }
+
+    /// The host sets the pattern of the LED ring.
+    fn on_led(&mut self, pattern: XinputLedPattern) {
+        let _ = pattern;
+    }
+
+    /// A well-formed message of another type, 0x02 and 0x03 included.
+    fn on_unknown(&mut self, packet: XinputRawPacket) {
+        let _ = packet;
+    }
+}
+
 /// The ability to convert struct to a buffer to send
 pub trait AsXinputReport {
     /// Write serialized report to the given buffer start from given offset
@@ -192,14 +214,24 @@ impl AsXinputReport for XinputControlReport {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct XinputRumbleState {
-    left: u8,
-    right: u8,
+    /// The heavy, low-frequency motor
+    pub left: u8,
+    /// The light, high-frequency motor
+    pub right: u8,
+}
+
+impl XinputRumbleState {
+    pub const fn new(left: u8, right: u8) -> Self {
+        XinputRumbleState { left, right }
+    }
 }
 
 #[repr(u8)]
 #[derive(PrimitiveEnum_u8, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum XinputLedPattern {
     Off = 0x00,
     Blink = 0x01,
@@ -217,28 +249,128 @@ pub enum XinputLedPattern {
     Alternate = 0x0D,
 }
 
+// host to device message types on interface 0
+const XINPUT_MSG_RUMBLE: u8 = 0x00;
+const XINPUT_MSG_LED: u8 = 0x01;
+const XINPUT_MSG_TYPE02: u8 = 0x02;
+const XINPUT_MSG_TYPE03: u8 = 0x03;
+
+// shortest valid messages, header (type, length) included
+const XINPUT_MSG_RUMBLE_MIN_LEN: usize = 5;
+const XINPUT_MSG_LED_MIN_LEN: usize = 3;
+const XINPUT_MSG_HEADER_LEN: usize = 2;
+
+/// A host message kept as it was received
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct XinputRawPacket {
+    len: u8,
+    buf: [u8; XINPUT_RW_BUFFER_SIZE],
+}
+
+impl XinputRawPacket {
+    /// Copy a packet, which must fit in `XINPUT_RW_BUFFER_SIZE`
+    fn new(value: &[u8]) -> Self {
+        let mut buf = [0; XINPUT_RW_BUFFER_SIZE];
+        buf[..value.len()].copy_from_slice(value);
+        XinputRawPacket {
+            len: value.len() as u8,
+            buf,
+        }
+    }
+
+    /// Message type, the first byte
+    pub fn kind(&self) -> u8 {
+        self.buf[0]
+    }
+
+    /// The whole packet, header included
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+
+    /// The packet without its (type, length) header
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[XINPUT_MSG_HEADER_LEN..self.len as usize]
+    }
+}
+
+impl core::fmt::Debug for XinputRawPacket {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("XinputRawPacket")
+            .field(&self.as_bytes())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum XinputHostStatus {
     /// Rumble status
     Rumble(XinputRumbleState),
     /// LED status
     Led(XinputLedPattern),
-    /// Unknown status packet, not parsed
-    Unknown,
+    /// Message type 0x02, sent by some drivers, payload not understood yet
+    Type02(XinputRawPacket),
+    /// Message type 0x03, sent by some drivers, payload not understood yet
+    Type03(XinputRawPacket),
+    /// Unknown message type, not parsed
+    Unknown(XinputRawPacket),
 }
 
-impl From<&[u8]> for XinputHostStatus {
+/// Reasons a host message cannot be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostStatusError {
+    /// Not even a (type, length) header
+    TooShort { len: usize },
+    /// Longer than any packet on interface 0
+    TooLong { len: usize },
+    /// The length byte doesn't match the received length
+    LengthMismatch { declared: u8, actual: usize },
+    /// The message is shorter than its type requires
+    Truncated { kind: u8, len: usize },
+    /// The LED message carries an unknown pattern
+    InvalidLedPattern(u8),
+}
+
+impl TryFrom<&[u8]> for XinputHostStatus {
+    type Error = HostStatusError;
+
     /// Build XinputHostStatus from raw host report
-    fn from(value: &[u8]) -> Self {
-        match (value[0], value.len()) {
-            (0x00, 0x08) => XinputHostStatus::Rumble(XinputRumbleState {
+    ///
+    /// Every message starts with its type and its total length.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let len = value.len();
+        if len < XINPUT_MSG_HEADER_LEN {
+            return Err(HostStatusError::TooShort { len });
+        }
+        if len > XINPUT_RW_BUFFER_SIZE {
+            return Err(HostStatusError::TooLong { len });
+        }
+        let (kind, declared) = (value[0], value[1]);
+        if declared as usize != len {
+            return Err(HostStatusError::LengthMismatch {
+                declared,
+                actual: len,
+            });
+        }
+
+        match kind {
+            XINPUT_MSG_RUMBLE if len < XINPUT_MSG_RUMBLE_MIN_LEN => {
+                Err(HostStatusError::Truncated { kind, len })
+            }
+            XINPUT_MSG_RUMBLE => Ok(XinputHostStatus::Rumble(XinputRumbleState {
                 left: value[3],
                 right: value[4],
-            }),
-            (0x01, 0x03) => match XinputLedPattern::from_primitive(value[2]) {
-                Some(led_pattern) => XinputHostStatus::Led(led_pattern),
-                None => XinputHostStatus::Unknown,
-            },
-            _ => XinputHostStatus::Unknown,
+            })),
+            XINPUT_MSG_LED if len < XINPUT_MSG_LED_MIN_LEN => {
+                Err(HostStatusError::Truncated { kind, len })
+            }
+            XINPUT_MSG_LED => XinputLedPattern::from_primitive(value[2])
+                .map(XinputHostStatus::Led)
+                .ok_or(HostStatusError::InvalidLedPattern(value[2])),
+            XINPUT_MSG_TYPE02 => Ok(XinputHostStatus::Type02(XinputRawPacket::new(value))),
+            XINPUT_MSG_TYPE03 => Ok(XinputHostStatus::Type03(XinputRawPacket::new(value))),
+            _ => Ok(XinputHostStatus::Unknown(XinputRawPacket::new(value))),
         }
     }
 }
@@ -259,6 +391,18 @@ pub struct Config<'d> {
     pub unknown_handler: Option<&'d dyn RequestHandler>, // subject to change
     /// A handler for security interface
     pub security_handler: Option<&'d dyn RequestHandler>, // subject to change
+    /// Configuration requests on the control interface, see [`crate::protocol`]
+    pub config_handler: Option<&'d dyn ConfigHandler>,
+    /// Answer the Microsoft OS 1.0 descriptors with this vendor request
+    /// code, for Windows to bind its Xinput driver without the controller's
+    /// VID and PID, see [`crate::msos`]
+    pub ms_os_vendor_code: Option<u8>,
+
+    // Answers to the vendor requests of the driver, see crate::capabilities
+    /// What the pad has, the requests stall with `None`
+    pub capabilities: Option<Capabilities>,
+    /// The answer to the serial request, the request stalls with `None`
+    pub serial: Option<[u8; SERIAL_SIZE]>,
 }
 
 impl<'d> Default for Config<'d> {
@@ -273,11 +417,35 @@ impl<'d> Default for Config<'d> {
             audio_handler: None,
             unknown_handler: None,
             security_handler: None,
+            config_handler: None,
+            ms_os_vendor_code: None,
+
+            capabilities: Some(Capabilities::GAMEPAD),
+            serial: Some(XINPUT_SERIAL),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+/// The device config of a wired controller with `vid` and `pid`, and the
+/// strings of `config`: vendor class, 8 byte control packets, bus powered
+/// drawing 500 mA, remote wakeup.
+pub fn usb_config<'d>(vid: u16, pid: u16, config: &Config<'d>) -> embassy_usb::Config<'d> {
+    let mut usb_config = embassy_usb::Config::new(vid, pid);
+    usb_config.max_power = 500;
+    usb_config.max_packet_size_0 = 8;
+    usb_config.device_class = USB_CLASS_VENDOR;
+    usb_config.device_sub_class = USB_SUBCLASS_VENDOR;
+    usb_config.device_protocol = USB_PROTOCOL_VENDOR;
+    usb_config.device_release = USB_DEVICE_RELEASE;
+    usb_config.supports_remote_wakeup = true;
+    usb_config.manufacturer = config.vendor_string;
+    usb_config.product = config.product_string;
+    usb_config.serial_number = config.serial_number_string;
+    usb_config
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ReportId {
     In(u8),
     Out(u8),
@@ -301,27 +469,72 @@ struct Control<'d> {
     serial_number_string: Option<&'d str>,
     security_string: Option<&'d str>,
     request_handler: Option<&'d dyn RequestHandler>,
+    config_handler: Option<&'d dyn ConfigHandler>,
+    ms_os: Option<MsOsDescriptors>,
+    capabilities: Option<Capabilities>,
+    serial: Option<[u8; SERIAL_SIZE]>,
+    /// The control interface
+    interface: InterfaceNumber,
 }
 
 impl<'d> Control<'d> {
-    fn new(
-        vendor_string: Option<&'d str>,
-        product_string: Option<&'d str>,
-        serial_number_string: Option<&'d str>,
-        security_string: Option<&'d str>,
-        request_handler: Option<&'d dyn RequestHandler>,
-    ) -> Self {
+    fn new(config: &Config<'d>, interface: InterfaceNumber) -> Self {
         Control {
-            vendor_string,
-            product_string,
-            serial_number_string,
-            security_string,
-            request_handler,
+            vendor_string: config.vendor_string,
+            product_string: config.product_string,
+            serial_number_string: config.serial_number_string,
+            security_string: config.security_string,
+            request_handler: config.request_handler,
+            config_handler: config.config_handler,
+            ms_os: config.ms_os_vendor_code.map(MsOsDescriptors::new),
+            capabilities: config.capabilities,
+            serial: config.serial,
+            interface,
         }
     }
 }
 
 impl<'d> Handler for Control<'d> {
+    fn control_out(&mut self, req: Request, data: &[u8]) -> Option<OutResponse> {
+        if !protocol::is_config_request(&req, self.interface) {
+            return None;
+        }
+        let handler = self.config_handler?;
+        Some(match protocol::handle_out(handler, &req, data) {
+            true => OutResponse::Accepted,
+            false => OutResponse::Rejected,
+        })
+    }
+
+    fn control_in<'a>(&'a mut self, req: Request, buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        if let Some(ms_os) = self.ms_os.filter(|ms_os| ms_os.is_compat_id_request(&req)) {
+            debug!("Xinput MS OS compat ID request");
+            // the host reads the header first
+            return Some(reply(&ms_os.compat_id(self.interface.into()), &req, buf));
+        }
+        if let Some(request) = XinputRequest::parse(&req, self.interface) {
+            debug!("Xinput vendor request {:?}", request);
+            return Some(match (request, self.capabilities, self.serial) {
+                (XinputRequest::InputCapabilities, Some(capabilities), _) => {
+                    reply(&capabilities.input(), &req, buf)
+                }
+                (XinputRequest::VibrationCapabilities, Some(capabilities), _) => {
+                    reply(&capabilities.vibration(), &req, buf)
+                }
+                (XinputRequest::Serial, _, Some(serial)) => reply(&serial, &req, buf),
+                _ => InResponse::Rejected,
+            });
+        }
+        if !protocol::is_config_request(&req, self.interface) {
+            return None;
+        }
+        let handler = self.config_handler?;
+        Some(match protocol::handle_in(handler, &req, buf) {
+            Some(len) => InResponse::Accepted(&buf[..len]),
+            None => InResponse::Rejected,
+        })
+    }
+
     fn get_string(&mut self, index: embassy_usb::types::StringIndex, lang_id: u16) -> Option<&str> {
         trace!("Xinput get_descriptor string");
         let _ = lang_id;
@@ -330,11 +543,19 @@ impl<'d> Handler for Control<'d> {
             0x02 => self.product_string,
             0x03 => self.serial_number_string,
             0x04 => self.security_string,
+            MS_OS_STRING_INDEX => self.ms_os.as_ref().map(MsOsDescriptors::string),
             _ => None,
         }
     }
 }
 
+/// Answer `data`, cut to the length the host asked for
+fn reply<'a>(data: &[u8], req: &Request, buf: &'a mut [u8]) -> InResponse<'a> {
+    let len = data.len().min(buf.len()).min(req.length as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    InResponse::Accepted(&buf[..len])
+}
+
 /// A shared state of USB interface status
 pub struct XinputState<'d> {
     control_control: MaybeUninit<Control<'d>>,
@@ -361,7 +582,8 @@ pub struct XinputReader<'d, D: Driver<'d>> {
     ep_out: D::EndpointOut,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, defmt::Format)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ReadError {
     BufferOverflow,
     Disabled,
@@ -409,15 +631,17 @@ impl<'d, D: Driver<'d>> XinputReader<'d, D> {
         self.ep_out.wait_enabled().await
     }
 
-    /// Delivers output reports from the Interrupt Out pipe to `handler`.
-    pub async fn run<T: RequestHandler>(mut self, use_report_ids: bool, handler: &T) -> ! {
+    /// Waits for the next well-formed message from the host.
+    ///
+    /// Malformed messages are logged and skipped.
+    pub async fn read_status(&mut self) -> XinputHostStatus {
         let mut buf = [0; XINPUT_RW_BUFFER_SIZE];
         loop {
             match self.read(&mut buf).await {
-                Ok(len) => {
-                    let id = if use_report_ids { buf[0] } else { 0 };
-                    handler.set_report(ReportId::Out(id), &buf[..len]);
-                }
+                Ok(len) => match XinputHostStatus::try_from(&buf[..len]) {
+                    Ok(status) => return status,
+                    Err(e) => warn!("Malformed message from host: {:?}", e),
+                },
                 Err(ReadError::BufferOverflow) => warn!(
                     "Host sent output report larger than the configured maximum output report length ({})", XINPUT_EP_MAX_PACKET_SIZE),
                 Err(ReadError::Disabled) => self.ep_out.wait_enabled().await,
@@ -425,6 +649,19 @@ impl<'d, D: Driver<'d>> XinputReader<'d, D> {
         }
     }
 
+    /// Delivers the messages from the Interrupt Out pipe to `handler`.
+    pub async fn run<H: XinputEventHandler>(mut self, handler: &mut H) -> ! {
+        loop {
+            match self.read_status().await {
+                XinputHostStatus::Rumble(rumble) => handler.on_rumble(rumble),
+                XinputHostStatus::Led(pattern) => handler.on_led(pattern),
+                XinputHostStatus::Type02(packet)
+                | XinputHostStatus::Type03(packet)
+                | XinputHostStatus::Unknown(packet) => handler.on_unknown(packet),
+            }
+        }
+    }
+
     /// Reads an output report from the Interrupt Out pipe.
     pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ReadError> {
         // Read packets from the endpoint, ignoring packets bigger than XINPUT_EP_MAX_PACKET_SIZE
@@ -463,16 +700,6 @@ fn build<'d, D: Driver<'d>>(
     Option<D::EndpointIn>,
     Option<D::EndpointIn>,
 ) {
-    // add the handler in advance so no mut ref more than once error
-    let control = state.control_control.write(Control::new(
-        config.vendor_string,
-        config.product_string,
-        config.serial_number_string,
-        config.security_string,
-        config.request_handler,
-    ));
-    builder.handler(control);
-
     // add a new configuration
     let mut func = builder.function(USB_CLASS_VENDOR, USB_SUBCLASS_VENDOR, USB_PROTOCOL_VENDOR);
 
@@ -501,6 +728,7 @@ fn build<'d, D: Driver<'d>>(
         "The extra str_index should be 4 but it's {} !",
         u8::from(str_index)
     );
+    let control_number = control_interface.interface_number();
 
     // the audio interface
     let mut audio_interface = func.interface();
@@ -528,14 +756,22 @@ fn build<'d, D: Driver<'d>>(
     let ep_in_if2 = alt_unknown.endpoint_interrupt_in(XINPUT_EP_MAX_PACKET_SIZE, 0x10);
 
     // the security interface, no endpoint
+    // its iInterface points to the security string allocated above
     let mut security_interface = func.interface();
     let mut alt_security = security_interface.alt_setting(
         USB_CLASS_VENDOR,
         XINPUT_IFACE_SUBCLASS_SECURITY,
         XINPUT_IFACE_PROTO_IF3,
-        None,
+        Some(str_index),
     );
     alt_security.descriptor(XINPUT_DESC_DESCTYPE_SECURITY, XINPUT_DESC_IF3);
+    drop(func);
+
+    // the handler once the function no longer borrows the builder
+    let control = state
+        .control_control
+        .write(Control::new(&config, control_number));
+    builder.handler(control);
 
     (
         Some(ep_out_if0),
@@ -591,6 +827,13 @@ impl<'d, D: Driver<'d>> XinputReaderWriter<'d, D> {
         self.writer.write_control(report).await
     }
 
+    /// Waits for the next message from the host.
+    ///
+    /// See [`XinputReader::read_status`].
+    pub async fn read_status(&mut self) -> XinputHostStatus {
+        self.reader.read_status().await
+    }
+
     /// Reads an output report from the Interrupt Out pipe.
     ///
     /// See [`XinputReader::read`].
@@ -0,0 +1,120 @@
+//! Convert raw stick readings to the Xinput range.
+
+use em_usb_pad_core::analog::{
+    AnalogSource, AxisCalibration, AxisCalibrator, NoAnalog, StickCalibration, Sticks,
+    SticksCalibration,
+};
+use em_usb_pad_core::board::AnalogInput;
+use em_usb_pad_core::shaping::SticksShaping;
+use em_usb_pad_core::xinput::XinputControlReport;
+
+/// Fixed readings, only the left stick is wired
+struct LeftStick {
+    x: u16,
+    y: u16,
+}
+
+impl AnalogSource for LeftStick {
+    fn read(&mut self, input: AnalogInput) -> Option<u16> {
+        match input {
+            AnalogInput::LeftStickX => Some(self.x),
+            AnalogInput::LeftStickY => Some(self.y),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn full_range_conversion() {
+    let axis = AxisCalibration::default();
+    // (raw, expected)
+    let table = [
+        (0, i16::MIN),
+        (1024, -16384),
+        (2048, 0),
+        (3071, 16375), // the upper half is one count shorter
+        (4095, i16::MAX),
+    ];
+    for (raw, expected) in table {
+        assert_eq!(axis.to_xinput(raw), expected, "raw {}", raw);
+    }
+}
+
+#[test]
+fn off_centre_stick() {
+    // a real stick rests at 1900 and only reaches 300 and 3900
+    let axis = AxisCalibration::new(300, 1900, 3900);
+    assert_eq!(axis.to_xinput(1900), 0);
+    assert_eq!(axis.to_xinput(300), i16::MIN);
+    assert_eq!(axis.to_xinput(1100), -16384);
+    assert_eq!(axis.to_xinput(2900), 16383);
+    assert_eq!(axis.to_xinput(3900), i16::MAX);
+    // past the ends
+    assert_eq!(axis.to_xinput(0), i16::MIN);
+    assert_eq!(axis.to_xinput(4095), i16::MAX);
+}
+
+#[test]
+fn inverted_axis() {
+    let axis = AxisCalibration::default().inverted();
+    assert_eq!(axis.to_xinput(0), i16::MAX);
+    assert_eq!(axis.to_xinput(2048), 0);
+    assert_eq!(axis.to_xinput(4095), -i16::MAX);
+}
+
+#[test]
+fn degenerate_calibration_reads_centre() {
+    let axis = AxisCalibration::new(2048, 2048, 2048);
+    assert_eq!(axis.to_xinput(0), 0);
+    assert_eq!(axis.to_xinput(4095), 0);
+}
+
+#[test]
+fn calibrator() {
+    let mut calibrator = AxisCalibrator::new();
+    assert_eq!(calibrator.finish(false, 0), None);
+    for raw in [1990, 2000, 2010] {
+        calibrator.rest(raw);
+    }
+    // only moved one way
+    calibrator.moved(2000);
+    calibrator.moved(3800);
+    assert_eq!(calibrator.finish(false, 0), None);
+
+    calibrator.moved(150);
+    assert_eq!(
+        calibrator.finish(true, 50),
+        Some(AxisCalibration::new(200, 2000, 3750).inverted())
+    );
+}
+
+#[test]
+fn sticks_in_the_report() {
+    let calibration = SticksCalibration {
+        left: StickCalibration {
+            x: AxisCalibration::default(),
+            // the pot goes down when pushed up
+            y: AxisCalibration::default().inverted(),
+        },
+        ..Default::default()
+    };
+    let mut sticks = Sticks::new(calibration);
+    sticks.sample(&mut LeftStick { x: 4095, y: 0 });
+    assert_eq!(sticks.left(), (i16::MAX, i16::MAX));
+    assert_eq!(sticks.right(), (0, 0));
+
+    let mut report = XinputControlReport::default();
+    sticks.apply(&mut report, &SticksShaping::NONE);
+    assert_eq!((report.js_left_x, report.js_left_y), (i16::MAX, i16::MAX));
+
+    // buttons mapped to the stick win
+    let mut report = XinputControlReport {
+        js_left_x: i16::MIN,
+        ..Default::default()
+    };
+    sticks.apply(&mut report, &SticksShaping::NONE);
+    assert_eq!((report.js_left_x, report.js_left_y), (i16::MIN, 0));
+
+    sticks.sample(&mut NoAnalog);
+    assert_eq!(sticks.left(), (0, 0));
+}
@@ -0,0 +1,230 @@
+//! Scan a simulated board.
+
+use std::cell::{Cell, RefCell};
+use std::convert::Infallible;
+
+use em_usb_pad_core::board::{
+    ActiveHigh, InputId, InputStates, Layout, RowPort, ScanStats, Scanner,
+};
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+/// Keys held down, and the column currently driven low
+#[derive(Default)]
+struct Wiring {
+    pressed: RefCell<Vec<(usize, usize)>>,
+    direct: RefCell<Vec<usize>>,
+    driven: Cell<Option<usize>>,
+}
+
+struct Row<'a>(&'a Wiring, usize);
+
+impl<'a> InputPin for Row<'a> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        self.is_low().map(|low| !low)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(match self.0.driven.get() {
+            Some(col) => self.0.pressed.borrow().contains(&(self.1, col)),
+            None => false,
+        })
+    }
+}
+
+struct Col<'a>(&'a Wiring, usize);
+
+impl<'a> OutputPin for Col<'a> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        assert_eq!(self.0.driven.get(), None, "two columns driven at once");
+        self.0.driven.set(Some(self.1));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        if self.0.driven.get() == Some(self.1) {
+            self.0.driven.set(None);
+        }
+        Ok(())
+    }
+}
+
+/// A button to VCC, high when pressed
+struct Button<'a>(&'a Wiring, usize);
+
+impl<'a> InputPin for Button<'a> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(self.0.direct.borrow().contains(&self.1))
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+fn matrix(row: u8, col: u8) -> InputId {
+    InputId::Matrix { row, col }
+}
+
+#[test]
+fn layout_indexes_every_button_once() {
+    let layout = Layout::new(4, 3, 2);
+    assert_eq!(layout.buttons(), 14);
+    for index in 0..layout.buttons() {
+        let input = layout.input(index).unwrap();
+        assert_eq!(layout.index(input), Some(index), "{:?}", input);
+    }
+    assert_eq!(layout.index(matrix(0, 2)), Some(2));
+    assert_eq!(layout.index(matrix(1, 0)), Some(3));
+    assert_eq!(layout.index(InputId::Direct(1)), Some(13));
+    assert_eq!(layout.input(14), None);
+    assert_eq!(layout.index(matrix(4, 0)), None);
+    assert_eq!(layout.index(matrix(0, 3)), None);
+    assert_eq!(layout.index(InputId::Direct(2)), None);
+}
+
+#[test]
+fn scan_reports_changes() {
+    let wiring = Wiring::default();
+    let mut scanner = Scanner::<_, _, _, 4, 3, 1>::new(
+        [
+            Row(&wiring, 0),
+            Row(&wiring, 1),
+            Row(&wiring, 2),
+            Row(&wiring, 3),
+        ],
+        [Col(&wiring, 0), Col(&wiring, 1), Col(&wiring, 2)],
+        [ActiveHigh(Button(&wiring, 0))],
+    );
+    assert_eq!(scanner.layout(), Layout::new(4, 3, 1));
+    assert_eq!(scanner.scan().count(), 0);
+
+    // several keys on the same row and column
+    wiring.pressed.borrow_mut().extend([(0, 0), (0, 2), (3, 2)]);
+    wiring.direct.borrow_mut().push(0);
+    let changes: Vec<_> = scanner.scan().collect();
+    assert_eq!(
+        changes,
+        [
+            (matrix(0, 0), true),
+            (matrix(0, 2), true),
+            (matrix(3, 2), true),
+            (InputId::Direct(0), true),
+        ]
+    );
+    assert_eq!(wiring.driven.get(), None);
+
+    // nothing changed
+    assert_eq!(scanner.scan().count(), 0);
+
+    wiring.pressed.borrow_mut().retain(|&key| key != (0, 2));
+    wiring.direct.borrow_mut().clear();
+    let changes: Vec<_> = scanner.scan().collect();
+    assert_eq!(
+        changes,
+        [(matrix(0, 2), false), (InputId::Direct(0), false)]
+    );
+    let layout = scanner.layout();
+    let states = scanner.states();
+    assert!(states.is_pressed(layout.index(matrix(0, 0)).unwrap()));
+    assert!(states.is_pressed(layout.index(matrix(3, 2)).unwrap()));
+    assert_eq!(states.0.count_ones(), 2);
+}
+
+#[test]
+fn direct_only_board() {
+    let wiring = Wiring::default();
+    let buttons: [_; 5] = core::array::from_fn(|n| ActiveHigh(Button(&wiring, n)));
+    let mut scanner = Scanner::<[Row; 0], Col, _, 0, 0, 5>::new([], [], buttons);
+    wiring.direct.borrow_mut().push(4);
+    assert_eq!(
+        scanner.scan().collect::<Vec<_>>(),
+        [(InputId::Direct(4), true)]
+    );
+}
+
+#[test]
+fn changes_ignore_buttons_outside_the_layout() {
+    let layout = Layout::new(1, 2, 0);
+    let current = InputStates(0b1011);
+    let changes: Vec<_> = current.changes(InputStates(0b1000), layout).collect();
+    assert_eq!(changes, [(matrix(0, 0), true), (matrix(0, 1), true)]);
+}
+
+/// All rows of the simulated wiring in one read, as from a GPIO port
+struct Port<'a>(&'a Wiring);
+
+impl<'a> RowPort for Port<'a> {
+    fn read_rows(&mut self) -> u32 {
+        let Some(col) = self.0.driven.get() else {
+            return 0;
+        };
+        let pressed = self.0.pressed.borrow();
+        pressed
+            .iter()
+            .filter(|&&(_, key_col)| key_col == col)
+            .fold(0, |rows, &(row, _)| rows | 1 << row)
+    }
+}
+
+#[test]
+fn rows_read_at_once() {
+    let wiring = Wiring::default();
+    let mut scanner = Scanner::<_, _, ActiveHigh<Button>, 4, 3, 0>::new(
+        Port(&wiring),
+        [Col(&wiring, 0), Col(&wiring, 1), Col(&wiring, 2)],
+        [],
+    );
+    wiring.pressed.borrow_mut().extend([(1, 0), (2, 0), (3, 1)]);
+    let changes: Vec<_> = scanner.scan().collect();
+    assert_eq!(
+        changes,
+        [
+            (matrix(1, 0), true),
+            (matrix(2, 0), true),
+            (matrix(3, 1), true)
+        ]
+    );
+}
+
+#[test]
+fn listen_drives_the_columns() {
+    let wiring = Wiring::default();
+    let mut scanner =
+        Scanner::<_, _, ActiveHigh<Button>, 1, 1, 0>::new([Row(&wiring, 0)], [Col(&wiring, 0)], []);
+    wiring.pressed.borrow_mut().push((0, 0));
+    scanner.listen();
+    assert_eq!(wiring.driven.get(), Some(0));
+    assert_eq!(scanner.rows_mut().read_rows(), 1);
+    // scanning releases them
+    assert_eq!(scanner.scan().count(), 1);
+    assert_eq!(wiring.driven.get(), None);
+}
+
+#[test]
+fn scan_stats() {
+    let at = Instant::from_micros;
+    let mut stats = ScanStats::new();
+    assert_eq!(stats.mean_cycles(), 0);
+    stats.record(at(0), 960);
+    stats.record(at(1000), 1920);
+    stats.record(at(2500), 1440);
+    assert_eq!(stats.scans(), 3);
+    assert_eq!(stats.mean_cycles(), 1440);
+    assert_eq!(stats.longest_cycles(), 1920);
+    assert_eq!(stats.longest_interval(), Duration::from_micros(1500));
+
+    // a sleep isn't a late scan
+    stats.resume();
+    stats.record(at(90_000), 480);
+    assert_eq!(stats.longest_interval(), Duration::from_micros(1500));
+
+    stats.reset();
+    assert_eq!(stats, ScanStats::new());
+}
@@ -0,0 +1,175 @@
+//! The vendor requests of the Xinput driver on the mock bus, see
+//! `capabilities`.
+
+use std::future::Future;
+
+use em_usb_pad_core::capabilities::{
+    Capabilities, XinputRequest, ALL_BUTTONS, BUTTON_THUMB_LEFT, BUTTON_THUMB_RIGHT, BUTTON_XBOX,
+};
+use em_usb_pad_core::mock::{MockDriver, MockState, Setup, Stalled};
+use em_usb_pad_core::xinput::{Config, XinputReaderWriter, XinputState};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_usb::control::Request;
+use embassy_usb::types::InterfaceNumber;
+use embassy_usb::Builder;
+
+// The tables are synthetic: written by hand after the requests and answers of
+// a wired controller as documented by the open source Xinput drivers, not
+// captured here. The setup packets of the Xinput driver:
+const INPUT_CAPABILITIES: [u8; 8] = [0xc1, 0x01, 0x00, 0x01, 0x00, 0x00, 0x14, 0x00];
+const VIBRATION_CAPABILITIES: [u8; 8] = [0xc1, 0x01, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00];
+const SERIAL: [u8; 8] = [0xc0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00];
+
+// and what a genuine controller answers them
+const REFERENCE_INPUT_CAPABILITIES: [u8; 20] = [
+    0x00, 0x14, 0xff, 0xf7, 0xff, 0xff, 0xc0, 0xff, 0xc0, 0xff, 0xc0, 0xff, 0xc0, 0xff, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+];
+const REFERENCE_VIBRATION_CAPABILITIES: [u8; 8] = [0x00, 0x08, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00];
+
+/// Enumerate a device on `mock` built with `config`, then run `host`.
+fn with_device(mock: &MockState, config: Config, host: impl Future<Output = ()>) {
+    let mut xinput_state = XinputState::new();
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+
+    let mut usb_config = embassy_usb::Config::new(0x045e, 0x028e);
+    usb_config.max_packet_size_0 = 8;
+    let mut builder = Builder::new(
+        MockDriver::new(mock),
+        usb_config,
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+    );
+    let _xinput = XinputReaderWriter::new(&mut builder, &mut xinput_state, config);
+    let mut usb = builder.build();
+
+    let host = async {
+        mock.enumerate().await.unwrap();
+        host.await;
+    };
+    match block_on(select(usb.run(), host)) {
+        Either::First(_) => unreachable!(),
+        Either::Second(()) => {}
+    }
+}
+
+#[test]
+fn answers_like_the_reference() {
+    let mock = &MockState::new();
+    with_device(mock, Config::default(), async {
+        let input = mock.control_in(Setup::from_bytes(INPUT_CAPABILITIES)).await;
+        assert_eq!(input.unwrap(), REFERENCE_INPUT_CAPABILITIES);
+        let vibration = mock
+            .control_in(Setup::from_bytes(VIBRATION_CAPABILITIES))
+            .await;
+        assert_eq!(vibration.unwrap(), REFERENCE_VIBRATION_CAPABILITIES);
+        let serial = mock.control_in(Setup::from_bytes(SERIAL)).await;
+        assert_eq!(serial.unwrap().len(), 4);
+    });
+}
+
+#[test]
+fn masks_are_configurable() {
+    let capabilities = Capabilities {
+        buttons: ALL_BUTTONS & !(BUTTON_THUMB_LEFT | BUTTON_THUMB_RIGHT | BUTTON_XBOX),
+        left_trigger: 0x00,
+        right_trigger: 0x00,
+        sticks: [0xff00, 0xff00, 0x0000, 0x0000],
+        motors: [0x00, 0x00],
+    };
+    let config = Config {
+        capabilities: Some(capabilities),
+        serial: Some([0x12, 0x34, 0x56, 0x78]),
+        ..Default::default()
+    };
+    let mock = &MockState::new();
+    with_device(mock, config, async {
+        let input = mock.control_in(Setup::from_bytes(INPUT_CAPABILITIES)).await;
+        let expected = [
+            0x00, 0x14, 0x3f, 0xf3, 0x00, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(input.unwrap(), expected);
+        let vibration = mock
+            .control_in(Setup::from_bytes(VIBRATION_CAPABILITIES))
+            .await;
+        assert_eq!(
+            vibration.unwrap(),
+            [0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        let serial = mock.control_in(Setup::from_bytes(SERIAL)).await;
+        assert_eq!(serial.unwrap(), [0x12, 0x34, 0x56, 0x78]);
+    });
+}
+
+#[test]
+fn short_reads_get_the_start() {
+    let mock = &MockState::new();
+    with_device(mock, Config::default(), async {
+        let mut setup = Setup::from_bytes(INPUT_CAPABILITIES);
+        setup.length = 2;
+        assert_eq!(mock.control_in(setup).await, Ok(vec![0x00, 0x14]));
+    });
+}
+
+#[test]
+fn stalls_without_answers() {
+    let config = Config {
+        capabilities: None,
+        serial: None,
+        ..Default::default()
+    };
+    let mock = &MockState::new();
+    with_device(mock, config, async {
+        for packet in [INPUT_CAPABILITIES, VIBRATION_CAPABILITIES, SERIAL] {
+            let setup = Setup::from_bytes(packet);
+            assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        }
+    });
+}
+
+#[test]
+fn other_requests_stall() {
+    let mock = &MockState::new();
+    with_device(mock, Config::default(), async {
+        // to another interface, another value, another request
+        let setup = Setup::new(0xc1, 0x01, 0x0100, 0x0001, 0x14);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        let setup = Setup::new(0xc1, 0x01, 0x0200, 0x0000, 0x14);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        let setup = Setup::new(0xc1, 0x02, 0x0100, 0x0000, 0x14);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        // class instead of vendor
+        let setup = Setup::new(0xa1, 0x01, 0x0100, 0x0000, 0x14);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+    });
+}
+
+#[test]
+fn parses_only_the_control_interface() {
+    let packets = [
+        (INPUT_CAPABILITIES, Some(XinputRequest::InputCapabilities)),
+        (
+            VIBRATION_CAPABILITIES,
+            Some(XinputRequest::VibrationCapabilities),
+        ),
+        (SERIAL, Some(XinputRequest::Serial)),
+        ([0xc1, 0x01, 0x00, 0x01, 0x02, 0x00, 0x14, 0x00], None),
+        ([0xc0, 0x01, 0x00, 0x01, 0x00, 0x00, 0x14, 0x00], None),
+    ];
+    for (packet, expected) in packets {
+        let request = Request::parse(&packet);
+        assert_eq!(
+            XinputRequest::parse(&request, InterfaceNumber(0)),
+            expected,
+            "{:02x?}",
+            packet
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Chords and their timing window.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::chords::{chord, Chord, CHORD_WINDOW};
+use em_usb_pad_core::mapping::{bind, Binding, Control, Mapper, Profile, TAP_PRESS};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use embassy_time::{Duration, Instant};
+
+const LAYOUT: Layout = Layout::new(0, 0, 4);
+
+const VIEW: InputId = InputId::Direct(0);
+const MENU: InputId = InputId::Direct(1);
+const A: InputId = InputId::Direct(2);
+const B: InputId = InputId::Direct(3);
+
+const BINDINGS: [Binding; 4] = [
+    bind(VIEW, Control::View),
+    bind(MENU, Control::Menu),
+    bind(A, Control::A),
+    bind(B, Control::B),
+];
+
+const CHORDS: [Chord; 3] = [
+    chord(&[VIEW, MENU], Control::Guide),
+    Chord {
+        inputs: &[A, B],
+        control: Control::LS,
+        window: Duration::from_millis(30),
+        suppress: false,
+    },
+    // not on this board
+    chord(&[A, InputId::Direct(9)], Control::RS),
+];
+
+static PROFILES: [Profile; 1] = [Profile {
+    name: "chords",
+    bindings: &BINDINGS,
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &CHORDS,
+}];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+}
+
+const WINDOW: u64 = CHORD_WINDOW.as_millis();
+
+#[test]
+fn pressed_together_for_guide() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    // held back while the chord may complete
+    let report = mapper.update(pressed(&[VIEW]), at(0));
+    assert!(!report.button_view && !report.xbox_button);
+    let report = mapper.update(pressed(&[VIEW, MENU]), at(WINDOW));
+    assert!(report.xbox_button);
+    assert!(!report.button_view && !report.button_menu);
+
+    // still suppressed while held, the other buttons work
+    let report = mapper.update(pressed(&[VIEW, MENU, A]), at(500));
+    assert!(report.xbox_button && report.button_a);
+    assert!(!report.button_view && !report.button_menu);
+
+    // breaking the chord releases Guide, not the buttons still held
+    let report = mapper.update(pressed(&[MENU]), at(600));
+    assert!(!report.xbox_button && !report.button_menu);
+    let report = mapper.update(pressed(&[MENU, VIEW]), at(610));
+    assert!(!report.xbox_button && !report.button_menu);
+    let report = mapper.update(pressed(&[]), at(700));
+    assert_eq!(report, Default::default());
+}
+
+#[test]
+fn too_slow_for_a_chord() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.update(pressed(&[VIEW]), at(0));
+    // the window over, the button goes through late
+    let report = mapper.update(pressed(&[VIEW]), at(WINDOW - 1));
+    assert!(!report.button_view);
+    let report = mapper.update(pressed(&[VIEW]), at(WINDOW));
+    assert!(report.button_view);
+
+    let report = mapper.update(pressed(&[VIEW, MENU]), at(WINDOW + 1));
+    assert!(!report.xbox_button);
+    assert!(report.button_view && report.button_menu);
+}
+
+#[test]
+fn quick_taps_still_get_through() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.update(pressed(&[MENU]), at(1000));
+    let report = mapper.update(pressed(&[]), at(1020));
+    assert!(report.button_menu);
+    let end = 1020 + TAP_PRESS.as_millis();
+    assert!(mapper.update(pressed(&[]), at(end - 1)).button_menu);
+    assert!(!mapper.update(pressed(&[]), at(end)).button_menu);
+}
+
+#[test]
+fn chord_without_suppression() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let report = mapper.update(pressed(&[A]), at(0));
+    assert!(report.button_a && !report.thumb_click_left);
+    let report = mapper.update(pressed(&[A, B]), at(30));
+    assert!(report.button_a && report.button_b && report.thumb_click_left);
+
+    // out of its own window
+    mapper.update(pressed(&[]), at(100));
+    mapper.update(pressed(&[B]), at(200));
+    let report = mapper.update(pressed(&[A, B]), at(231));
+    assert!(report.button_a && report.button_b && !report.thumb_click_left);
+}
+
+#[test]
+fn missing_buttons_never_complete() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let report = mapper.update(pressed(&[A]), at(0));
+    assert!(report.button_a && !report.thumb_click_right);
+}
@@ -0,0 +1,319 @@
+//! Configuration requests on the mock bus, see `protocol`.
+
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+
+use em_usb_pad_core::board::InputId;
+use em_usb_pad_core::mapping::{bind, Control, DpadMode, Layer, Profile};
+use em_usb_pad_core::mock::{MockDriver, MockState, Setup, Stalled};
+use em_usb_pad_core::protocol::{
+    decode_profile, decode_settings, ConfigHandler, ConfigInfo, ConfigRequest, CONFIG_MAX_DATA,
+    CONFIG_REQUEST, PROTOCOL_VERSION,
+};
+use em_usb_pad_core::settings::{Remap, Settings, StickDeadzone, SETTINGS_VERSION};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use em_usb_pad_core::xinput::{Config, XinputReaderWriter, XinputState};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_usb::Builder;
+
+const REQUEST_IN: u8 = 0xc1;
+const REQUEST_OUT: u8 = 0x41;
+
+const PLAIN: Profile = Profile {
+    name: "plain",
+    bindings: &[
+        bind(InputId::Direct(0), Control::A),
+        bind(
+            InputId::Matrix { row: 1, col: 2 },
+            Control::LeftTrigger(128),
+        ),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+static PROFILES: [Profile; 3] = [
+    PLAIN,
+    Profile {
+        name: "layered",
+        layer: Some(Layer {
+            key: InputId::Direct(5),
+            tap: None,
+            bindings: &[bind(InputId::Direct(0), Control::Macro(3))],
+        }),
+        ..PLAIN
+    },
+    Profile {
+        name: "empty",
+        bindings: &[],
+        ..PLAIN
+    },
+];
+
+/// Settings and saves, three profiles
+#[derive(Default)]
+struct Pad {
+    settings: RefCell<Settings>,
+    saves: Cell<u32>,
+}
+
+impl ConfigHandler for Pad {
+    fn profiles(&self) -> &'static [Profile] {
+        &PROFILES
+    }
+
+    fn settings(&self) -> Settings {
+        *self.settings.borrow()
+    }
+
+    fn set_settings(&self, settings: Settings) -> bool {
+        if settings.profile as usize >= PROFILES.len() {
+            return false;
+        }
+        *self.settings.borrow_mut() = settings;
+        true
+    }
+
+    fn save(&self) -> bool {
+        self.saves.set(self.saves.get() + 1);
+        true
+    }
+}
+
+/// Enumerate a device on `mock` answering for `pad`, then run `host`.
+fn with_device(mock: &MockState, pad: &Pad, host: impl Future<Output = ()>) {
+    let mut xinput_state = XinputState::new();
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+
+    let mut config = embassy_usb::Config::new(0x045e, 0x028e);
+    config.max_packet_size_0 = 8;
+    let mut builder = Builder::new(
+        MockDriver::new(mock),
+        config,
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+    );
+    let config = Config {
+        config_handler: Some(pad),
+        ..Default::default()
+    };
+    let _xinput = XinputReaderWriter::new(&mut builder, &mut xinput_state, config);
+    let mut usb = builder.build();
+
+    let host = async {
+        mock.enumerate().await.unwrap();
+        host.await;
+    };
+    match block_on(select(usb.run(), host)) {
+        Either::First(_) => unreachable!(),
+        Either::Second(()) => {}
+    }
+}
+
+async fn request_in(mock: &MockState, request: ConfigRequest) -> Result<Vec<u8>, Stalled> {
+    let setup = Setup::new(
+        REQUEST_IN,
+        CONFIG_REQUEST,
+        request.value(),
+        ConfigRequest::index(0),
+        CONFIG_MAX_DATA as u16,
+    );
+    mock.control_in(setup).await
+}
+
+async fn request_out(mock: &MockState, request: ConfigRequest) -> Result<(), Stalled> {
+    let mut data = [0; CONFIG_MAX_DATA];
+    let len = request.encode_data(&mut data);
+    let setup = Setup::new(
+        REQUEST_OUT,
+        CONFIG_REQUEST,
+        request.value(),
+        ConfigRequest::index(0),
+        len as u16,
+    );
+    mock.control_out(setup, &data[..len]).await
+}
+
+fn settings() -> Settings {
+    Settings {
+        profile: 2,
+        dpad_mode: DpadMode::LeftStick,
+        turbo_rate: 15,
+        deadzones: [
+            None,
+            Some(StickDeadzone {
+                inner: 2000,
+                outer: 31000,
+            }),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn read_and_write_the_settings() {
+    let pad = Pad::default();
+    let mock = &MockState::new();
+    with_device(mock, &pad, async {
+        let info = request_in(mock, ConfigRequest::Info).await.unwrap();
+        assert_eq!(
+            ConfigInfo::decode(&info),
+            Some(ConfigInfo {
+                protocol: PROTOCOL_VERSION,
+                settings_version: SETTINGS_VERSION,
+                profiles: 3,
+            })
+        );
+
+        let read = request_in(mock, ConfigRequest::GetSettings).await.unwrap();
+        assert_eq!(decode_settings(&read), Some(Settings::default()));
+
+        request_out(mock, ConfigRequest::SetSettings(settings()))
+            .await
+            .unwrap();
+        let read = request_in(mock, ConfigRequest::GetSettings).await.unwrap();
+        assert_eq!(decode_settings(&read), Some(settings()));
+
+        request_out(mock, ConfigRequest::SelectProfile(1))
+            .await
+            .unwrap();
+        request_out(mock, ConfigRequest::Save).await.unwrap();
+    });
+    assert_eq!(
+        *pad.settings.borrow(),
+        Settings {
+            profile: 1,
+            ..settings()
+        }
+    );
+    assert_eq!(pad.saves.get(), 1);
+}
+
+#[test]
+fn remaps_go_through() {
+    let pad = Pad::default();
+    let mock = &MockState::new();
+    let mut settings = settings();
+    settings.set_remap(Remap {
+        profile: 1,
+        input: InputId::Matrix { row: 3, col: 0 },
+        control: Control::NextProfile,
+    });
+    with_device(mock, &pad, async {
+        request_out(mock, ConfigRequest::SetSettings(settings))
+            .await
+            .unwrap();
+        let read = request_in(mock, ConfigRequest::GetSettings).await.unwrap();
+        assert_eq!(decode_settings(&read), Some(settings));
+    });
+    assert_eq!(*pad.settings.borrow(), settings);
+}
+
+#[test]
+fn dump_the_profiles() {
+    let pad = Pad::default();
+    let mock = &MockState::new();
+    with_device(mock, &pad, async {
+        let read = request_in(mock, ConfigRequest::GetProfile(0))
+            .await
+            .unwrap();
+        let dump = decode_profile(&read).unwrap();
+        assert_eq!(dump.name, "plain");
+        assert!(dump.bindings.iter().eq(PLAIN.bindings.iter().copied()));
+        assert_eq!(dump.layer, None);
+
+        let read = request_in(mock, ConfigRequest::GetProfile(1))
+            .await
+            .unwrap();
+        let dump = decode_profile(&read).unwrap();
+        assert_eq!(dump.name, "layered");
+        let (key, bindings) = dump.layer.unwrap();
+        assert_eq!(key, InputId::Direct(5));
+        assert_eq!(
+            bindings.iter().collect::<Vec<_>>(),
+            [bind(InputId::Direct(0), Control::Macro(3))]
+        );
+
+        let read = request_in(mock, ConfigRequest::GetProfile(2))
+            .await
+            .unwrap();
+        assert_eq!(read, [5, b'e', b'm', b'p', b't', b'y', 0, 0]);
+
+        assert_eq!(
+            request_in(mock, ConfigRequest::GetProfile(3)).await,
+            Err(Stalled)
+        );
+    });
+}
+
+#[test]
+fn older_settings_are_accepted() {
+    let pad = Pad::default();
+    let mock = &MockState::new();
+    with_device(mock, &pad, async {
+        let setup = Setup::new(REQUEST_OUT, CONFIG_REQUEST, 0x0200, 0x0100, 4);
+        mock.control_out(setup, &[1, 2, 2, 30]).await.unwrap();
+    });
+    assert_eq!(
+        *pad.settings.borrow(),
+        Settings {
+            profile: 2,
+            dpad_mode: DpadMode::RightStick,
+            turbo_rate: 30,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn bad_requests_stall() {
+    let pad = Pad::default();
+    let mock = &MockState::new();
+    with_device(mock, &pad, async {
+        // another version of the protocol, but for the info
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x0100, 0x0200, 16);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x0000, 0x0200, 16);
+        assert_eq!(mock.control_in(setup).await.unwrap()[0], PROTOCOL_VERSION);
+
+        // unknown command, or in the wrong direction
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x4200, 0x0100, 16);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x0400, 0x0100, 16);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+        let setup = Setup::new(REQUEST_OUT, CONFIG_REQUEST, 0x0100, 0x0100, 0);
+        assert_eq!(mock.control_out(setup, &[]).await, Err(Stalled));
+
+        // settings that don't decode, or that the pad refuses
+        let setup = Setup::new(REQUEST_OUT, CONFIG_REQUEST, 0x0200, 0x0100, 4);
+        assert_eq!(mock.control_out(setup, &[1, 0, 9, 10]).await, Err(Stalled));
+        let setup = Setup::new(REQUEST_OUT, CONFIG_REQUEST, 0x0200, 0x0100, 2);
+        assert_eq!(mock.control_out(setup, &[9, 0]).await, Err(Stalled));
+        assert_eq!(
+            request_out(mock, ConfigRequest::SelectProfile(3)).await,
+            Err(Stalled)
+        );
+
+        // to the other interfaces
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x0000, 0x0101, 16);
+        assert_eq!(mock.control_in(setup).await, Err(Stalled));
+
+        // a short read gets the start
+        let setup = Setup::new(REQUEST_IN, CONFIG_REQUEST, 0x0000, 0x0100, 1);
+        assert_eq!(mock.control_in(setup).await, Ok(vec![PROTOCOL_VERSION]));
+    });
+    assert_eq!(*pad.settings.borrow(), Settings::default());
+}
@@ -0,0 +1,106 @@
+//! Debounce recorded switch traces, scanned at different rates.
+
+use em_usb_pad_core::board::InputStates;
+use em_usb_pad_core::debounce::{Debounce, Debouncer};
+use embassy_time::{Duration, Instant};
+
+/// Edges of a cheap tactile switch on button 0, in µs
+const TRACE: &[(u64, bool)] = &[
+    // pressed, bouncing for 1.3 ms
+    (10_000, true),
+    (10_150, false),
+    (10_400, true),
+    (10_900, false),
+    (11_300, true),
+    // released, bouncing for 0.7 ms
+    (60_000, false),
+    (60_200, true),
+    (60_700, false),
+    // a 100 µs glitch, e.g. ESD on a long wire
+    (90_000, true),
+    (90_100, false),
+];
+
+/// The trace as read at `time`
+fn level(time: u64) -> bool {
+    TRACE
+        .iter()
+        .take_while(|(edge, _)| *edge <= time)
+        .last()
+        .is_some_and(|(_, pressed)| *pressed)
+}
+
+/// Scan the trace every `period` µs for 120 ms, returns the debounced edges.
+fn replay(config: Debounce, period: u64) -> Vec<(u64, bool)> {
+    let mut debouncer = Debouncer::new(config);
+    let mut edges = Vec::new();
+    let mut previous = InputStates::default();
+    for time in (0..120_000).step_by(period as usize) {
+        let raw = InputStates(level(time) as u64);
+        let states = debouncer.update(raw, Instant::from_micros(time));
+        assert_eq!(states, debouncer.states());
+        if states != previous {
+            edges.push((time, states.is_pressed(0)));
+        }
+        previous = states;
+    }
+    edges
+}
+
+const FIVE_MS: Duration = Duration::from_millis(5);
+
+#[test]
+fn off_lets_the_bounces_through() {
+    let edges = replay(Debounce::Off, 250);
+    let presses = edges.iter().filter(|(_, pressed)| *pressed).count();
+    assert!(presses > 3, "{:?}", edges);
+}
+
+#[test]
+fn eager() {
+    let config = Debounce::Eager { lockout: FIVE_MS };
+    // no latency, the glitch goes through
+    let expected = [
+        (10_000, true),
+        (60_000, false),
+        (90_000, true),
+        (95_000, false),
+    ];
+    assert_eq!(replay(config, 1000), expected);
+    assert_eq!(replay(config, 250), expected);
+}
+
+#[test]
+fn deferred() {
+    let config = Debounce::Deferred { stable: FIVE_MS };
+    // 5 ms after the last bounce seen
+    assert_eq!(replay(config, 1000), [(17_000, true), (65_000, false)]);
+    assert_eq!(replay(config, 250), [(16_500, true), (65_750, false)]);
+}
+
+#[test]
+fn integrator() {
+    let config = Debounce::Integrator { time: FIVE_MS };
+    assert_eq!(replay(config, 1000), [(16_000, true), (64_000, false)]);
+    assert_eq!(replay(config, 250), [(16_250, true), (65_500, false)]);
+}
+
+#[test]
+fn buttons_are_debounced_on_their_own() {
+    let mut debouncer = Debouncer::new(Debounce::Deferred { stable: FIVE_MS });
+    let at = Instant::from_millis;
+    debouncer.update(InputStates(0b01), at(0));
+    debouncer.update(InputStates(0b11), at(3));
+    assert_eq!(
+        debouncer.update(InputStates(0b11), at(5)),
+        InputStates(0b01)
+    );
+    assert_eq!(
+        debouncer.update(InputStates(0b10), at(8)),
+        InputStates(0b11)
+    );
+    assert_eq!(
+        debouncer.update(InputStates(0b10), at(13)),
+        InputStates(0b10)
+    );
+}
@@ -0,0 +1,312 @@
+//! Build the Xinput device on the mock driver and compare its descriptors,
+//! byte for byte, with reference descriptors of a wired Xbox 360 controller
+//! from ArduinoXinput. They live in `tests/golden/`, and aren't a capture of
+//! a genuine controller.
+
+use std::fmt::Write;
+use std::future::Future;
+
+use em_usb_pad_core::mock::{
+    MockDriver, MockState, Setup, Stalled, DESCRIPTOR_TYPE_CONFIGURATION, DESCRIPTOR_TYPE_DEVICE,
+    DESCRIPTOR_TYPE_STRING,
+};
+use em_usb_pad_core::msos::{DEFAULT_VENDOR_CODE, MS_OS_STRING_INDEX};
+use em_usb_pad_core::xinput::{
+    usb_config, Config, XinputReaderWriter, XinputState, USB_XINPUT_PID, USB_XINPUT_VID,
+};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_usb::Builder;
+
+const GOLDEN_DEVICE: &str = include_str!("golden/arduino_xinput_device.hex");
+const GOLDEN_CONFIGURATION: &str = include_str!("golden/arduino_xinput_configuration.hex");
+const GOLDEN_STRINGS: &str = include_str!("golden/arduino_xinput_strings.txt");
+
+const LANG_ID_EN_US: u16 = 0x0409;
+
+/// Differences with the reference we cannot or don't want to avoid.
+/// (offset in the device descriptor, reference value, our value, why)
+const KNOWN_DEVICE_DEVIATIONS: &[(usize, u8, u8, &str)] = &[(
+    2,
+    0x00,
+    0x10,
+    "embassy-usb always serves a BOS descriptor, so it reports bcdUSB 2.10",
+)];
+
+struct Dump {
+    device: Vec<u8>,
+    configuration: Vec<u8>,
+    strings: Vec<(u8, Option<String>)>,
+}
+
+/// Parse a hex dump, `#` starts a comment.
+fn parse_hex(text: &str) -> Vec<u8> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(|line| line.split_whitespace())
+        .map(|byte| u8::from_str_radix(byte, 16).expect("bad hex byte in golden file"))
+        .collect()
+}
+
+/// Parse `index: text` lines, `#` at line start is a comment.
+fn parse_strings(text: &str) -> Vec<(u8, String)> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (index, string) = line.split_once(": ").expect("bad line in golden file");
+            (index.parse().unwrap(), string.to_string())
+        })
+        .collect()
+}
+
+/// Build the device with the firmware's USB config, but the reference strings, then enumerate it.
+fn dump_reference_lookalike() -> Dump {
+    let golden_strings = parse_strings(GOLDEN_STRINGS);
+    let string = |index: u8| {
+        golden_strings
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, s)| s.as_str())
+    };
+
+    let mock = MockState::new();
+    let mut xinput_state = XinputState::new();
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+
+    let config = Config {
+        vendor_string: string(1),
+        product_string: string(2),
+        serial_number_string: string(3),
+        security_string: string(4),
+        ..Default::default()
+    };
+    let mut builder = Builder::new(
+        MockDriver::new(&mock),
+        usb_config(USB_XINPUT_VID, USB_XINPUT_PID, &config),
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+    );
+    let _xinput = XinputReaderWriter::new(&mut builder, &mut xinput_state, config);
+    let mut usb = builder.build();
+
+    let host = async {
+        let descriptors = mock.enumerate().await.expect("enumeration failed");
+        let mut strings = Vec::new();
+        for (index, _) in &golden_strings {
+            strings.push((*index, mock.get_string(*index, LANG_ID_EN_US).await));
+        }
+        Dump {
+            device: descriptors.device,
+            configuration: descriptors.configuration,
+            strings,
+        }
+    };
+    match block_on(select(usb.run(), host)) {
+        Either::First(_) => unreachable!(),
+        Either::Second(dump) => dump,
+    }
+}
+
+fn descriptor_name(descriptor_type: u8) -> &'static str {
+    match descriptor_type {
+        0x01 => "DEVICE",
+        0x02 => "CONFIGURATION",
+        0x04 => "INTERFACE",
+        0x05 => "ENDPOINT",
+        0x21 => "XINPUT",
+        0x41 => "XINPUT SECURITY",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Split a blob into descriptors by following `bLength`.
+fn split_descriptors(mut blob: &[u8]) -> Vec<&[u8]> {
+    let mut descriptors = Vec::new();
+    while !blob.is_empty() {
+        let length = (blob[0] as usize).clamp(1, blob.len());
+        descriptors.push(&blob[..length]);
+        blob = &blob[length..];
+    }
+    descriptors
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Descriptor by descriptor listing, with the mismatching ones marked with `!`.
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let expected = split_descriptors(expected);
+    let actual = split_descriptors(actual);
+    let mut out = String::from("expected = reference, actual = em-usb-pad\n");
+    let mut offset = 0;
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        let name = descriptor_name(e.or(a).and_then(|d| d.get(1)).copied().unwrap_or(0));
+        match (e, a) {
+            (Some(e), Some(a)) if e == a => {
+                writeln!(out, "  @{:04x} {:<16} {}", offset, name, hex(e)).unwrap();
+            }
+            _ => {
+                let e = e.map_or("<missing>".to_string(), |d| hex(d));
+                let a = a.map_or("<missing>".to_string(), |d| hex(d));
+                writeln!(out, "! @{:04x} {:<16} expected {}", offset, name, e).unwrap();
+                writeln!(out, "! @{:04x} {:<16} actual   {}", offset, name, a).unwrap();
+            }
+        }
+        offset += e.or(a).map_or(0, |d| d.len());
+    }
+    out
+}
+
+fn assert_descriptors_eq(what: &str, expected: &[u8], actual: &[u8]) {
+    if expected != actual {
+        panic!("{} descriptor mismatch\n{}", what, diff(expected, actual));
+    }
+}
+
+#[test]
+fn device_descriptor_matches_reference() {
+    let mut expected = parse_hex(GOLDEN_DEVICE);
+    for (offset, reference, ours, _why) in KNOWN_DEVICE_DEVIATIONS {
+        assert_eq!(expected[*offset], *reference);
+        expected[*offset] = *ours;
+    }
+    let dump = dump_reference_lookalike();
+    assert_eq!(dump.device[1], DESCRIPTOR_TYPE_DEVICE);
+    assert_descriptors_eq("device", &expected, &dump.device);
+}
+
+#[test]
+fn configuration_descriptor_matches_reference() {
+    let expected = parse_hex(GOLDEN_CONFIGURATION);
+    let dump = dump_reference_lookalike();
+    assert_eq!(dump.configuration[1], DESCRIPTOR_TYPE_CONFIGURATION);
+    assert_descriptors_eq("configuration", &expected, &dump.configuration);
+}
+
+#[test]
+fn string_descriptors_match_reference() {
+    let dump = dump_reference_lookalike();
+    for ((index, expected), (_, actual)) in parse_strings(GOLDEN_STRINGS).iter().zip(&dump.strings)
+    {
+        assert_eq!(
+            Some(expected),
+            actual.as_ref(),
+            "string descriptor {} mismatch",
+            index
+        );
+    }
+}
+
+/// Build the device with the IDs of pid.codes' test PID, then run `host`.
+fn with_custom_ids<T>(
+    mock: &MockState,
+    ms_os_vendor_code: Option<u8>,
+    host: impl Future<Output = T>,
+) -> T {
+    let mut xinput_state = XinputState::new();
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+
+    let config = Config {
+        ms_os_vendor_code,
+        ..Default::default()
+    };
+    let mut builder = Builder::new(
+        MockDriver::new(mock),
+        usb_config(0x1209, 0x0001, &config),
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+    );
+    let _xinput = XinputReaderWriter::new(&mut builder, &mut xinput_state, config);
+    let mut usb = builder.build();
+
+    match block_on(select(usb.run(), host)) {
+        Either::First(_) => unreachable!(),
+        Either::Second(result) => result,
+    }
+}
+
+/// The request for the extended compat ID descriptor, as Windows sends it
+fn compat_id_request(vendor_code: u8, length: u16) -> Setup {
+    Setup::new(0xc0, vendor_code, 0x0000, 0x0004, length)
+}
+
+#[test]
+fn ms_os_descriptors_bind_xusb() {
+    let mock = &MockState::new();
+    let configuration = with_custom_ids(mock, Some(DEFAULT_VENDOR_CODE), async {
+        let descriptors = mock.enumerate().await.unwrap();
+
+        // asked with language 0, "MSFT100", the vendor code and a pad byte
+        let string = mock
+            .get_descriptor(DESCRIPTOR_TYPE_STRING, MS_OS_STRING_INDEX, 0, 0x12)
+            .await
+            .unwrap();
+        let mut expected = vec![0x12, DESCRIPTOR_TYPE_STRING];
+        expected.extend("MSFT100".encode_utf16().flat_map(u16::to_le_bytes));
+        expected.extend([DEFAULT_VENDOR_CODE, 0x00]);
+        assert_eq!(string, expected);
+
+        // the header first, then all of it
+        let header = mock
+            .control_in(compat_id_request(DEFAULT_VENDOR_CODE, 0x10))
+            .await
+            .unwrap();
+        assert_eq!(
+            header,
+            parse_hex("28 00 00 00  00 01  04 00  01  00 00 00 00 00 00 00")
+        );
+        let compat_id = mock
+            .control_in(compat_id_request(DEFAULT_VENDOR_CODE, 0x28))
+            .await
+            .unwrap();
+        let function = parse_hex(
+            "00 01                     # interface 0, reserved
+             58 55 53 42 31 30 00 00   # XUSB10
+             00 00 00 00 00 00 00 00   # no sub compatible ID
+             00 00 00 00 00 00",
+        );
+        assert_eq!(compat_id[..16], header);
+        assert_eq!(compat_id[16..], function);
+
+        // no extended properties, nor another vendor code
+        let properties = Setup::new(0xc0, DEFAULT_VENDOR_CODE, 0x0000, 0x0005, 0x0a);
+        assert_eq!(mock.control_in(properties).await, Err(Stalled));
+        let other = compat_id_request(DEFAULT_VENDOR_CODE + 1, 0x10);
+        assert_eq!(mock.control_in(other).await, Err(Stalled));
+        descriptors.configuration
+    });
+
+    // the interfaces stay those of the reference
+    assert_descriptors_eq(
+        "configuration",
+        &parse_hex(GOLDEN_CONFIGURATION),
+        &configuration,
+    );
+}
+
+#[test]
+fn ms_os_descriptors_are_optional() {
+    let mock = &MockState::new();
+    with_custom_ids(mock, None, async {
+        mock.enumerate().await.unwrap();
+        assert_eq!(mock.get_string(MS_OS_STRING_INDEX, 0).await, None);
+        let request = compat_id_request(DEFAULT_VENDOR_CODE, 0x10);
+        assert_eq!(mock.control_in(request).await, Err(Stalled));
+    });
+}
@@ -0,0 +1,122 @@
+//! The D-pad buttons driving the D-pad or either stick.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::mapping::{bind, Control, DpadMode, Mapper, Profile, STICK_DIAGONAL};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use embassy_time::Instant;
+
+const LAYOUT: Layout = Layout::new(0, 0, 7);
+
+const UP: InputId = InputId::Direct(0);
+const DOWN: InputId = InputId::Direct(1);
+const LEFT: InputId = InputId::Direct(2);
+const RIGHT: InputId = InputId::Direct(3);
+const VIEW: InputId = InputId::Direct(4);
+const MENU: InputId = InputId::Direct(5);
+const A: InputId = InputId::Direct(6);
+
+const FIGHT_STICK: Profile = Profile {
+    name: "fight stick",
+    bindings: &[
+        bind(UP, Control::DpadUp),
+        bind(DOWN, Control::DpadDown),
+        bind(LEFT, Control::DpadLeft),
+        bind(RIGHT, Control::DpadRight),
+        bind(VIEW, Control::View),
+        bind(MENU, Control::Menu),
+        bind(A, Control::A),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::NEUTRAL,
+    dpad_hotkey: &[VIEW, MENU],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+static PROFILES: [Profile; 1] = [FIGHT_STICK];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+#[test]
+fn routes_to_each_target() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    assert_eq!(mapper.dpad_mode(), DpadMode::Dpad);
+    let report = mapper.update(pressed(&[UP]), Instant::from_millis(0));
+    assert!(report.dpad_up);
+    assert_eq!((report.js_left_y, report.js_right_y), (0, 0));
+
+    mapper.set_dpad_mode(DpadMode::LeftStick);
+    let report = mapper.update(pressed(&[UP]), Instant::from_millis(0));
+    assert!(!report.dpad_up);
+    assert_eq!((report.js_left_x, report.js_left_y), (0, i16::MAX));
+    assert_eq!((report.js_right_x, report.js_right_y), (0, 0));
+
+    mapper.set_dpad_mode(DpadMode::RightStick);
+    let report = mapper.update(pressed(&[LEFT]), Instant::from_millis(0));
+    assert!(!report.dpad_left);
+    assert_eq!((report.js_left_x, report.js_left_y), (0, 0));
+    assert_eq!((report.js_right_x, report.js_right_y), (i16::MIN, 0));
+}
+
+#[test]
+fn diagonals_stay_on_the_circle() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.set_dpad_mode(DpadMode::LeftStick);
+    // (held, x, y)
+    let table = [
+        (&[UP, RIGHT][..], STICK_DIAGONAL, STICK_DIAGONAL),
+        (&[DOWN, LEFT], -STICK_DIAGONAL, -STICK_DIAGONAL),
+        (&[UP, LEFT], -STICK_DIAGONAL, STICK_DIAGONAL),
+        // cleaned to neutral horizontally, back to full deflection
+        (&[DOWN, LEFT, RIGHT], 0, i16::MIN),
+    ];
+    for (held, x, y) in table {
+        let report = mapper.update(pressed(held), Instant::from_millis(0));
+        assert_eq!((report.js_left_x, report.js_left_y), (x, y), "{:?}", held);
+    }
+    let (x, y) = (STICK_DIAGONAL as i32, STICK_DIAGONAL as i32);
+    assert!((x * x + y * y - 32767 * 32767).abs() < 2 * 32767);
+}
+
+#[test]
+fn hotkey_cycles_the_modes() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let modes = [DpadMode::LeftStick, DpadMode::RightStick, DpadMode::Dpad];
+    for mode in modes {
+        mapper.update(pressed(&[VIEW]), Instant::from_millis(0));
+        let report = mapper.update(pressed(&[VIEW, MENU]), Instant::from_millis(0));
+        assert_eq!(mapper.dpad_mode(), mode);
+        // the hotkey buttons are held back
+        assert!(!report.button_view && !report.button_menu);
+        mapper.update(pressed(&[]), Instant::from_millis(0));
+    }
+
+    // holding it doesn't switch again, the other buttons still work
+    mapper.update(pressed(&[VIEW, MENU]), Instant::from_millis(0));
+    let report = mapper.update(pressed(&[VIEW, MENU, A, UP]), Instant::from_millis(0));
+    assert_eq!(mapper.dpad_mode(), DpadMode::LeftStick);
+    assert!(report.button_a);
+    assert_eq!(report.js_left_y, i16::MAX);
+
+    // a single hotkey button is a button
+    let report = mapper.update(pressed(&[MENU]), Instant::from_millis(0));
+    assert!(report.button_menu);
+    assert_eq!(mapper.dpad_mode(), DpadMode::LeftStick);
+}
+
+#[test]
+fn stateless_report_uses_the_dpad() {
+    let report = FIGHT_STICK.report(LAYOUT, pressed(&[UP, VIEW, MENU]));
+    assert!(report.dpad_up && report.button_view && report.button_menu);
+    assert_eq!(report.js_left_y, 0);
+}
@@ -0,0 +1,198 @@
+//! Every ghost a 4x3 matrix without diodes can make.
+
+use em_usb_pad_core::board::{BoardInfo, InputId, InputStates, Layout};
+use em_usb_pad_core::ghosting::{ghost_keys, AntiGhost, GhostPolicy};
+
+const LAYOUT: Layout = Layout::new(4, 3, 1);
+
+const BOARD: BoardInfo = BoardInfo {
+    name: "keypad",
+    layout: LAYOUT,
+    analog: &[],
+    player_leds: false,
+    rumble: false,
+    diodes: false,
+};
+
+fn key(row: usize, col: usize) -> usize {
+    LAYOUT
+        .index(InputId::Matrix {
+            row: row as u8,
+            col: col as u8,
+        })
+        .unwrap()
+}
+
+fn states(keys: &[(usize, usize)]) -> InputStates {
+    let mut states = InputStates::default();
+    for &(row, col) in keys {
+        states.set(key(row, col), true);
+    }
+    states
+}
+
+/// What a scan reads without diodes: a row reads low when the driven column
+/// reaches it through the pressed keys, whichever way the current goes.
+fn read(pressed: &[(usize, usize)]) -> InputStates {
+    let mut read = InputStates::default();
+    for col in 0..LAYOUT.cols as usize {
+        // (rows, cols) reached from the driven column
+        let (mut rows, mut cols) = (0u32, 1u32 << col);
+        loop {
+            let (before_rows, before_cols) = (rows, cols);
+            for &(r, c) in pressed {
+                if cols & 1 << c != 0 || rows & 1 << r != 0 {
+                    rows |= 1 << r;
+                    cols |= 1 << c;
+                }
+            }
+            if (rows, cols) == (before_rows, before_cols) {
+                break;
+            }
+        }
+        for row in 0..LAYOUT.rows as usize {
+            if rows & 1 << row != 0 {
+                read.set(key(row, col), true);
+            }
+        }
+    }
+    read
+}
+
+type Key = (usize, usize);
+
+/// Every rectangle, and the corner left out of it
+fn rectangles() -> Vec<([Key; 3], Key)> {
+    let mut all = Vec::new();
+    for r1 in 0..4 {
+        for r2 in r1 + 1..4 {
+            for c1 in 0..3 {
+                for c2 in c1 + 1..3 {
+                    let corners = [(r1, c1), (r1, c2), (r2, c1), (r2, c2)];
+                    for ghost in corners {
+                        let held: Vec<_> = corners.into_iter().filter(|&k| k != ghost).collect();
+                        all.push(([held[0], held[1], held[2]], ghost));
+                    }
+                }
+            }
+        }
+    }
+    all
+}
+
+#[test]
+fn three_corners_make_a_ghost() {
+    let all = rectangles();
+    assert_eq!(all.len(), 6 * 3 * 4);
+    for (held, ghost) in all {
+        let raw = read(&held);
+        assert!(raw.is_pressed(key(ghost.0, ghost.1)), "{:?}", held);
+        let mut rectangle = states(&held);
+        rectangle.set(key(ghost.0, ghost.1), true);
+        assert_eq!(ghost_keys(LAYOUT, raw), rectangle, "{:?}", held);
+    }
+}
+
+#[test]
+fn no_rectangle_no_ghost() {
+    let patterns: [&[(usize, usize)]; 6] = [
+        &[(0, 0), (0, 1), (0, 2)],
+        &[(0, 1), (1, 1), (2, 1), (3, 1)],
+        &[(0, 0), (1, 1), (2, 2)],
+        &[(0, 0), (0, 1), (1, 2)],
+        &[(1, 0), (2, 0), (3, 1), (3, 2)],
+        &[],
+    ];
+    for held in patterns {
+        let raw = read(held);
+        assert_eq!(raw, states(held));
+        assert_eq!(
+            ghost_keys(LAYOUT, raw),
+            InputStates::default(),
+            "{:?}",
+            held
+        );
+    }
+}
+
+#[test]
+fn direct_buttons_never_ghost() {
+    let mut raw = read(&[(0, 0), (0, 1), (1, 0)]);
+    raw.set(LAYOUT.index(InputId::Direct(0)).unwrap(), true);
+    let ghosts = ghost_keys(LAYOUT, raw);
+    assert!(!ghosts.is_pressed(LAYOUT.index(InputId::Direct(0)).unwrap()));
+    assert_eq!(ghosts.0.count_ones(), 4);
+}
+
+/// Press the keys one after the other, returns the last filtered states.
+fn press_in_turn(filter: &mut AntiGhost, keys: &[(usize, usize)]) -> InputStates {
+    let mut filtered = InputStates::default();
+    for n in 1..=keys.len() {
+        filtered = filter.filter(read(&keys[..n]));
+    }
+    filtered
+}
+
+#[test]
+fn hold_back_keeps_the_keys_held_before() {
+    for (held, ghost) in rectangles() {
+        let mut filter = AntiGhost::new(&BOARD, GhostPolicy::HoldBack);
+        // the third key shows up with its ghost, they can't be told apart
+        assert_eq!(
+            press_in_turn(&mut filter, &held),
+            states(&held[..2]),
+            "{:?}",
+            held
+        );
+        assert_eq!(filter.ghosts().0.count_ones(), 4);
+
+        // releasing a key breaks the rectangle, the ghost goes with it
+        let filtered = filter.filter(read(&held[1..]));
+        assert_eq!(filtered, states(&held[1..]), "{:?}", held);
+        assert!(!filtered.is_pressed(key(ghost.0, ghost.1)));
+        assert_eq!(filter.ghosts(), InputStates::default());
+    }
+}
+
+#[test]
+fn hold_back_a_real_fourth_key() {
+    let keys = [(0, 0), (0, 2), (3, 0), (3, 2)];
+    let mut filter = AntiGhost::new(&BOARD, GhostPolicy::HoldBack);
+    assert_eq!(press_in_turn(&mut filter, &keys), states(&keys[..2]));
+    // any three of them still read as four, nothing changes
+    assert_eq!(filter.filter(read(&keys[1..])), states(&keys[..2]));
+    assert_eq!(filter.filter(read(&keys[2..])), states(&keys[2..]));
+}
+
+#[test]
+fn release_drops_the_rectangle() {
+    for (held, _) in rectangles() {
+        let mut filter = AntiGhost::new(&BOARD, GhostPolicy::Release);
+        let mut with_direct = read(&held);
+        with_direct.set(LAYOUT.index(InputId::Direct(0)).unwrap(), true);
+        let filtered = filter.filter(with_direct);
+        assert_eq!(
+            filtered,
+            InputStates(1 << LAYOUT.index(InputId::Direct(0)).unwrap())
+        );
+    }
+}
+
+#[test]
+fn off_and_diodes_report_everything() {
+    let held = [(1, 0), (1, 1), (2, 1)];
+    let raw = read(&held);
+    assert_eq!(raw.0.count_ones(), 4);
+
+    let mut off = AntiGhost::new(&BOARD, GhostPolicy::Off);
+    assert_eq!(off.filter(raw), raw);
+
+    let diodes = BoardInfo {
+        diodes: true,
+        ..BOARD
+    };
+    let mut filter = AntiGhost::new(&diodes, GhostPolicy::Release);
+    assert_eq!(filter.policy(), GhostPolicy::Off);
+    assert_eq!(filter.filter(raw), raw);
+    assert_eq!(filter.ghosts(), InputStates::default());
+}
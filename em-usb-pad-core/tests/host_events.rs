@@ -0,0 +1,109 @@
+//! Deliver the messages sent by the host on the mock bus to an event handler.
+
+use em_usb_pad_core::mock::{MockDriver, MockState};
+use em_usb_pad_core::xinput::{
+    Config, XinputEventHandler, XinputHostStatus, XinputLedPattern, XinputRawPacket,
+    XinputReaderWriter, XinputRumbleState, XinputState,
+};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_usb::Builder;
+
+const EP_OUT_CONTROL: u8 = 0x01;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Rumble(XinputRumbleState),
+    Led(XinputLedPattern),
+    Unknown(Vec<u8>),
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: Vec<Event>,
+}
+
+impl XinputEventHandler for Recorder {
+    fn on_rumble(&mut self, rumble: XinputRumbleState) {
+        self.events.push(Event::Rumble(rumble));
+    }
+
+    fn on_led(&mut self, pattern: XinputLedPattern) {
+        self.events.push(Event::Led(pattern));
+    }
+
+    fn on_unknown(&mut self, packet: XinputRawPacket) {
+        self.events.push(Event::Unknown(packet.as_bytes().to_vec()));
+    }
+}
+
+#[test]
+fn host_messages_reach_the_handler() {
+    let mock = MockState::new();
+    let mut xinput_state = XinputState::new();
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+
+    let mut config = embassy_usb::Config::new(0x045e, 0x028e);
+    config.max_packet_size_0 = 8;
+    let mut builder = Builder::new(
+        MockDriver::new(&mock),
+        config,
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+    );
+    let xinput = XinputReaderWriter::new(&mut builder, &mut xinput_state, Config::default());
+    let mut usb = builder.build();
+    let (mut reader, _writer) = xinput.split();
+
+    let mut recorder = Recorder::default();
+    let host = async {
+        mock.enumerate().await.unwrap();
+
+        // the stream flavor first
+        mock.send_out(EP_OUT_CONTROL, &[0x01, 0x03, 0x06]);
+        assert_eq!(
+            reader.read_status().await,
+            XinputHostStatus::Led(XinputLedPattern::On1)
+        );
+
+        // then the handler flavor, malformed messages are skipped
+        mock.send_out(
+            EP_OUT_CONTROL,
+            &[0x00, 0x08, 0x00, 0xc0, 0x20, 0x00, 0x00, 0x00],
+        );
+        mock.send_out(EP_OUT_CONTROL, &[0x01, 0x03, 0x42]);
+        mock.send_out(EP_OUT_CONTROL, &[0x01]);
+        mock.send_out(EP_OUT_CONTROL, &[0x01, 0x03, 0x0a]);
+        mock.send_out(
+            EP_OUT_CONTROL,
+            &[0x02, 0x08, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+        );
+        let run = reader.run(&mut recorder);
+        let drained = async {
+            while !mock.out_drained(EP_OUT_CONTROL) {
+                embassy_futures::yield_now().await;
+            }
+            // let the last message get dispatched
+            embassy_futures::yield_now().await;
+        };
+        select(run, drained).await;
+    };
+    match block_on(select(usb.run(), host)) {
+        Either::First(_) => unreachable!(),
+        Either::Second(()) => {}
+    }
+
+    assert_eq!(
+        recorder.events,
+        [
+            Event::Rumble(XinputRumbleState::new(0xc0, 0x20)),
+            Event::Led(XinputLedPattern::Rotate),
+            Event::Unknown(vec![0x02, 0x08, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00]),
+        ]
+    );
+}
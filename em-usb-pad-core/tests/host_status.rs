@@ -0,0 +1,148 @@
+//! Parse host to device messages of interface 0.
+//!
+//! The tables are synthetic: written by hand after the layout the Linux xpad
+//! driver builds its messages with (`xpad_play_effect` for the rumble,
+//! `xpad_send_led_command` for the LED), not captured on a bus.
+
+use em_usb_pad_core::xinput::{
+    HostStatusError, XinputHostStatus, XinputLedPattern, XinputRumbleState,
+};
+
+/// Messages laid out like xpad's, with what they should parse into.
+const VALID: &[(&[u8], Expected)] = &[
+    // rumble, (type, length, 0, left, right, 0, 0, 0)
+    (
+        &[0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        Expected::Rumble(0x00, 0x00),
+    ),
+    (
+        &[0x00, 0x08, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00],
+        Expected::Rumble(0xff, 0x00),
+    ),
+    (
+        &[0x00, 0x08, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00],
+        Expected::Rumble(0x00, 0xff),
+    ),
+    (
+        &[0x00, 0x08, 0x00, 0x80, 0x40, 0x00, 0x00, 0x00],
+        Expected::Rumble(0x80, 0x40),
+    ),
+    // rumble without the trailing padding
+    (
+        &[0x00, 0x05, 0x00, 0x12, 0x34],
+        Expected::Rumble(0x12, 0x34),
+    ),
+    // LED, (type, length, pattern)
+    (&[0x01, 0x03, 0x00], Expected::Led(XinputLedPattern::Off)),
+    (&[0x01, 0x03, 0x02], Expected::Led(XinputLedPattern::Flash1)),
+    (&[0x01, 0x03, 0x06], Expected::Led(XinputLedPattern::On1)),
+    (&[0x01, 0x03, 0x0a], Expected::Led(XinputLedPattern::Rotate)),
+    (
+        &[0x01, 0x03, 0x0d],
+        Expected::Led(XinputLedPattern::Alternate),
+    ),
+    // not understood yet, kept raw
+    (
+        &[0x02, 0x08, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+        Expected::Type02,
+    ),
+    (&[0x03, 0x03, 0x03], Expected::Type03),
+    (&[0x08, 0x03, 0x00], Expected::Unknown),
+    (&[0xff, 0x02], Expected::Unknown),
+];
+
+const INVALID: &[(&[u8], HostStatusError)] = &[
+    (&[], HostStatusError::TooShort { len: 0 }),
+    (&[0x00], HostStatusError::TooShort { len: 1 }),
+    (
+        &[0x00, 0x08, 0x00, 0xff],
+        HostStatusError::LengthMismatch {
+            declared: 8,
+            actual: 4,
+        },
+    ),
+    (
+        &[0x01, 0x02, 0x06],
+        HostStatusError::LengthMismatch {
+            declared: 2,
+            actual: 3,
+        },
+    ),
+    (
+        &[0x00, 0x04, 0x00, 0xff],
+        HostStatusError::Truncated { kind: 0x00, len: 4 },
+    ),
+    (
+        &[0x01, 0x02],
+        HostStatusError::Truncated { kind: 0x01, len: 2 },
+    ),
+    (
+        &[0x01, 0x03, 0x0e],
+        HostStatusError::InvalidLedPattern(0x0e),
+    ),
+    (
+        &[0x01, 0x03, 0xff],
+        HostStatusError::InvalidLedPattern(0xff),
+    ),
+    (
+        &[
+            0x02, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+        HostStatusError::TooLong { len: 33 },
+    ),
+];
+
+#[derive(Debug)]
+enum Expected {
+    Rumble(u8, u8),
+    Led(XinputLedPattern),
+    Type02,
+    Type03,
+    Unknown,
+}
+
+#[test]
+fn valid_messages() {
+    for (packet, expected) in VALID {
+        let status = XinputHostStatus::try_from(*packet)
+            .unwrap_or_else(|e| panic!("{:02x?} rejected: {:?}", packet, e));
+        let raw = match (expected, status) {
+            (Expected::Rumble(left, right), XinputHostStatus::Rumble(state)) => {
+                assert_eq!(
+                    state,
+                    XinputRumbleState::new(*left, *right),
+                    "{:02x?}",
+                    packet
+                );
+                continue;
+            }
+            (Expected::Led(pattern), XinputHostStatus::Led(led)) => {
+                assert_eq!(led, *pattern, "{:02x?}", packet);
+                continue;
+            }
+            (Expected::Type02, XinputHostStatus::Type02(raw)) => raw,
+            (Expected::Type03, XinputHostStatus::Type03(raw)) => raw,
+            (Expected::Unknown, XinputHostStatus::Unknown(raw)) => raw,
+            (expected, status) => {
+                panic!("{:02x?}: expected {:?}, got {:?}", packet, expected, status)
+            }
+        };
+        assert_eq!(raw.as_bytes(), *packet);
+        assert_eq!(raw.kind(), packet[0]);
+        assert_eq!(raw.payload(), &packet[2..]);
+    }
+}
+
+#[test]
+fn invalid_messages() {
+    for (packet, expected) in INVALID {
+        assert_eq!(
+            XinputHostStatus::try_from(*packet),
+            Err(*expected),
+            "{:02x?}",
+            packet
+        );
+    }
+}
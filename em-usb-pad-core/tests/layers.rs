@@ -0,0 +1,165 @@
+//! The function key: its layer, taps and buttons held across layer changes.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::mapping::{
+    bind, Binding, Control, Layer, Mapper, Profile, TAP_PRESS, TAP_TERM,
+};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use embassy_time::Instant;
+
+const LAYOUT: Layout = Layout::new(0, 0, 5);
+
+const FN: InputId = InputId::Direct(0);
+const VIEW: InputId = InputId::Direct(1);
+const MENU: InputId = InputId::Direct(2);
+const A: InputId = InputId::Direct(3);
+const B: InputId = InputId::Direct(4);
+
+const BINDINGS: [Binding; 4] = [
+    bind(VIEW, Control::View),
+    bind(MENU, Control::Menu),
+    bind(A, Control::A),
+    bind(B, Control::B),
+];
+
+const LAYER: [Binding; 3] = [
+    bind(VIEW, Control::Guide),
+    bind(A, Control::LS),
+    bind(B, Control::Macro(1)),
+];
+
+const fn profile(name: &'static str, tap: Control) -> Profile {
+    Profile {
+        name,
+        bindings: &BINDINGS,
+        shaping: SticksShaping::NONE,
+        triggers: TriggersShaping::NONE,
+        socd: SocdConfig::OFF,
+        dpad_hotkey: &[],
+        turbo_hotkey: &[],
+        macro_hotkey: &[VIEW, MENU],
+        layer: Some(Layer {
+            key: FN,
+            tap: Some(tap),
+            bindings: &LAYER,
+        }),
+        chords: &[],
+    }
+}
+
+static PROFILES: [Profile; 2] = [
+    profile("first", Control::NextProfile),
+    profile("second", Control::RS),
+];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+}
+
+#[test]
+fn holding_the_key_switches_layer() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let report = mapper.update(pressed(&[FN]), at(0));
+    assert!(mapper.layer_held());
+    assert_eq!(report, Default::default());
+
+    let report = mapper.update(pressed(&[FN, VIEW, A, MENU]), at(10));
+    assert!(report.xbox_button && report.thumb_click_left);
+    // not on the layer, the profile's binding
+    assert!(report.button_menu);
+    assert!(!report.button_view && !report.button_a);
+
+    // a layer button pressing a macro
+    mapper.update(pressed(&[FN, B]), at(20));
+    assert_eq!(mapper.macro_pressed(), Some(1));
+    mapper.update(pressed(&[FN, B]), at(30));
+    assert_eq!(mapper.macro_pressed(), None);
+}
+
+#[test]
+fn buttons_keep_the_layer_they_were_pressed_in() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.update(pressed(&[A]), at(0));
+    // held before the key, still the profile's
+    let report = mapper.update(pressed(&[A, FN]), at(10));
+    assert!(report.button_a && !report.thumb_click_left);
+    let report = mapper.update(pressed(&[A, FN, VIEW]), at(20));
+    assert!(report.button_a && report.xbox_button);
+
+    // the key let go, the layer buttons held stay on it
+    let report = mapper.update(pressed(&[A, VIEW]), at(30));
+    assert!(!mapper.layer_held());
+    assert!(report.button_a && report.xbox_button && !report.button_view);
+
+    // released and pressed again, back to the profile
+    mapper.update(pressed(&[A]), at(40));
+    let report = mapper.update(pressed(&[A, VIEW]), at(50));
+    assert!(report.button_view && !report.xbox_button);
+}
+
+#[test]
+fn tap_presses_the_tap_control() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.select(1);
+    mapper.update(pressed(&[FN]), at(1000));
+    let report = mapper.update(pressed(&[]), at(1100));
+    assert!(report.thumb_click_right);
+    let ms = 1100 + TAP_PRESS.as_millis();
+    assert!(mapper.update(pressed(&[]), at(ms - 1)).thumb_click_right);
+    assert!(!mapper.update(pressed(&[]), at(ms)).thumb_click_right);
+}
+
+#[test]
+fn tap_switches_profiles() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.update(pressed(&[FN]), at(0));
+    mapper.update(pressed(&[]), at(50));
+    assert_eq!(mapper.active(), 1);
+    // the tap of the new profile isn't pressed
+    let report = mapper.update(pressed(&[]), at(60));
+    assert!(!report.thumb_click_right);
+}
+
+#[test]
+fn holds_are_not_taps() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    // too long
+    mapper.update(pressed(&[FN]), at(0));
+    mapper.update(pressed(&[]), at(TAP_TERM.as_millis()));
+    assert_eq!(mapper.active(), 0);
+
+    // a button pressed meanwhile, even quickly
+    mapper.update(pressed(&[FN]), at(1000));
+    mapper.update(pressed(&[FN, A]), at(1010));
+    mapper.update(pressed(&[FN]), at(1020));
+    mapper.update(pressed(&[]), at(1030));
+    assert_eq!(mapper.active(), 0);
+
+    // pressed along with another button
+    mapper.update(pressed(&[FN, A]), at(2000));
+    mapper.update(pressed(&[]), at(2010));
+    assert_eq!(mapper.active(), 0);
+}
+
+#[test]
+fn layer_buttons_are_not_hotkeys() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.update(pressed(&[FN, VIEW]), at(0));
+    let report = mapper.update(pressed(&[FN, VIEW, MENU]), at(10));
+    assert!(!mapper.macro_hotkey_held());
+    assert!(report.xbox_button && report.button_menu);
+
+    mapper.update(pressed(&[]), at(20));
+    let report = mapper.update(pressed(&[VIEW, MENU]), at(30));
+    assert!(mapper.macro_hotkey_held());
+    assert!(!report.button_view && !report.button_menu);
+}
@@ -0,0 +1,189 @@
+//! Play the LED patterns against a virtual clock.
+
+use std::cell::RefCell;
+
+use em_usb_pad_core::led::{LedOutput, LedQuadrants, LedRing};
+use em_usb_pad_core::xinput::XinputLedPattern;
+use embassy_time::Instant;
+
+/// Records every frame
+struct Leds<'a>(&'a RefCell<Vec<u8>>);
+
+impl<'a> LedOutput for Leds<'a> {
+    fn set_leds(&mut self, leds: LedQuadrants) {
+        self.0.borrow_mut().push(leds.0);
+    }
+}
+
+/// Play `pattern` from `start_ms` until `end_ms`, following the frame deadlines.
+///
+/// Returns (time, frame) for every change.
+fn play<O: LedOutput>(
+    ring: &mut LedRing<O>,
+    pattern: XinputLedPattern,
+    start_ms: u64,
+    end_ms: u64,
+) -> Vec<(u64, u8)> {
+    let mut changes = Vec::new();
+    ring.set_pattern(pattern, Instant::from_millis(start_ms));
+    let mut now = Instant::from_millis(start_ms);
+    loop {
+        let before = ring.shown();
+        let next = ring.update(now);
+        if ring.shown() != before || changes.is_empty() {
+            changes.push((now.as_millis(), ring.shown().0));
+        }
+        match next {
+            Some(next) if next.as_millis() < end_ms => now = next,
+            _ => return changes,
+        }
+    }
+}
+
+#[test]
+fn steady_patterns() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    let table = [
+        (XinputLedPattern::On1, 0b0001),
+        (XinputLedPattern::On2, 0b0010),
+        (XinputLedPattern::On3, 0b0100),
+        (XinputLedPattern::On4, 0b1000),
+        (XinputLedPattern::Off, 0b0000),
+    ];
+    for (pattern, leds) in table {
+        ring.set_pattern(pattern, Instant::from_millis(0));
+        assert_eq!(ring.update(Instant::from_millis(0)), None, "{:?}", pattern);
+        assert_eq!(ring.shown(), LedQuadrants(leds), "{:?}", pattern);
+    }
+    assert_eq!(
+        *log.borrow(),
+        [0b0000, 0b0001, 0b0010, 0b0100, 0b1000, 0b0000]
+    );
+}
+
+#[test]
+fn flash_then_settle() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    assert_eq!(
+        play(&mut ring, XinputLedPattern::Flash3, 1000, 10_000),
+        [
+            (1000, 0b0100),
+            (1250, 0b0000),
+            (1500, 0b0100),
+            (1750, 0b0000),
+            (2000, 0b0100),
+            (2250, 0b0000),
+            (2500, 0b0100),
+        ]
+    );
+    // nothing more to do once settled
+    assert_eq!(ring.update(Instant::from_millis(60_000)), None);
+    assert!(ring.shown().is_on(3));
+}
+
+#[test]
+fn blinking_forever() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    let changes = play(&mut ring, XinputLedPattern::Blink, 0, 2000);
+    assert_eq!(changes.len(), 8);
+    for (i, (time, leds)) in changes.iter().enumerate() {
+        assert_eq!(*time, i as u64 * 250);
+        assert_eq!(*leds, if i % 2 == 0 { 0b1111 } else { 0b0000 });
+    }
+    assert!(ring.update(Instant::from_millis(100_000)).is_some());
+}
+
+#[test]
+fn rotation_goes_round_the_ring() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    let leds: Vec<u8> = play(&mut ring, XinputLedPattern::Rotate, 0, 800)
+        .into_iter()
+        .map(|(_, leds)| leds)
+        .collect();
+    assert_eq!(
+        leds,
+        [0b0001, 0b0010, 0b1000, 0b0100, 0b0001, 0b0010, 0b1000, 0b0100]
+    );
+}
+
+#[test]
+fn temporary_patterns_go_back_to_previous_setting() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    ring.set_pattern(XinputLedPattern::On2, Instant::from_millis(0));
+    ring.update(Instant::from_millis(0));
+
+    let changes = play(&mut ring, XinputLedPattern::Alternate, 100, 10_000);
+    assert_eq!(changes.first(), Some(&(100, 0b1001)));
+    assert_eq!(changes[1], (350, 0b0110));
+    assert_eq!(changes.last(), Some(&(2100, 0b0010)));
+
+    let changes = play(&mut ring, XinputLedPattern::BlinkOnce, 3000, 10_000);
+    assert_eq!(
+        changes,
+        [
+            (3000, 0b0010),
+            (3250, 0b0000),
+            (3500, 0b0010),
+            (3750, 0b0000),
+            (4000, 0b0010),
+            (4250, 0b0000),
+            (4500, 0b0010),
+        ]
+    );
+
+    // the previous setting of a flash is the quadrant it settles on
+    play(&mut ring, XinputLedPattern::Flash4, 5000, 10_000);
+    let changes = play(&mut ring, XinputLedPattern::BlinkSlow, 10_000, 12_000);
+    assert_eq!(
+        changes,
+        [
+            (10_000, 0b1000),
+            (10_500, 0b0000),
+            (11_000, 0b1000),
+            (11_500, 0b0000)
+        ]
+    );
+}
+
+#[test]
+fn new_pattern_interrupts_animation() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    play(&mut ring, XinputLedPattern::Rotate, 0, 350);
+    ring.set_pattern(XinputLedPattern::On1, Instant::from_millis(350));
+    assert_eq!(ring.update(Instant::from_millis(350)), None);
+    assert_eq!(ring.shown(), LedQuadrants::only(1));
+}
+
+#[test]
+fn notify_blinks_then_shows_the_player_again() {
+    let log = RefCell::new(Vec::new());
+    let mut ring = LedRing::new(Leds(&log));
+    play(&mut ring, XinputLedPattern::On2, 0, 100);
+    ring.notify(2, Instant::from_millis(100));
+    let mut changes = Vec::new();
+    let mut now = Instant::from_millis(100);
+    loop {
+        let next = ring.update(now);
+        changes.push((now.as_millis(), ring.shown().0));
+        match next {
+            Some(next) => now = next,
+            None => break,
+        }
+    }
+    assert_eq!(
+        changes,
+        [
+            (100, 0b1111),
+            (180, 0b0000),
+            (260, 0b1111),
+            (340, 0b0000),
+            (420, 0b0010)
+        ]
+    );
+}
@@ -0,0 +1,288 @@
+//! Record and play macros against a virtual clock.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::macros::{Macro, MacroError, Macros, MACRO_CAPACITY};
+use em_usb_pad_core::mapping::{bind, Control, Mapper, Profile};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use em_usb_pad_core::xinput::XinputControlReport;
+use embassy_time::{Duration, Instant};
+
+fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+}
+
+fn neutral() -> XinputControlReport {
+    XinputControlReport::default()
+}
+
+fn a() -> XinputControlReport {
+    XinputControlReport {
+        button_a: true,
+        ..Default::default()
+    }
+}
+
+fn a_and_stick() -> XinputControlReport {
+    XinputControlReport {
+        button_a: true,
+        js_left_x: -1234,
+        trigger_right: 200,
+        ..Default::default()
+    }
+}
+
+/// Record `reports` at their times into slot 0, stop at `stop_ms`.
+fn record(macros: &mut Macros, reports: &[(u64, XinputControlReport)], stop_ms: u64) {
+    let mut report = neutral();
+    macros.apply(&mut report, true, Some(0), at(reports[0].0));
+    assert_eq!(macros.recording(), Some(0));
+    for (ms, live) in reports {
+        let mut report = XinputControlReport { ..*live };
+        assert_eq!(macros.apply(&mut report, false, None, at(*ms)), None);
+        // the live report goes through while recording
+        assert_eq!(report, *live);
+    }
+    macros.apply(&mut neutral(), false, Some(0), at(stop_ms));
+    assert_eq!(macros.recording(), None);
+}
+
+/// Play slot 0 from `start_ms`, following the frame deadlines and feeding
+/// a neutral live report.
+///
+/// Returns (time, report) for every frame.
+fn play(macros: &mut Macros, start_ms: u64) -> Vec<(u64, XinputControlReport)> {
+    let mut stream = Vec::new();
+    let mut now = at(start_ms);
+    let mut pressed = Some(0);
+    loop {
+        let mut report = neutral();
+        let next = macros.apply(&mut report, false, pressed.take(), now);
+        stream.push((now.as_millis(), report));
+        match next {
+            Some(next) => {
+                assert!(next > now);
+                now = next;
+            }
+            None => return stream,
+        }
+    }
+}
+
+#[test]
+fn plays_back_what_was_recorded() {
+    let mut macros = Macros::new();
+    // the wait before the first change isn't kept
+    let recorded = [
+        (1000, neutral()),
+        (1300, a()),
+        (1304, a()),
+        (1350, a_and_stick()),
+        (1475, neutral()),
+        (1600, a()),
+    ];
+    record(&mut macros, &recorded, 1700);
+
+    let stream = play(&mut macros, 5000);
+    assert_eq!(
+        stream,
+        [
+            (5000, a()),
+            (5050, a_and_stick()),
+            (5175, neutral()),
+            (5300, a()),
+            // stopping goes back to neutral
+            (5400, neutral()),
+        ]
+    );
+    assert_eq!(macros.playing(), None);
+}
+
+#[test]
+fn the_format_is_compact() {
+    let mut macros = Macros::new();
+    record(&mut macros, &[(0, a()), (100, neutral())], 200);
+    // delay 0, byte 1 changed to A, then delay 100, byte 1 back to 0
+    assert_eq!(
+        macros.get(0).unwrap().as_bytes(),
+        [0x00, 0x02, 0x00, 0x10, 0x64, 0x02, 0x00, 0x00]
+    );
+
+    record(&mut macros, &[(0, a()), (300, neutral())], 400);
+    // 300 ms takes two bytes
+    assert_eq!(
+        macros.get(0).unwrap().as_bytes()[4..],
+        [0xac, 0x02, 0x02, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn late_calls_catch_up() {
+    let mut macros = Macros::new();
+    let recorded = [(0, a()), (10, neutral()), (20, a()), (30, neutral())];
+    record(&mut macros, &recorded, 40);
+
+    let mut report = neutral();
+    assert_eq!(
+        macros.apply(&mut report, false, Some(0), at(0)),
+        Some(at(10))
+    );
+    assert_eq!(report, a());
+    // two frames late, the third plays and its deadline stays on schedule
+    let mut report = neutral();
+    assert_eq!(macros.apply(&mut report, false, None, at(25)), Some(at(30)));
+    assert_eq!(report, a());
+}
+
+#[test]
+fn pressing_again_restarts() {
+    let mut macros = Macros::new();
+    record(&mut macros, &[(0, a()), (100, neutral())], 200);
+    let mut report = neutral();
+    macros.apply(&mut report, false, Some(0), at(1000));
+    let mut report = neutral();
+    assert_eq!(
+        macros.apply(&mut report, false, Some(0), at(1050)),
+        Some(at(1150))
+    );
+    assert_eq!(report, a());
+}
+
+#[test]
+fn empty_slots_play_nothing() {
+    let mut macros = Macros::new();
+    let mut report = a();
+    assert_eq!(macros.apply(&mut report, false, Some(1), at(0)), None);
+    assert_eq!(report, a());
+    assert_eq!(macros.playing(), None);
+    // no such slot
+    assert!(!macros.start_recording(200));
+    assert!(!macros.play(200, at(0)));
+}
+
+#[test]
+fn full_recording_stops_on_neutral() {
+    let mut macros = Macros::new();
+    macros.start_recording(0);
+    let mut ms = 0;
+    while macros.recording().is_some() {
+        let mut report = XinputControlReport {
+            js_left_x: ms as i16 * 7,
+            ..Default::default()
+        };
+        macros.apply(&mut report, false, None, at(ms));
+        ms += 4;
+        assert!(ms < 10_000);
+    }
+    let stored = macros.get(0).unwrap();
+    assert!(stored.as_bytes().len() <= MACRO_CAPACITY);
+    assert_eq!(stored.frames().last().unwrap().report(), neutral());
+}
+
+#[test]
+fn bytes_round_trip() {
+    let mut macros = Macros::new();
+    let recorded = [(0, a_and_stick()), (20_000, a()), (20_001, neutral())];
+    record(&mut macros, &recorded, 20_002);
+    let bytes = macros.get(0).unwrap().as_bytes().to_vec();
+
+    let loaded = Macro::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.as_bytes(), &bytes[..]);
+    let frames: Vec<_> = loaded
+        .frames()
+        .map(|frame| (frame.delay, frame.report()))
+        .collect();
+    assert_eq!(
+        frames,
+        [
+            (Duration::from_millis(0), a_and_stick()),
+            (Duration::from_millis(20_000), a()),
+            (Duration::from_millis(1), neutral()),
+        ]
+    );
+    assert!(macros.set(3, loaded));
+    assert!(!macros.get(3).unwrap().is_empty());
+
+    // cut in the middle of a frame, a frame changing nothing
+    assert_eq!(
+        Macro::from_bytes(&bytes[..bytes.len() - 1]).err(),
+        Some(MacroError::Malformed)
+    );
+    assert_eq!(
+        Macro::from_bytes(&[0, 0, 0]).err(),
+        Some(MacroError::Malformed)
+    );
+    assert_eq!(
+        Macro::from_bytes(&[0; MACRO_CAPACITY + 1]).err(),
+        Some(MacroError::Full)
+    );
+}
+
+const LAYOUT: Layout = Layout::new(0, 0, 4);
+const VIEW: InputId = InputId::Direct(0);
+const RB: InputId = InputId::Direct(1);
+const A: InputId = InputId::Direct(2);
+const MACRO: InputId = InputId::Direct(3);
+
+static PROFILES: [Profile; 1] = [Profile {
+    name: "macros",
+    bindings: &[
+        bind(VIEW, Control::View),
+        bind(RB, Control::RB),
+        bind(A, Control::A),
+        bind(MACRO, Control::Macro(2)),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[VIEW, RB],
+    layer: None,
+    chords: &[],
+}];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+#[test]
+fn record_mode_through_the_mapper() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let mut macros = Macros::new();
+    let mut step = |inputs: &[InputId], ms: u64| {
+        let mut report = mapper.update(pressed(inputs), at(ms));
+        macros.apply(
+            &mut report,
+            mapper.macro_hotkey_held(),
+            mapper.macro_pressed(),
+            at(ms),
+        );
+        (report, macros.recording(), macros.playing())
+    };
+
+    let (report, _, _) = step(&[VIEW, RB], 0);
+    assert!(!report.button_view && !report.shoulder_right);
+    assert_eq!(step(&[VIEW, RB, MACRO], 10).1, Some(2));
+    step(&[], 20);
+    step(&[A], 100);
+    step(&[], 150);
+    assert_eq!(step(&[MACRO], 500).1, None);
+
+    // holding the button doesn't restart it
+    let (report, _, playing) = step(&[], 1000);
+    assert!(!report.button_a);
+    assert_eq!(playing, None);
+    let (report, _, playing) = step(&[MACRO], 1010);
+    assert!(report.button_a);
+    assert_eq!(playing, Some(2));
+    let (report, _, _) = step(&[MACRO], 1059);
+    assert!(report.button_a);
+    let (report, _, playing) = step(&[MACRO], 1060);
+    assert!(!report.button_a);
+    assert_eq!(playing, None);
+}
@@ -0,0 +1,255 @@
+//! Map button states through profiles.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::chords::{chord, Chord};
+use em_usb_pad_core::mapping::{bind, hotkeys_overlap, Control, Direction, Mapper, Profile};
+use em_usb_pad_core::settings::{Remap, Settings};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use em_usb_pad_core::xinput::XinputControlReport;
+use embassy_time::Instant;
+
+const LAYOUT: Layout = Layout::new(2, 2, 1);
+
+const fn key(row: u8, col: u8) -> InputId {
+    InputId::Matrix { row, col }
+}
+
+const FACE: Profile = Profile {
+    name: "face",
+    bindings: &[
+        bind(key(0, 0), Control::A),
+        bind(key(0, 1), Control::B),
+        bind(key(1, 0), Control::LT),
+        // two buttons on the same control
+        bind(key(1, 1), Control::A),
+        bind(InputId::Direct(0), Control::NextProfile),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+const STICK: Profile = Profile {
+    name: "stick",
+    bindings: &[
+        bind(key(0, 0), Control::LeftStick(Direction::Up)),
+        bind(key(0, 1), Control::LeftStick(Direction::Down)),
+        bind(key(1, 0), Control::RightStick(Direction::Left)),
+        bind(key(1, 1), Control::DpadRight),
+        bind(InputId::Direct(0), Control::NextProfile),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+static PROFILES: [Profile; 2] = [FACE, STICK];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+#[test]
+fn buttons_and_triggers() {
+    let report = FACE.report(LAYOUT, pressed(&[key(0, 1), key(1, 0)]));
+    assert_eq!(
+        report,
+        XinputControlReport {
+            button_b: true,
+            trigger_left: 0xff,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        FACE.report(LAYOUT, InputStates::default()),
+        Default::default()
+    );
+}
+
+#[test]
+fn any_bound_button_holds_the_control() {
+    let both = FACE.report(LAYOUT, pressed(&[key(0, 0), key(1, 1)]));
+    let one = FACE.report(LAYOUT, pressed(&[key(1, 1)]));
+    assert!(both.button_a);
+    assert!(one.button_a);
+}
+
+#[test]
+fn stick_directions() {
+    let report = STICK.report(LAYOUT, pressed(&[key(0, 0), key(1, 0)]));
+    assert_eq!(report.js_left_x, 0);
+    assert_eq!(report.js_left_y, i16::MAX);
+    assert_eq!(report.js_right_x, i16::MIN);
+    assert_eq!(report.js_right_y, 0);
+
+    // opposite directions cancel out
+    let report = STICK.report(LAYOUT, pressed(&[key(0, 0), key(0, 1)]));
+    assert_eq!((report.js_left_x, report.js_left_y), (0, 0));
+}
+
+#[test]
+fn switch_profiles_at_runtime() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    assert_eq!(mapper.active_profile().name, "face");
+    assert!(
+        mapper
+            .update(pressed(&[key(0, 0)]), Instant::from_millis(0))
+            .button_a
+    );
+
+    // switching reads the held buttons through the new profile
+    let report = mapper.update(
+        pressed(&[key(0, 0), InputId::Direct(0)]),
+        Instant::from_millis(0),
+    );
+    assert_eq!(mapper.active_profile().name, "stick");
+    assert!(!report.button_a);
+    assert_eq!(report.js_left_y, i16::MAX);
+
+    // holding the switch doesn't switch again
+    mapper.update(pressed(&[InputId::Direct(0)]), Instant::from_millis(0));
+    assert_eq!(mapper.active(), 1);
+
+    // from the last profile back to the first
+    mapper.update(pressed(&[]), Instant::from_millis(0));
+    mapper.update(pressed(&[InputId::Direct(0)]), Instant::from_millis(0));
+    assert_eq!(mapper.active(), 0);
+}
+
+#[test]
+fn select_profiles() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    assert!(mapper.select_by_name("stick"));
+    assert_eq!(mapper.active(), 1);
+    assert!(!mapper.select_by_name("missing"));
+    assert!(!mapper.select(2));
+    assert_eq!(mapper.active(), 1);
+    assert!(mapper.select(0));
+    assert!(
+        mapper
+            .update(pressed(&[key(1, 1)]), Instant::from_millis(0))
+            .button_a
+    );
+}
+
+#[test]
+fn remapped_buttons_of_the_active_profile() {
+    let mut settings = Settings::default();
+    // a button of the table, and one it doesn't have
+    settings.set_remap(Remap {
+        profile: 0,
+        input: key(0, 0),
+        control: Control::Y,
+    });
+    settings.set_remap(Remap {
+        profile: 1,
+        input: key(0, 1),
+        control: Control::RB,
+    });
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    mapper.set_remaps(&settings.remaps);
+
+    let report = mapper.update(pressed(&[key(0, 0), key(0, 1)]), Instant::from_millis(0));
+    assert_eq!(
+        report,
+        XinputControlReport {
+            button_b: true,
+            button_y: true,
+            ..Default::default()
+        }
+    );
+
+    // following the profile switches
+    mapper.update(pressed(&[InputId::Direct(0)]), Instant::from_millis(0));
+    let report = mapper.update(pressed(&[key(0, 0), key(0, 1)]), Instant::from_millis(0));
+    assert_eq!(
+        report,
+        XinputControlReport {
+            shoulder_right: true,
+            js_left_y: i16::MAX,
+            ..Default::default()
+        }
+    );
+
+    mapper.set_remaps(&Settings::default().remaps);
+    let report = mapper.update(pressed(&[key(0, 1)]), Instant::from_millis(0));
+    assert_eq!(report.js_left_y, i16::MIN);
+
+    // as the settings read them, past an empty slot
+    let mut remaps = Settings::default().remaps;
+    remaps[1] = Some(Remap {
+        profile: 1,
+        input: key(0, 1),
+        control: Control::RB,
+    });
+    mapper.set_remaps(&remaps);
+    let report = mapper.update(pressed(&[key(0, 1)]), Instant::from_millis(0));
+    assert!(report.shoulder_right);
+}
+
+#[test]
+fn buttons_missing_from_the_board_are_ignored() {
+    const OTHER_BOARD: Profile = Profile {
+        name: "other",
+        bindings: &[
+            bind(key(5, 5), Control::Y),
+            bind(InputId::Direct(3), Control::X),
+        ],
+        shaping: SticksShaping::NONE,
+        triggers: TriggersShaping::NONE,
+        socd: SocdConfig::OFF,
+        dpad_hotkey: &[],
+        turbo_hotkey: &[],
+        macro_hotkey: &[],
+        layer: None,
+        chords: &[],
+    };
+    let all = InputStates(u64::MAX);
+    assert_eq!(OTHER_BOARD.report(LAYOUT, all), Default::default());
+}
+
+#[test]
+fn overlapping_hotkeys() {
+    const VIEW: InputId = key(0, 1);
+    const MENU: InputId = key(1, 1);
+    assert!(!FACE.hotkeys_overlap());
+    assert!(!hotkeys_overlap(&[&[VIEW, MENU], &[key(0, 0)], &[]], &[]));
+    assert!(hotkeys_overlap(
+        &[&[VIEW, MENU], &[key(0, 0)], &[MENU]],
+        &[]
+    ));
+    assert!(hotkeys_overlap(&[&[VIEW], &[VIEW]], &[]));
+    assert!(!hotkeys_overlap(
+        &[&[InputId::Direct(0)], &[key(0, 0)]],
+        &[]
+    ));
+
+    // a hotkey holding part of a chord
+    const GUIDE: [Chord; 1] = [chord(&[VIEW, MENU], Control::Guide)];
+    assert!(hotkeys_overlap(&[&[MENU, key(0, 0)]], &GUIDE));
+    assert!(!hotkeys_overlap(&[&[key(0, 0)], &[]], &GUIDE));
+
+    let mut profile = FACE;
+    profile.dpad_hotkey = &[VIEW, MENU, InputId::Direct(0)];
+    profile.macro_hotkey = &[VIEW, InputId::Direct(0)];
+    assert!(profile.hotkeys_overlap());
+    profile.macro_hotkey = &[InputId::Direct(1)];
+    assert!(!profile.hotkeys_overlap());
+    profile.chords = &GUIDE;
+    assert!(profile.hotkeys_overlap());
+}
@@ -0,0 +1,146 @@
+//! Drive the rumble motors from host messages.
+
+use std::cell::RefCell;
+
+use em_usb_pad_core::rumble::{
+    MotorConfig, Rumble, RumbleConfig, RumbleCurve, RumbleOutput, RUMBLE_CURVE_POINTS,
+};
+use em_usb_pad_core::xinput::XinputHostStatus;
+use embassy_time::{Duration, Instant};
+
+/// Records every speed change
+struct Motors<'a>(&'a RefCell<Vec<(u8, u8)>>);
+
+impl<'a> RumbleOutput for Motors<'a> {
+    fn set_motors(&mut self, left: u8, right: u8) {
+        self.0.borrow_mut().push((left, right));
+    }
+}
+
+/// Feed a rumble message as it comes from the host
+fn receive<O: RumbleOutput>(rumble: &mut Rumble<O>, packet: &[u8], now_ms: u64) {
+    match XinputHostStatus::try_from(packet) {
+        Ok(XinputHostStatus::Rumble(state)) => rumble.set(state, Instant::from_millis(now_ms)),
+        other => panic!("not a rumble message: {:?}", other),
+    }
+}
+
+fn rumble_packet(left: u8, right: u8) -> [u8; 8] {
+    [0x00, 0x08, 0x00, left, right, 0x00, 0x00, 0x00]
+}
+
+#[test]
+fn follows_host_requests() {
+    let log = RefCell::new(Vec::new());
+    let mut rumble = Rumble::new(Motors(&log), RumbleConfig::default());
+    receive(&mut rumble, &rumble_packet(0xff, 0x00), 0);
+    receive(&mut rumble, &rumble_packet(0xff, 0x00), 10); // unchanged, not applied again
+    receive(&mut rumble, &rumble_packet(0x40, 0x80), 20);
+    receive(&mut rumble, &rumble_packet(0x00, 0x00), 30);
+    assert_eq!(*log.borrow(), [(0, 0), (0xff, 0), (0x40, 0x80), (0, 0)]);
+}
+
+#[test]
+fn curves_and_maximum() {
+    let config = RumbleConfig {
+        left: MotorConfig {
+            curve: RumbleCurve::Quadratic,
+            max: 0xff,
+        },
+        right: MotorConfig {
+            curve: RumbleCurve::Linear,
+            max: 0x80,
+        },
+        ..Default::default()
+    };
+    let log = RefCell::new(Vec::new());
+    let mut rumble = Rumble::new(Motors(&log), config);
+    receive(&mut rumble, &rumble_packet(0x80, 0xff), 0);
+    assert_eq!(rumble.running(), (0x40, 0x80));
+
+    // (input, linear, quadratic, square root)
+    let table = [
+        (0x00, 0x00, 0x00, 0x00),
+        (0x01, 0x01, 0x00, 0x0f),
+        (0x40, 0x40, 0x10, 0x7f),
+        (0x80, 0x80, 0x40, 0xb4),
+        (0xff, 0xff, 0xff, 0xff),
+    ];
+    for (input, linear, quadratic, square_root) in table {
+        assert_eq!(RumbleCurve::Linear.apply(input), linear);
+        assert_eq!(RumbleCurve::Quadratic.apply(input), quadratic);
+        assert_eq!(RumbleCurve::SquareRoot.apply(input), square_root);
+    }
+}
+
+#[test]
+fn table_curve_interpolates() {
+    // a step at the middle
+    let mut points = [0; RUMBLE_CURVE_POINTS];
+    points[8..].fill(200);
+    points[RUMBLE_CURVE_POINTS - 1] = 255;
+    let curve = RumbleCurve::Table(points);
+    assert_eq!(curve.apply(0), 0);
+    assert_eq!(curve.apply(112), 0);
+    assert_eq!(curve.apply(120), 100);
+    assert_eq!(curve.apply(128), 200);
+    assert_eq!(curve.apply(240), 200);
+    assert_eq!(curve.apply(255), 255);
+}
+
+#[test]
+fn stops_when_host_goes_silent() {
+    let config = RumbleConfig {
+        timeout: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let log = RefCell::new(Vec::new());
+    let mut rumble = Rumble::new(Motors(&log), config);
+    receive(&mut rumble, &rumble_packet(0xff, 0xff), 0);
+    rumble.tick(Instant::from_millis(300));
+    assert_eq!(rumble.running(), (0xff, 0xff));
+    // any other message keeps it going
+    rumble.keep_alive(Instant::from_millis(400));
+    rumble.tick(Instant::from_millis(800));
+    assert_eq!(rumble.running(), (0xff, 0xff));
+    rumble.tick(Instant::from_millis(900));
+    assert_eq!(rumble.running(), (0, 0));
+    // a new request starts it again
+    receive(&mut rumble, &rumble_packet(0x10, 0x00), 1000);
+    assert_eq!(rumble.running(), (0x10, 0));
+}
+
+#[test]
+fn no_timeout_keeps_running() {
+    // the default, the host doesn't repeat a rumble
+    let config = RumbleConfig::default();
+    assert_eq!(config.timeout, None);
+    let log = RefCell::new(Vec::new());
+    let mut rumble = Rumble::new(Motors(&log), config);
+    receive(&mut rumble, &rumble_packet(0xff, 0xff), 0);
+    rumble.tick(Instant::from_millis(60_000));
+    assert_eq!(rumble.running(), (0xff, 0xff));
+
+    // until the bus is reset
+    rumble.stop();
+    assert_eq!(rumble.running(), (0, 0));
+    receive(&mut rumble, &rumble_packet(0x20, 0x00), 60_010);
+    assert_eq!(rumble.running(), (0x20, 0));
+}
+
+#[test]
+fn stops_on_suspend() {
+    let log = RefCell::new(Vec::new());
+    let mut rumble = Rumble::new(Motors(&log), RumbleConfig::default());
+    receive(&mut rumble, &rumble_packet(0xff, 0x80), 0);
+    rumble.set_suspended(true);
+    assert_eq!(rumble.running(), (0, 0));
+    // requests while suspended are not applied, nor remembered after resuming
+    receive(&mut rumble, &rumble_packet(0xff, 0xff), 10);
+    assert_eq!(rumble.running(), (0, 0));
+    rumble.set_suspended(false);
+    assert_eq!(rumble.running(), (0, 0));
+    receive(&mut rumble, &rumble_packet(0x20, 0x20), 20);
+    assert_eq!(rumble.running(), (0x20, 0x20));
+    assert_eq!(*log.borrow(), [(0, 0), (0xff, 0x80), (0, 0), (0x20, 0x20)]);
+}
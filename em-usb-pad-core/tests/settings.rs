@@ -0,0 +1,362 @@
+//! Settings in a simulated NOR flash, with power cut at every step of a save.
+
+use em_usb_pad_core::board::InputId;
+use em_usb_pad_core::mapping::{Control, Direction, DpadMode};
+use em_usb_pad_core::settings::{Remap, Settings, StickDeadzone, MAX_REMAPS, SETTINGS_MAX_SIZE};
+use em_usb_pad_core::shaping::SticksShaping;
+use em_usb_pad_core::store::{crc32, NorFlash, SettingsStore};
+
+const PAGE_SIZE: usize = 256;
+
+#[derive(Debug, PartialEq)]
+enum FlashError {
+    /// The power is gone, nothing works anymore
+    PowerCut,
+}
+
+/// Two pages of NOR flash that lose power after a number of bytes
+struct SimFlash {
+    bytes: Vec<u8>,
+    erases: [usize; 2],
+    /// Bytes written or erased until the power is cut
+    budget: Option<usize>,
+}
+
+impl SimFlash {
+    fn new() -> Self {
+        SimFlash {
+            bytes: vec![0xff; 2 * PAGE_SIZE],
+            erases: [0; 2],
+            budget: None,
+        }
+    }
+
+    /// Take `len` bytes of the budget, `None` if the power is already cut
+    /// and how many can be done before it is.
+    fn spend(&mut self, len: usize) -> Option<usize> {
+        match self.budget {
+            None => Some(len),
+            Some(0) => None,
+            Some(budget) => {
+                self.budget = Some(budget.saturating_sub(len));
+                Some(len.min(budget))
+            }
+        }
+    }
+
+    /// Power back on
+    fn restore(&mut self) {
+        self.budget = None;
+    }
+}
+
+impl NorFlash for SimFlash {
+    type Error = FlashError;
+    const PAGE_SIZE: usize = PAGE_SIZE;
+    const WRITE_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+        if self.budget == Some(0) {
+            return Err(FlashError::PowerCut);
+        }
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.bytes[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        assert_eq!(offset % Self::WRITE_SIZE, 0, "unaligned write");
+        assert_eq!(bytes.len() % Self::WRITE_SIZE, 0, "partial write");
+        let done = self.spend(bytes.len()).ok_or(FlashError::PowerCut)?;
+        for (at, byte) in bytes[..done].iter().enumerate() {
+            let old = &mut self.bytes[offset + at];
+            assert_eq!(*old, 0xff, "write to flash not erased at {}", offset + at);
+            *old = *byte;
+        }
+        if done < bytes.len() {
+            // the byte being written when the power went is half done
+            self.bytes[offset + done] &= bytes[done] | 0xf0;
+            return Err(FlashError::PowerCut);
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, offset: u32) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        assert_eq!(offset % PAGE_SIZE, 0, "unaligned erase");
+        let done = self.spend(PAGE_SIZE).ok_or(FlashError::PowerCut)?;
+        self.erases[offset / PAGE_SIZE] += 1;
+        self.bytes[offset..offset + done].fill(0xff);
+        if done < PAGE_SIZE {
+            return Err(FlashError::PowerCut);
+        }
+        Ok(())
+    }
+}
+
+fn settings(n: u8) -> Settings {
+    Settings {
+        profile: n % 3,
+        dpad_mode: [DpadMode::Dpad, DpadMode::LeftStick, DpadMode::RightStick][n as usize % 3],
+        turbo_rate: 5 + n % 20,
+        deadzones: [
+            Some(StickDeadzone {
+                inner: n as u16 * 100,
+                outer: 30000,
+            }),
+            None,
+        ],
+        ..Default::default()
+    }
+}
+
+/// A record of settings as an older firmware wrote it
+fn record(version: u8, sequence: u32, settings: &[u8]) -> Vec<u8> {
+    let mut record = vec![0x75, 0x5e, version, settings.len() as u8];
+    record.extend(sequence.to_le_bytes());
+    record.extend(settings);
+    let crc = crc32(&record);
+    while record.len() % 4 != 0 {
+        record.push(0);
+    }
+    record.extend(crc.to_le_bytes());
+    record
+}
+
+#[test]
+fn blank_flash_has_the_defaults() {
+    let mut store = SettingsStore::new(SimFlash::new());
+    assert_eq!(store.load(), Settings::default());
+}
+
+#[test]
+fn saved_settings_load_back() {
+    let mut store = SettingsStore::new(SimFlash::new());
+    store.load();
+    store.save(&settings(1)).unwrap();
+    store.save(&settings(2)).unwrap();
+    assert_eq!(store.load(), settings(2));
+
+    let mut flash = SimFlash::new();
+    flash.bytes = store.flash().bytes.clone();
+    let mut store = SettingsStore::new(flash);
+    assert_eq!(store.load(), settings(2));
+    store.save(&settings(3)).unwrap();
+    assert_eq!(store.load(), settings(3));
+}
+
+#[test]
+fn pages_wear_evenly() {
+    let mut store = SettingsStore::new(SimFlash::new());
+    store.load();
+    for n in 0..1000 {
+        store.save(&settings(n as u8)).unwrap();
+        if n % 37 == 0 {
+            // as after a reset
+            assert_eq!(store.load(), settings(n as u8));
+        }
+    }
+    let [first, second] = store.flash().erases;
+    // records of 28 bytes, 9 to a page
+    assert!(first + second <= 112, "{} erases", first + second);
+    assert!(first.abs_diff(second) <= 1);
+    assert_eq!(store.load(), settings((999 % 256) as u8));
+}
+
+#[test]
+fn power_cut_keeps_old_or_new_settings() {
+    // saves landing in the middle of a page and moving to the other one
+    for before in [3, 10, 20] {
+        for budget in 0..=PAGE_SIZE + 32 {
+            let mut store = SettingsStore::new(SimFlash::new());
+            store.load();
+            for n in 0..before {
+                store.save(&settings(n)).unwrap();
+            }
+            let old = settings(before - 1);
+            let new = settings(before);
+
+            store.flash().budget = Some(budget);
+            let saved = store.save(&new);
+            store.flash().restore();
+
+            let loaded = store.load();
+            if saved.is_ok() {
+                assert_eq!(loaded, new);
+            } else {
+                assert!(loaded == old || loaded == new, "budget {}", budget);
+            }
+
+            // and saving still works
+            store.save(&settings(50)).unwrap();
+            assert_eq!(store.load(), settings(50));
+            store.save(&settings(51)).unwrap();
+            assert_eq!(store.load(), settings(51));
+        }
+    }
+}
+
+#[test]
+fn damaged_record_falls_back_to_the_one_before() {
+    let mut store = SettingsStore::new(SimFlash::new());
+    store.load();
+    store.save(&settings(1)).unwrap();
+    store.save(&settings(2)).unwrap();
+    // a bit flipped in the settings of the second record
+    store.flash().bytes[28 + 9] ^= 0x04;
+    assert_eq!(store.load(), settings(1));
+
+    // not appended after the damage
+    store.save(&settings(3)).unwrap();
+    assert_eq!(store.flash().erases, [0, 1]);
+    assert_eq!(store.load(), settings(3));
+}
+
+#[test]
+fn older_versions_migrate() {
+    let mut flash = SimFlash::new();
+    let old = record(1, 7, &[2, 1, 15]);
+    flash.bytes[..old.len()].copy_from_slice(&old);
+    let mut store = SettingsStore::new(flash);
+    let expected = Settings {
+        profile: 2,
+        dpad_mode: DpadMode::LeftStick,
+        turbo_rate: 15,
+        ..Default::default()
+    };
+    assert_eq!(store.load(), expected);
+
+    // written back in the current version, after it
+    store.save(&expected).unwrap();
+    assert_eq!(store.flash().erases, [0, 0]);
+    assert_eq!(store.load(), expected);
+}
+
+#[test]
+fn unknown_settings_are_the_defaults() {
+    let mut flash = SimFlash::new();
+    let newer = record(9, 1, &[1, 2, 3, 4]);
+    flash.bytes[..newer.len()].copy_from_slice(&newer);
+    let mut store = SettingsStore::new(flash);
+    assert_eq!(store.load(), Settings::default());
+
+    let mut flash = SimFlash::new();
+    // a D-pad mode that doesn't exist
+    let wrong = record(1, 1, &[0, 7, 10]);
+    flash.bytes[..wrong.len()].copy_from_slice(&wrong);
+    let mut store = SettingsStore::new(flash);
+    assert_eq!(store.load(), Settings::default());
+
+    let mut store = SettingsStore::new(SimFlash::new());
+    store.flash().bytes[..4].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+    assert_eq!(store.load(), Settings::default());
+}
+
+#[test]
+fn settings_encoding() {
+    let mut settings = Settings {
+        profile: 1,
+        dpad_mode: DpadMode::RightStick,
+        turbo_rate: 12,
+        deadzones: [
+            None,
+            Some(StickDeadzone {
+                inner: 0x1234,
+                outer: 0x7fff,
+            }),
+        ],
+        ..Default::default()
+    };
+    let mut buf = [0; SETTINGS_MAX_SIZE];
+    let len = settings.encode(&mut buf);
+    assert_eq!(
+        buf[..len],
+        [1, 2, 12, 0b10, 0, 0, 0, 0, 0x34, 0x12, 0xff, 0x7f, 0]
+    );
+    assert_eq!(Settings::decode(3, &buf[..len]), Some(settings));
+    assert_eq!(Settings::decode(2, &buf[..len - 1]), Some(settings));
+    assert_eq!(Settings::decode(2, &buf[..3]), None);
+    assert_eq!(Settings::decode(4, &buf[..len]), None);
+
+    let remap = Remap {
+        profile: 2,
+        input: InputId::Matrix { row: 1, col: 3 },
+        control: Control::RightStick(Direction::Left),
+    };
+    assert!(settings.set_remap(remap));
+    let len = settings.encode(&mut buf);
+    assert_eq!(buf[12..len], [1, 2, 0, 1, 3, 20, 2]);
+    assert_eq!(Settings::decode(3, &buf[..len]), Some(settings));
+    assert_eq!(Settings::decode(3, &buf[..len - 1]), None);
+    assert_eq!(settings.remapped(2, remap.input), Some(remap.control));
+    assert_eq!(settings.remapped(1, remap.input), None);
+
+    let shaping = SticksShaping::NONE.with_deadzones(settings.deadzones);
+    assert_eq!(shaping.left, SticksShaping::NONE.left);
+    assert_eq!((shaping.right.inner, shaping.right.outer), (0x1234, 0x7fff));
+    assert_eq!(shaping.right.curve, SticksShaping::NONE.right.curve);
+}
+
+#[test]
+fn remaps_stay_packed() {
+    let mut settings = Settings::default();
+    let remap = |n: u8| Remap {
+        profile: 0,
+        input: InputId::Direct(n),
+        control: Control::Macro(n),
+    };
+    for n in 0..MAX_REMAPS as u8 {
+        assert!(settings.set_remap(remap(n)));
+    }
+    assert!(!settings.set_remap(remap(MAX_REMAPS as u8)));
+    // replacing one still works when full
+    assert!(settings.set_remap(Remap {
+        control: Control::A,
+        ..remap(2)
+    }));
+    assert_eq!(settings.remapped(0, InputId::Direct(2)), Some(Control::A));
+
+    assert!(settings.clear_remap(0, InputId::Direct(0)));
+    assert!(!settings.clear_remap(0, InputId::Direct(0)));
+    assert_eq!(settings.remaps().count(), MAX_REMAPS - 1);
+    assert_eq!(settings.remaps().next(), Some(&remap(1)));
+
+    let mut buf = [0; SETTINGS_MAX_SIZE];
+    let len = settings.encode(&mut buf);
+    assert_eq!(len, 13 + 6 * (MAX_REMAPS - 1));
+    assert_eq!(Settings::decode(3, &buf[..len]), Some(settings));
+}
+
+#[test]
+fn remaps_after_a_gap_still_count() {
+    let remap = |n: u8| Remap {
+        profile: 0,
+        input: InputId::Direct(n),
+        control: Control::Macro(n),
+    };
+    let mut settings = Settings::default();
+    settings.remaps[0] = Some(remap(0));
+    settings.remaps[2] = Some(remap(2));
+    assert!(settings.remaps().eq([&remap(0), &remap(2)]));
+    assert_eq!(
+        settings.remapped(0, InputId::Direct(2)),
+        Some(Control::Macro(2))
+    );
+
+    // replaced where it is, not in the gap
+    assert!(settings.set_remap(Remap {
+        control: Control::A,
+        ..remap(2)
+    }));
+    assert_eq!(settings.remaps().count(), 2);
+    assert_eq!(settings.remapped(0, InputId::Direct(2)), Some(Control::A));
+
+    // packed once encoded
+    let mut buf = [0; SETTINGS_MAX_SIZE];
+    let len = settings.encode(&mut buf);
+    let decoded = Settings::decode(3, &buf[..len]).unwrap();
+    assert!(decoded.remaps().eq(settings.remaps()));
+    assert_eq!(decoded.remaps[1], settings.remaps[2]);
+    assert_eq!(decoded.remaps[2], None);
+}
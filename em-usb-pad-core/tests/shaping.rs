@@ -0,0 +1,127 @@
+//! Deadzones and response curves of the sticks.
+
+use em_usb_pad_core::shaping::{Deadzone, ResponseCurve, StickShaping, FULL, SHAPING_CURVE_POINTS};
+
+const MAX: i16 = i16::MAX;
+
+fn shaping(deadzone: Deadzone, inner: u16, outer: u16) -> StickShaping {
+    StickShaping {
+        deadzone,
+        inner,
+        outer,
+        ..StickShaping::NONE
+    }
+}
+
+#[test]
+fn none_keeps_the_stick_as_is() {
+    for position in [(0, 0), (123, -4567), (MAX, MAX), (i16::MIN, 20000)] {
+        let expected = (position.0.max(-MAX), position.1.max(-MAX));
+        assert_eq!(StickShaping::NONE.apply(position), expected);
+    }
+}
+
+#[test]
+fn axial_deadzone() {
+    let axial = shaping(Deadzone::Axial, 4000, 30000);
+    assert_eq!(axial.apply((3000, -3999)), (0, 0));
+    // only the axis out of the deadzone moves, diagonals snap to the axes
+    assert_eq!(axial.apply((10000, 3000)), (7561, 0));
+    // stretched from the deadzone to the saturation
+    assert_eq!(axial.apply((17000, -17000)), (16383, -16383));
+    assert_eq!(axial.apply((30000, -31000)), (MAX, -MAX));
+}
+
+#[test]
+fn radial_deadzones() {
+    let radial = shaping(Deadzone::Radial, 4000, MAX as u16);
+    let scaled = shaping(Deadzone::ScaledRadial, 4000, MAX as u16);
+    // inside the circle, even on the diagonal where axial would let it through
+    assert_eq!(radial.apply((2800, 2800)), (0, 0));
+    assert_eq!(scaled.apply((2800, 2800)), (0, 0));
+
+    // just out of it: radial jumps, scaled radial starts from 0
+    assert_eq!(radial.apply((4100, 0)), (4100, 0));
+    assert_eq!(scaled.apply((4100, 0)), (113, 0));
+
+    // the direction is kept
+    let (x, y) = scaled.apply((12000, 9000));
+    assert!((x as i32 * 3 - y as i32 * 4).abs() < 8, "{:?}", (x, y));
+
+    // full deflection on both
+    assert_eq!(radial.apply((MAX, 0)), (MAX, 0));
+    assert_eq!(scaled.apply((0, -MAX)), (0, -MAX));
+}
+
+#[test]
+fn outer_saturation_makes_a_circle() {
+    let saturated = shaping(Deadzone::ScaledRadial, 0, 28000);
+    assert_eq!(saturated.apply((28000, 0)), (MAX, 0));
+    assert_eq!(saturated.apply((0, -30000)), (0, -MAX));
+    // a square gate's corner ends on the circle
+    let (x, y) = saturated.apply((MAX, MAX));
+    assert_eq!(x, y);
+    assert!((23160..=23170).contains(&x), "{}", x);
+}
+
+#[test]
+fn anti_deadzone() {
+    let anti = StickShaping {
+        anti_deadzone: 8000,
+        ..shaping(Deadzone::Axial, 2000, MAX as u16)
+    };
+    assert_eq!(anti.apply((1000, 0)), (0, 0));
+    // the smallest move out of the deadzone goes over the game's deadzone
+    let (x, _) = anti.apply((2010, 0));
+    assert!((8000..8010).contains(&x), "{}", x);
+    assert_eq!(anti.apply((-MAX, MAX)), (-MAX, MAX));
+}
+
+#[test]
+fn curves() {
+    // (input, linear, 50% exponential, fully cubic)
+    let table = [
+        (0, 0, 0, 0),
+        (8192, 8192, 4352, 512),
+        (16384, 16384, 10240, 4096),
+        (FULL, FULL, FULL, FULL),
+    ];
+    for (input, linear, half, cubic) in table {
+        assert_eq!(ResponseCurve::Linear.apply(input), linear);
+        assert_eq!(
+            ResponseCurve::Exponential(50).apply(input),
+            half,
+            "{}",
+            input
+        );
+        assert_eq!(
+            ResponseCurve::Exponential(100).apply(input),
+            cubic,
+            "{}",
+            input
+        );
+    }
+    // out of range inputs are clamped
+    assert_eq!(ResponseCurve::Linear.apply(-5), 0);
+    assert_eq!(ResponseCurve::Linear.apply(40000), FULL);
+}
+
+#[test]
+fn table_curve() {
+    // flat until the middle, then straight up
+    let mut points = [0; SHAPING_CURVE_POINTS];
+    for (i, point) in points.iter_mut().enumerate().skip(8) {
+        *point = ((i - 8) * 4096).min(FULL as usize) as u16;
+    }
+    let curve = ResponseCurve::Table(points);
+    assert_eq!(curve.apply(0), 0);
+    assert_eq!(curve.apply(16383), 0);
+    assert_eq!(curve.apply(16384 + 1024), 2048);
+    assert_eq!(curve.apply(FULL), FULL);
+
+    let shaped = StickShaping {
+        curve,
+        ..StickShaping::NONE
+    };
+    assert_eq!(shaped.apply((10000, -20000)), (0, -7232));
+}
@@ -0,0 +1,199 @@
+//! Opposite directions held together, step by step.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::mapping::{bind, Control, Direction, Mapper, Profile};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::{PadCleaner, SocdConfig, SocdMode};
+use embassy_time::Instant;
+
+const U: u8 = 1 << Direction::Up as u8;
+const D: u8 = 1 << Direction::Down as u8;
+const L: u8 = 1 << Direction::Left as u8;
+const R: u8 = 1 << Direction::Right as u8;
+
+fn directions(bits: u8) -> [bool; 4] {
+    let mut directions = [false; 4];
+    for (i, direction) in directions.iter_mut().enumerate() {
+        *direction = bits & 1 << i != 0;
+    }
+    directions
+}
+
+/// Feed the (held, expected) steps to a fresh cleaner.
+fn replay(config: SocdConfig, steps: &[(u8, u8)]) {
+    let mut cleaner = PadCleaner::new();
+    for (step, &(held, expected)) in steps.iter().enumerate() {
+        assert_eq!(
+            cleaner.clean(config, directions(held)),
+            directions(expected),
+            "{:?} step {}",
+            config,
+            step
+        );
+    }
+}
+
+fn both(mode: SocdMode) -> SocdConfig {
+    SocdConfig {
+        horizontal: mode,
+        vertical: mode,
+    }
+}
+
+#[test]
+fn off() {
+    replay(SocdConfig::OFF, &[(L, L), (L | R, L | R), (U | D, U | D)]);
+}
+
+#[test]
+fn neutral() {
+    replay(
+        SocdConfig::NEUTRAL,
+        &[
+            (L, L),
+            (L | R, 0),
+            (R, R),
+            (R | U | D, R),
+            (U | D, 0),
+            (U, U),
+            (0, 0),
+        ],
+    );
+}
+
+#[test]
+fn last_input_wins() {
+    replay(
+        both(SocdMode::LastInputWins),
+        &[
+            (L, L),
+            (L | R, R),
+            // back to the one still held
+            (L, L),
+            (L | R, R),
+            (R, R),
+            (L | R, L),
+            (U | L | R, U | L),
+            (U | D | L | R, D | L),
+            (0, 0),
+            // pressed at the same time, no way to tell
+            (U | D, 0),
+            (U, U),
+        ],
+    );
+}
+
+#[test]
+fn first_input_wins() {
+    replay(
+        both(SocdMode::FirstInputWins),
+        &[
+            (L, L),
+            (L | R, L),
+            (R, R),
+            (L | R, R),
+            (U | L | R, U | R),
+            (U | D | L | R, U | R),
+            (D, D),
+            (0, 0),
+            (L | R, 0),
+        ],
+    );
+}
+
+#[test]
+fn up_priority() {
+    replay(
+        both(SocdMode::UpPriority),
+        &[
+            (D, D),
+            (U | D, U),
+            (D, D),
+            // horizontally it is neutral
+            (L | R | D, D),
+            (0, 0),
+        ],
+    );
+    replay(
+        SocdConfig::HITBOX,
+        &[(U | D, U), (U | D | L | R, U), (D | R, D | R)],
+    );
+}
+
+#[test]
+fn axes_have_their_own_modes() {
+    let config = SocdConfig {
+        horizontal: SocdMode::LastInputWins,
+        vertical: SocdMode::Off,
+    };
+    replay(config, &[(L, L), (L | R, R), (L | R | U | D, R | U | D)]);
+}
+
+const LAYOUT: Layout = Layout::new(0, 0, 8);
+
+const HITBOX: Profile = Profile {
+    name: "hitbox",
+    bindings: &[
+        bind(InputId::Direct(0), Control::DpadUp),
+        bind(InputId::Direct(1), Control::DpadDown),
+        bind(InputId::Direct(2), Control::DpadLeft),
+        bind(InputId::Direct(3), Control::DpadRight),
+        bind(InputId::Direct(4), Control::LeftStick(Direction::Up)),
+        bind(InputId::Direct(5), Control::LeftStick(Direction::Down)),
+        bind(InputId::Direct(6), Control::LeftStick(Direction::Left)),
+        bind(InputId::Direct(7), Control::LeftStick(Direction::Right)),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig {
+        horizontal: SocdMode::LastInputWins,
+        vertical: SocdMode::UpPriority,
+    },
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+static PROFILES: [Profile; 1] = [HITBOX];
+
+#[test]
+fn dpad_through_the_mapper() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    // (left, right, up, down) of the report
+    let dpad = |mapper: &mut Mapper, states: u64| {
+        let report = mapper.update(InputStates(states), Instant::from_millis(0));
+        (
+            report.dpad_left,
+            report.dpad_right,
+            report.dpad_up,
+            report.dpad_down,
+        )
+    };
+    assert_eq!(dpad(&mut mapper, 0b0100), (true, false, false, false));
+    assert_eq!(dpad(&mut mapper, 0b1100), (false, true, false, false));
+    assert_eq!(dpad(&mut mapper, 0b1111), (false, true, true, false));
+    assert_eq!(dpad(&mut mapper, 0b0110), (true, false, false, true));
+
+    // without the order of the presses, left + right is neutral
+    let report = HITBOX.report(LAYOUT, InputStates(0b1100));
+    assert!(!report.dpad_left && !report.dpad_right);
+}
+
+#[test]
+fn stick_from_buttons() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let stick = |mapper: &mut Mapper, states: u64| {
+        let report = mapper.update(InputStates(states << 4), Instant::from_millis(0));
+        (report.js_left_x, report.js_left_y)
+    };
+    assert_eq!(stick(&mut mapper, 0b1000), (i16::MAX, 0));
+    assert_eq!(stick(&mut mapper, 0b1100), (i16::MIN, 0));
+    assert_eq!(stick(&mut mapper, 0b0011), (0, i16::MAX));
+    assert_eq!(stick(&mut mapper, 0b0010), (0, i16::MIN));
+    // the D-pad keeps its own order
+    let report = mapper.update(InputStates(0b0100_0100), Instant::from_millis(0));
+    assert!(report.dpad_left);
+    assert_eq!(report.js_left_x, i16::MIN);
+}
@@ -0,0 +1,143 @@
+//! Triggers from the ADC or from buttons, through the same report.
+
+use em_usb_pad_core::analog::{AnalogSource, TriggerCalibration, Triggers, TriggersCalibration};
+use em_usb_pad_core::board::{AnalogInput, InputId, InputStates, Layout};
+use em_usb_pad_core::mapping::{bind, Control, Profile};
+use em_usb_pad_core::shaping::{ResponseCurve, SticksShaping, TriggerShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use em_usb_pad_core::xinput::XinputControlReport;
+
+/// Fixed readings, only the left trigger is wired
+struct LeftTrigger(u16);
+
+impl AnalogSource for LeftTrigger {
+    fn read(&mut self, input: AnalogInput) -> Option<u16> {
+        match input {
+            AnalogInput::LeftTrigger => Some(self.0),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn calibration() {
+    let normal = TriggerCalibration {
+        released: 500,
+        pressed: 3500,
+    };
+    // (raw, expected)
+    let table = [(0, 0), (500, 0), (2000, 127), (3500, 255), (4095, 255)];
+    for (raw, expected) in table {
+        assert_eq!(normal.to_xinput(raw), expected, "raw {}", raw);
+    }
+
+    // a hall sensor reading less when pressed
+    let reversed = TriggerCalibration {
+        released: 3000,
+        pressed: 1000,
+    };
+    assert_eq!(reversed.to_xinput(3500), 0);
+    assert_eq!(reversed.to_xinput(2000), 127);
+    assert_eq!(reversed.to_xinput(900), 255);
+
+    let broken = TriggerCalibration {
+        released: 2000,
+        pressed: 2000,
+    };
+    assert_eq!(broken.to_xinput(4095), 0);
+}
+
+#[test]
+fn shaping() {
+    assert_eq!(TriggerShaping::NONE.apply(0), 0);
+    assert_eq!(TriggerShaping::NONE.apply(128), 128);
+    assert_eq!(TriggerShaping::NONE.apply(255), 255);
+
+    let deadzones = TriggerShaping {
+        deadzone: 20,
+        outer: 235,
+        ..TriggerShaping::NONE
+    };
+    assert_eq!(deadzones.apply(20), 0);
+    assert_eq!(deadzones.apply(21), 1);
+    assert_eq!(deadzones.apply(127), 126);
+    assert_eq!(deadzones.apply(235), 255);
+    assert_eq!(deadzones.apply(250), 255);
+
+    let curved = TriggerShaping {
+        curve: ResponseCurve::Exponential(100),
+        ..TriggerShaping::NONE
+    };
+    assert_eq!(curved.apply(128), 32);
+    assert_eq!(curved.apply(255), 255);
+}
+
+#[test]
+fn hair_trigger() {
+    let hair = TriggerShaping {
+        deadzone: 10,
+        hair_trigger: Some(16),
+        ..TriggerShaping::NONE
+    };
+    assert_eq!(hair.apply(10), 0);
+    assert_eq!(hair.apply(24), 0);
+    assert_eq!(hair.apply(26), 255);
+    assert_eq!(hair.apply(200), 255);
+}
+
+const LAYOUT: Layout = Layout::new(0, 0, 3);
+
+const ARCADE: Profile = Profile {
+    name: "arcade",
+    bindings: &[
+        bind(InputId::Direct(0), Control::LT),
+        bind(InputId::Direct(1), Control::RightTrigger(100)),
+        bind(InputId::Direct(2), Control::RightTrigger(180)),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+};
+
+#[test]
+fn digital_triggers() {
+    let report = ARCADE.report(LAYOUT, InputStates(0b001));
+    assert_eq!((report.trigger_left, report.trigger_right), (255, 0));
+    let report = ARCADE.report(LAYOUT, InputStates(0b010));
+    assert_eq!((report.trigger_left, report.trigger_right), (0, 100));
+    // the deepest button wins
+    let report = ARCADE.report(LAYOUT, InputStates(0b110));
+    assert_eq!((report.trigger_left, report.trigger_right), (0, 180));
+}
+
+#[test]
+fn analog_and_digital_share_the_report() {
+    let mut triggers = Triggers::new(TriggersCalibration::FULL_RANGE);
+    assert_eq!(triggers.left(), None);
+    triggers.sample(&mut LeftTrigger(2048));
+    assert_eq!(triggers.left(), Some(127));
+    assert_eq!(triggers.right(), None);
+
+    // the analog trigger alone
+    let mut report = ARCADE.report(LAYOUT, InputStates(0));
+    triggers.apply(&mut report, &ARCADE.triggers);
+    assert_eq!((report.trigger_left, report.trigger_right), (127, 0));
+
+    // a button presses it further
+    let mut report = ARCADE.report(LAYOUT, InputStates(0b001));
+    triggers.apply(&mut report, &ARCADE.triggers);
+    assert_eq!(report.trigger_left, 255);
+
+    // but a lighter button doesn't hold it back
+    let mut report = XinputControlReport {
+        trigger_left: 50,
+        ..Default::default()
+    };
+    triggers.apply(&mut report, &TriggersShaping::NONE);
+    assert_eq!(report.trigger_left, 127);
+}
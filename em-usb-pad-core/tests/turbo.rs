@@ -0,0 +1,201 @@
+//! Turbo and autofire against a virtual clock.
+
+use em_usb_pad_core::board::{InputId, InputStates, Layout};
+use em_usb_pad_core::mapping::{bind, Control, Mapper, Profile};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+use em_usb_pad_core::turbo::{Turbo, TurboMode, DEFAULT_TURBO_RATE};
+use em_usb_pad_core::xinput::XinputControlReport;
+use embassy_time::Instant;
+
+fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+}
+
+fn a_held() -> XinputControlReport {
+    XinputControlReport {
+        button_a: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn off_by_default() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    assert!(!turbo.is_active());
+    for ms in [0, 50, 100, 150] {
+        let mut report = a_held();
+        assert_eq!(turbo.apply(&mut report, false, at(ms)), None);
+        assert_eq!(report, a_held());
+    }
+}
+
+#[test]
+fn turbo_pulses_while_held() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    assert!(turbo.set_mode(Control::A, TurboMode::Turbo, at(0)));
+    assert_eq!(turbo.mode(Control::A), TurboMode::Turbo);
+
+    // pressed at 1000 ms, 10 pulses per second: 50 ms pressed, 50 released
+    // (time, pressed, next edge)
+    let table = [
+        (1000, true, 1050),
+        (1049, true, 1050),
+        (1050, false, 1100),
+        (1099, false, 1100),
+        (1100, true, 1150),
+        (1150, false, 1200),
+    ];
+    for (ms, pressed, next) in table {
+        let mut report = a_held();
+        assert_eq!(turbo.apply(&mut report, false, at(ms)), Some(at(next)));
+        assert_eq!(report.button_a, pressed, "{} ms", ms);
+    }
+
+    // released, nothing left to pulse
+    let mut report = XinputControlReport::default();
+    assert_eq!(turbo.apply(&mut report, false, at(1210)), None);
+    assert!(!report.button_a);
+
+    // the pulses start over on the next press
+    let mut report = a_held();
+    assert_eq!(turbo.apply(&mut report, false, at(1230)), Some(at(1280)));
+    assert!(report.button_a);
+}
+
+#[test]
+fn autofire_pulses_untouched() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    turbo.set_mode(Control::DpadLeft, TurboMode::Auto, at(500));
+    // (time, pressed)
+    let table = [(500, true), (560, false), (600, true), (675, false)];
+    for (ms, pressed) in table {
+        let mut report = XinputControlReport::default();
+        turbo.apply(&mut report, false, at(ms));
+        assert_eq!(report.dpad_left, pressed, "{} ms", ms);
+        assert!(!report.button_a);
+    }
+}
+
+#[test]
+fn triggers_pulse_all_the_way() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    turbo.set_mode(Control::LT, TurboMode::Turbo, at(0));
+    turbo.set_mode(Control::RT, TurboMode::Auto, at(0));
+    let half_pulled = || XinputControlReport {
+        trigger_left: 100,
+        ..Default::default()
+    };
+
+    let mut report = half_pulled();
+    turbo.apply(&mut report, false, at(0));
+    // a held trigger keeps its value, autofire pulls all the way
+    assert_eq!((report.trigger_left, report.trigger_right), (100, 255));
+
+    let mut report = half_pulled();
+    turbo.apply(&mut report, false, at(50));
+    assert_eq!((report.trigger_left, report.trigger_right), (0, 0));
+}
+
+#[test]
+fn hotkey_cycles_the_mode_of_the_button_pressed() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    let modes = [TurboMode::Turbo, TurboMode::Auto, TurboMode::Off];
+    let mut ms = 0;
+    for mode in modes {
+        // the button is held back until released, the hotkey or not
+        let mut report = a_held();
+        turbo.apply(&mut report, true, at(ms));
+        assert!(!report.button_a);
+        assert_eq!(turbo.take_toggled(), Some((Control::A, mode)));
+        assert_eq!(turbo.take_toggled(), None);
+
+        let mut report = a_held();
+        turbo.apply(&mut report, false, at(ms + 10));
+        assert!(!report.button_a);
+        assert_eq!(turbo.mode(Control::A), mode);
+
+        let mut report = XinputControlReport::default();
+        turbo.apply(&mut report, false, at(ms + 20));
+        ms += 1000;
+    }
+    assert!(!turbo.is_active());
+
+    // holding the hotkey with a button already held doesn't switch it
+    let mut report = a_held();
+    turbo.apply(&mut report, false, at(ms));
+    let mut report = a_held();
+    turbo.apply(&mut report, true, at(ms + 10));
+    assert!(report.button_a);
+    assert_eq!(turbo.take_toggled(), None);
+}
+
+#[test]
+fn rate_sets_the_period() {
+    let mut turbo = Turbo::new(0);
+    assert_eq!(turbo.rate(), 1);
+    turbo.set_rate(25);
+    assert_eq!(turbo.rate(), 25);
+    turbo.set_mode(Control::B, TurboMode::Auto, at(0));
+    let mut report = XinputControlReport::default();
+    assert_eq!(turbo.apply(&mut report, false, at(0)), Some(at(20)));
+    assert!(report.button_b);
+}
+
+#[test]
+fn only_buttons_pulse() {
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+    assert!(!turbo.set_mode(Control::NextProfile, TurboMode::Auto, at(0)));
+    assert_eq!(turbo.mode(Control::NextProfile), TurboMode::Off);
+    assert!(!turbo.is_active());
+}
+
+const LAYOUT: Layout = Layout::new(0, 0, 3);
+const VIEW: InputId = InputId::Direct(0);
+const LB: InputId = InputId::Direct(1);
+const A: InputId = InputId::Direct(2);
+
+static PROFILES: [Profile; 1] = [Profile {
+    name: "turbo",
+    bindings: &[
+        bind(VIEW, Control::View),
+        bind(LB, Control::LB),
+        bind(A, Control::A),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::OFF,
+    dpad_hotkey: &[],
+    turbo_hotkey: &[VIEW, LB],
+    macro_hotkey: &[],
+    layer: None,
+    chords: &[],
+}];
+
+fn pressed(inputs: &[InputId]) -> InputStates {
+    let mut states = InputStates::default();
+    for input in inputs {
+        states.set(LAYOUT.index(*input).unwrap(), true);
+    }
+    states
+}
+
+#[test]
+fn mapper_holds_back_the_hotkey() {
+    let mut mapper = Mapper::new(LAYOUT, &PROFILES);
+    let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+
+    let mut report = mapper.update(pressed(&[VIEW, LB]), at(0));
+    assert!(mapper.turbo_hotkey_held());
+    assert!(!report.button_view && !report.shoulder_left);
+    turbo.apply(&mut report, mapper.turbo_hotkey_held(), at(0));
+
+    let mut report = mapper.update(pressed(&[VIEW, LB, A]), at(10));
+    turbo.apply(&mut report, mapper.turbo_hotkey_held(), at(10));
+    assert!(!report.button_a);
+    assert_eq!(turbo.mode(Control::A), TurboMode::Turbo);
+
+    let report = mapper.update(pressed(&[VIEW]), at(20));
+    assert!(!mapper.turbo_hotkey_held());
+    assert!(report.button_view);
+}
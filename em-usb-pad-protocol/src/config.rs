@@ -0,0 +1,304 @@
+//! Vendor control requests configuring the pad.
+//!
+//! The requests go to the Xinput control interface with a request code the
+//! Windows driver never sends, so it keeps ignoring them:
+//! ```text
+//! bmRequestType  0xc1 (IN) or 0x41 (OUT): vendor, to the interface
+//! bRequest       CONFIG_REQUEST
+//! wValue         command << 8 | argument
+//! wIndex         PROTOCOL_VERSION << 8 | interface
+//! ```
+//! The commands:
+//! ```text
+//! 0x00 IN   info: protocol version, settings version, profile count
+//! 0x01 IN   settings: their version, then the settings
+//! 0x02 OUT  settings: their version, then the settings, older versions too
+//! 0x03 OUT  select profile `argument`
+//! 0x04 OUT  save the settings now
+//! 0x05 IN   profile `argument`: the length of its name, its name, the
+//!           count of its bindings, its bindings, then 0 without function
+//!           key or 1, the key, the count of the bindings of its layer and
+//!           those bindings
+//! ```
+//! A device rejects the other versions of the protocol, with a stall, but for
+//! the info: its first byte is the version in all of them.
+
+use crate::controls::{Binding, InputId, BINDING_SIZE, INPUT_SIZE};
+use crate::settings::{Settings, SETTINGS_MAX_SIZE, SETTINGS_VERSION};
+
+/// bRequest of the configuration requests
+pub const CONFIG_REQUEST: u8 = 0x5e;
+
+/// bmRequestType of the requests with an IN data stage
+pub const REQUEST_TYPE_IN: u8 = 0xc1;
+
+/// bmRequestType of the requests with an OUT data stage, or none
+pub const REQUEST_TYPE_OUT: u8 = 0x41;
+
+/// The version of the commands, in wIndex
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The longest data stage of a command, the control buffer of the device
+/// must hold it
+pub const CONFIG_MAX_DATA: usize = 256;
+
+const COMMAND_INFO: u8 = 0x00;
+const COMMAND_GET_SETTINGS: u8 = 0x01;
+const COMMAND_SET_SETTINGS: u8 = 0x02;
+const COMMAND_SELECT_PROFILE: u8 = 0x03;
+const COMMAND_SAVE: u8 = 0x04;
+const COMMAND_GET_PROFILE: u8 = 0x05;
+
+/// What the device tells about itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfigInfo {
+    /// The version of the protocol it speaks
+    pub protocol: u8,
+    /// The version of the settings it sends
+    pub settings_version: u8,
+    /// How many profiles it has
+    pub profiles: u8,
+}
+
+impl ConfigInfo {
+    pub const SIZE: usize = 3;
+
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        [self.protocol, self.settings_version, self.profiles]
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [protocol, settings_version, profiles, ..] => Some(ConfigInfo {
+                protocol,
+                settings_version,
+                profiles,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A configuration request of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigRequest {
+    /// Answered with [`ConfigInfo`]
+    Info,
+    /// Answered with the settings, see [`encode_settings`]
+    GetSettings,
+    SetSettings(Settings),
+    SelectProfile(u8),
+    Save,
+    /// Answered with the bindings of a profile, see [`encode_profile`]
+    GetProfile(u8),
+}
+
+/// Why a request is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// Of another version of the protocol
+    Version(u8),
+    /// Unknown command or the wrong direction for it
+    Command(u8),
+    /// Settings that don't decode
+    Settings,
+}
+
+impl ConfigRequest {
+    /// Whether the device answers it in an IN data stage
+    pub fn is_in(&self) -> bool {
+        matches!(
+            self,
+            ConfigRequest::Info | ConfigRequest::GetSettings | ConfigRequest::GetProfile(_)
+        )
+    }
+
+    /// bmRequestType
+    pub fn request_type(&self) -> u8 {
+        if self.is_in() {
+            REQUEST_TYPE_IN
+        } else {
+            REQUEST_TYPE_OUT
+        }
+    }
+
+    /// wValue
+    pub fn value(&self) -> u16 {
+        let (command, argument) = match self {
+            ConfigRequest::Info => (COMMAND_INFO, 0),
+            ConfigRequest::GetSettings => (COMMAND_GET_SETTINGS, 0),
+            ConfigRequest::SetSettings(_) => (COMMAND_SET_SETTINGS, 0),
+            ConfigRequest::SelectProfile(index) => (COMMAND_SELECT_PROFILE, *index),
+            ConfigRequest::Save => (COMMAND_SAVE, 0),
+            ConfigRequest::GetProfile(index) => (COMMAND_GET_PROFILE, *index),
+        };
+        (command as u16) << 8 | argument as u16
+    }
+
+    /// wIndex, to the interface
+    pub fn index(interface: u8) -> u16 {
+        (PROTOCOL_VERSION as u16) << 8 | interface as u16
+    }
+
+    /// Write the OUT data stage to `buf`, returns its length.
+    pub fn encode_data(&self, buf: &mut [u8; CONFIG_MAX_DATA]) -> usize {
+        match self {
+            ConfigRequest::SetSettings(settings) => encode_settings(settings, buf),
+            _ => 0,
+        }
+    }
+
+    /// Parse a request, `data` being its OUT data stage.
+    pub fn decode(is_in: bool, value: u16, index: u16, data: &[u8]) -> Result<Self, ConfigError> {
+        let command = (value >> 8) as u8;
+        let version = (index >> 8) as u8;
+        if version != PROTOCOL_VERSION && !(is_in && command == COMMAND_INFO) {
+            return Err(ConfigError::Version(version));
+        }
+        let request = match command {
+            COMMAND_INFO => ConfigRequest::Info,
+            COMMAND_GET_SETTINGS => ConfigRequest::GetSettings,
+            COMMAND_SET_SETTINGS => {
+                ConfigRequest::SetSettings(decode_settings(data).ok_or(ConfigError::Settings)?)
+            }
+            COMMAND_SELECT_PROFILE => ConfigRequest::SelectProfile(value as u8),
+            COMMAND_SAVE => ConfigRequest::Save,
+            COMMAND_GET_PROFILE => ConfigRequest::GetProfile(value as u8),
+            _ => return Err(ConfigError::Command(command)),
+        };
+        if request.is_in() != is_in {
+            return Err(ConfigError::Command(command));
+        }
+        Ok(request)
+    }
+}
+
+/// The settings version, then the settings, returns the length.
+pub fn encode_settings(settings: &Settings, buf: &mut [u8]) -> usize {
+    let mut encoded = [0; SETTINGS_MAX_SIZE];
+    let len = settings.encode(&mut encoded);
+    buf[0] = SETTINGS_VERSION;
+    buf[1..1 + len].copy_from_slice(&encoded[..len]);
+    1 + len
+}
+
+/// The settings sent by [`encode_settings`], of this version or an older one
+pub fn decode_settings(bytes: &[u8]) -> Option<Settings> {
+    let (version, settings) = bytes.split_first()?;
+    Settings::decode(*version, settings)
+}
+
+/// A profile for [`ConfigRequest::GetProfile`], `layer` being the function
+/// key and its bindings. Returns the length, `None` if it doesn't fit in `buf`.
+pub fn encode_profile(
+    name: &str,
+    bindings: &[Binding],
+    layer: Option<(InputId, &[Binding])>,
+    buf: &mut [u8],
+) -> Option<usize> {
+    let mut writer = Writer { buf, len: 0 };
+    writer.push(&[u8::try_from(name.len()).ok()?])?;
+    writer.push(name.as_bytes())?;
+    writer.push_bindings(bindings)?;
+    match layer {
+        Some((key, bindings)) => {
+            writer.push(&[1])?;
+            writer.push(&key.encode())?;
+            writer.push_bindings(bindings)?;
+        }
+        None => writer.push(&[0])?,
+    }
+    Some(writer.len)
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Option<()> {
+        let end = self.len + bytes.len();
+        self.buf.get_mut(self.len..end)?.copy_from_slice(bytes);
+        self.len = end;
+        Some(())
+    }
+
+    fn push_bindings(&mut self, bindings: &[Binding]) -> Option<()> {
+        self.push(&[u8::try_from(bindings.len()).ok()?])?;
+        bindings
+            .iter()
+            .try_for_each(|binding| self.push(&binding.encode()))
+    }
+}
+
+/// A profile sent by [`encode_profile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileDump<'a> {
+    pub name: &'a str,
+    pub bindings: Bindings<'a>,
+    /// The function key and its bindings
+    pub layer: Option<(InputId, Bindings<'a>)>,
+}
+
+/// Encoded bindings, all valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bindings<'a>(&'a [u8]);
+
+impl<'a> Bindings<'a> {
+    pub fn len(&self) -> usize {
+        self.0.len() / BINDING_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Binding> + 'a {
+        self.0
+            .chunks_exact(BINDING_SIZE)
+            .filter_map(Binding::decode)
+    }
+
+    /// The count then the bindings at the start of `bytes`, and what's after
+    fn decode(bytes: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let (count, bytes) = bytes.split_first()?;
+        let size = *count as usize * BINDING_SIZE;
+        let bindings = bytes.get(..size)?;
+        if bindings
+            .chunks_exact(BINDING_SIZE)
+            .any(|binding| Binding::decode(binding).is_none())
+        {
+            return None;
+        }
+        Some((Bindings(bindings), &bytes[size..]))
+    }
+}
+
+/// The profile sent by [`encode_profile`], `None` if malformed
+pub fn decode_profile(bytes: &[u8]) -> Option<ProfileDump<'_>> {
+    let (len, bytes) = bytes.split_first()?;
+    let name = bytes.get(..*len as usize)?;
+    let name = core::str::from_utf8(name).ok()?;
+    let (bindings, bytes) = Bindings::decode(&bytes[*len as usize..])?;
+    let (layer, rest) = match bytes.split_first()? {
+        (0, rest) => (None, rest),
+        (1, bytes) => {
+            let key = InputId::decode(bytes.get(..INPUT_SIZE)?)?;
+            let (bindings, rest) = Bindings::decode(&bytes[INPUT_SIZE..])?;
+            (Some((key, bindings)), rest)
+        }
+        _ => return None,
+    };
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(ProfileDump {
+        name,
+        bindings,
+        layer,
+    })
+}
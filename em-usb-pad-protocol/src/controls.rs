@@ -0,0 +1,224 @@
+//! The buttons of a board and the controls they are bound to.
+//!
+//! Encoded for the host, [`InputId::encode`] and [`Control::encode`]:
+//! ```text
+//! input:   0 row col, a key of the matrix
+//!          1 pin 0, a button on its own pin
+//! control: code value, value being the trigger position, the direction
+//!          (up, down, left, right) or the macro slot
+//! ```
+
+/// A physical button of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InputId {
+    /// The key at the crossing of a row and a column of the matrix
+    Matrix { row: u8, col: u8 },
+    /// A button on its own pin
+    Direct(u8),
+}
+
+/// A direction of a stick, pushed all the way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// What the D-pad buttons drive
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DpadMode {
+    #[default]
+    Dpad,
+    /// The left stick, pushed all the way
+    LeftStick,
+    /// The right stick, pushed all the way
+    RightStick,
+}
+
+impl DpadMode {
+    /// The mode the hotkey switches to
+    pub fn next(self) -> Self {
+        match self {
+            DpadMode::Dpad => DpadMode::LeftStick,
+            DpadMode::LeftStick => DpadMode::RightStick,
+            DpadMode::RightStick => DpadMode::Dpad,
+        }
+    }
+}
+
+/// What a button does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Control {
+    A,
+    B,
+    X,
+    Y,
+    /// Left bumper
+    LB,
+    /// Right bumper
+    RB,
+    View,
+    Menu,
+    Guide,
+    /// Left stick click
+    LS,
+    /// Right stick click
+    RS,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+    /// Left trigger, fully pressed
+    LT,
+    /// Right trigger, fully pressed
+    RT,
+    /// Left trigger, pressed to the given value
+    LeftTrigger(u8),
+    /// Right trigger, pressed to the given value
+    RightTrigger(u8),
+    LeftStick(Direction),
+    RightStick(Direction),
+    /// Switch to the next profile when pressed
+    NextProfile,
+    /// Play the macro in the given slot when pressed, or record it with the
+    /// macro hotkey held
+    Macro(u8),
+}
+
+/// One line of a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub input: InputId,
+    pub control: Control,
+}
+
+/// Shorthand for the profile tables
+pub const fn bind(input: InputId, control: Control) -> Binding {
+    Binding { input, control }
+}
+
+/// Size of an encoded [`InputId`]
+pub const INPUT_SIZE: usize = 3;
+
+/// Size of an encoded [`Control`]
+pub const CONTROL_SIZE: usize = 2;
+
+/// Size of an encoded [`Binding`], the input then the control
+pub const BINDING_SIZE: usize = INPUT_SIZE + CONTROL_SIZE;
+
+impl InputId {
+    pub fn encode(&self) -> [u8; INPUT_SIZE] {
+        match *self {
+            InputId::Matrix { row, col } => [0, row, col],
+            InputId::Direct(pin) => [1, pin, 0],
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [0, row, col, ..] => Some(InputId::Matrix { row, col }),
+            [1, pin, 0, ..] => Some(InputId::Direct(pin)),
+            _ => None,
+        }
+    }
+}
+
+impl Direction {
+    fn decode(value: u8) -> Option<Self> {
+        [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
+/// The controls without a value, in the order of their codes
+const PLAIN_CONTROLS: [Control; 17] = [
+    Control::A,
+    Control::B,
+    Control::X,
+    Control::Y,
+    Control::LB,
+    Control::RB,
+    Control::View,
+    Control::Menu,
+    Control::Guide,
+    Control::LS,
+    Control::RS,
+    Control::DpadUp,
+    Control::DpadDown,
+    Control::DpadLeft,
+    Control::DpadRight,
+    Control::LT,
+    Control::RT,
+];
+
+const CODE_LEFT_TRIGGER: u8 = 17;
+const CODE_RIGHT_TRIGGER: u8 = 18;
+const CODE_LEFT_STICK: u8 = 19;
+const CODE_RIGHT_STICK: u8 = 20;
+const CODE_NEXT_PROFILE: u8 = 21;
+const CODE_MACRO: u8 = 22;
+
+impl Control {
+    pub fn encode(&self) -> [u8; CONTROL_SIZE] {
+        match *self {
+            Control::LeftTrigger(value) => [CODE_LEFT_TRIGGER, value],
+            Control::RightTrigger(value) => [CODE_RIGHT_TRIGGER, value],
+            Control::LeftStick(direction) => [CODE_LEFT_STICK, direction as u8],
+            Control::RightStick(direction) => [CODE_RIGHT_STICK, direction as u8],
+            Control::NextProfile => [CODE_NEXT_PROFILE, 0],
+            Control::Macro(slot) => [CODE_MACRO, slot],
+            plain => {
+                let code = PLAIN_CONTROLS.iter().position(|control| *control == plain);
+                [code.unwrap_or_default() as u8, 0]
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let [code, value, ..] = *bytes else {
+            return None;
+        };
+        let control = match code {
+            CODE_LEFT_TRIGGER => Control::LeftTrigger(value),
+            CODE_RIGHT_TRIGGER => Control::RightTrigger(value),
+            CODE_LEFT_STICK => Control::LeftStick(Direction::decode(value)?),
+            CODE_RIGHT_STICK => Control::RightStick(Direction::decode(value)?),
+            CODE_NEXT_PROFILE if value == 0 => Control::NextProfile,
+            CODE_MACRO => Control::Macro(value),
+            _ if value == 0 => *PLAIN_CONTROLS.get(code as usize)?,
+            _ => return None,
+        };
+        Some(control)
+    }
+}
+
+impl Binding {
+    pub fn encode(&self) -> [u8; BINDING_SIZE] {
+        let mut bytes = [0; BINDING_SIZE];
+        bytes[..INPUT_SIZE].copy_from_slice(&self.input.encode());
+        bytes[INPUT_SIZE..].copy_from_slice(&self.control.encode());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < BINDING_SIZE {
+            return None;
+        }
+        Some(Binding {
+            input: InputId::decode(&bytes[..INPUT_SIZE])?,
+            control: Control::decode(&bytes[INPUT_SIZE..])?,
+        })
+    }
+}
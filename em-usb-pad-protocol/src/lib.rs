@@ -0,0 +1,10 @@
+//! What the pad and a host exchange to configure the pad.
+//!
+//! The firmware, through `em-usb-pad-core`, and the host tools both build on
+//! it, so they agree on every byte. It only needs `core`.
+
+#![no_std]
+
+pub mod config;
+pub mod controls;
+pub mod settings;
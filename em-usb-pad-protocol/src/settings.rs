@@ -0,0 +1,217 @@
+//! What the user can change at runtime, kept across power cycles.
+//!
+//! The mappings are compiled in, [`Settings`] only picks among them and
+//! overrides a few values. It is stored and sent as a small versioned binary
+//! blob, each version decodes the older ones:
+//! ```text
+//! version 1: profile dpad_mode turbo_rate
+//! version 2: version 1, then flags (bit 0 left, bit 1 right deadzone set)
+//!            and the inner and outer deadzones of each stick, u16 LE
+//! version 3: version 2, then the count of remaps and for each its profile,
+//!            input and control
+//! ```
+
+use crate::controls::{Binding, Control, DpadMode, InputId, BINDING_SIZE};
+
+/// The version [`Settings::encode`] writes
+pub const SETTINGS_VERSION: u8 = 3;
+
+/// The most buttons bound to other controls, all profiles together
+pub const MAX_REMAPS: usize = 8;
+
+/// Size of an encoded [`Remap`]
+const REMAP_SIZE: usize = 1 + BINDING_SIZE;
+
+/// Size of version 2
+const DEADZONES_END: usize = 12;
+
+/// The longest encoded [`Settings`]
+pub const SETTINGS_MAX_SIZE: usize = DEADZONES_END + 1 + MAX_REMAPS * REMAP_SIZE;
+
+/// Turbo pulses per second at first
+pub const DEFAULT_TURBO_RATE: u8 = 10;
+
+/// Deadzones of a stick replacing the profile's
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StickDeadzone {
+    pub inner: u16,
+    pub outer: u16,
+}
+
+/// A button of a profile bound to another control than in the profile's table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Remap {
+    /// Index of the profile
+    pub profile: u8,
+    pub input: InputId,
+    pub control: Control,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Settings {
+    /// Index of the active profile
+    pub profile: u8,
+    pub dpad_mode: DpadMode,
+    /// Turbo pulses per second
+    pub turbo_rate: u8,
+    /// Left and right, `None` keeps the profile's
+    pub deadzones: [Option<StickDeadzone>; 2],
+    /// The slots used, see [`used_remaps`]
+    pub remaps: [Option<Remap>; MAX_REMAPS],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            profile: 0,
+            dpad_mode: DpadMode::Dpad,
+            turbo_rate: DEFAULT_TURBO_RATE,
+            deadzones: [None; 2],
+            remaps: [None; MAX_REMAPS],
+        }
+    }
+}
+
+impl Settings {
+    /// Write the current version to `buf`, returns its length.
+    pub fn encode(&self, buf: &mut [u8; SETTINGS_MAX_SIZE]) -> usize {
+        buf[0] = self.profile;
+        buf[1] = match self.dpad_mode {
+            DpadMode::Dpad => 0,
+            DpadMode::LeftStick => 1,
+            DpadMode::RightStick => 2,
+        };
+        buf[2] = self.turbo_rate;
+        buf[3] = 0;
+        for (n, deadzone) in self.deadzones.iter().enumerate() {
+            if deadzone.is_some() {
+                buf[3] |= 1 << n;
+            }
+            let deadzone = deadzone.unwrap_or_default();
+            buf[4 + n * 4..6 + n * 4].copy_from_slice(&deadzone.inner.to_le_bytes());
+            buf[6 + n * 4..8 + n * 4].copy_from_slice(&deadzone.outer.to_le_bytes());
+        }
+        let mut len = DEADZONES_END + 1;
+        for remap in self.remaps() {
+            buf[len] = remap.profile;
+            let binding = Binding {
+                input: remap.input,
+                control: remap.control,
+            };
+            buf[len + 1..len + REMAP_SIZE].copy_from_slice(&binding.encode());
+            len += REMAP_SIZE;
+        }
+        buf[DEADZONES_END] = ((len - DEADZONES_END - 1) / REMAP_SIZE) as u8;
+        len
+    }
+
+    /// Read settings written by `version`, this one or an older one.
+    ///
+    /// `None` if they don't make sense or come from a newer firmware.
+    pub fn decode(version: u8, bytes: &[u8]) -> Option<Self> {
+        let size = match version {
+            1 => 3,
+            2 => DEADZONES_END,
+            3 => {
+                let count = *bytes.get(DEADZONES_END)? as usize;
+                if count > MAX_REMAPS {
+                    return None;
+                }
+                DEADZONES_END + 1 + count * REMAP_SIZE
+            }
+            _ => return None,
+        };
+        if bytes.len() != size {
+            return None;
+        }
+        let mut settings = Settings {
+            profile: bytes[0],
+            dpad_mode: match bytes[1] {
+                0 => DpadMode::Dpad,
+                1 => DpadMode::LeftStick,
+                2 => DpadMode::RightStick,
+                _ => return None,
+            },
+            turbo_rate: bytes[2],
+            ..Default::default()
+        };
+        if version >= 2 {
+            for n in 0..2 {
+                if bytes[3] & 1 << n != 0 {
+                    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+                    settings.deadzones[n] = Some(StickDeadzone {
+                        inner: u16_at(4 + n * 4),
+                        outer: u16_at(6 + n * 4),
+                    });
+                }
+            }
+        }
+        if version >= 3 {
+            let remaps = bytes[DEADZONES_END + 1..].chunks_exact(REMAP_SIZE);
+            for (slot, remap) in settings.remaps.iter_mut().zip(remaps) {
+                let binding = Binding::decode(&remap[1..])?;
+                *slot = Some(Remap {
+                    profile: remap[0],
+                    input: binding.input,
+                    control: binding.control,
+                });
+            }
+        }
+        Some(settings)
+    }
+
+    /// The buttons bound to other controls
+    pub fn remaps(&self) -> impl Iterator<Item = &Remap> + '_ {
+        used_remaps(&self.remaps)
+    }
+
+    /// The control `input` of `profile` is bound to, if remapped
+    pub fn remapped(&self, profile: u8, input: InputId) -> Option<Control> {
+        self.remaps()
+            .find(|remap| remap.profile == profile && remap.input == input)
+            .map(|remap| remap.control)
+    }
+
+    /// Bind a button of a profile to another control, replacing its last
+    /// remap. Returns false when there are [`MAX_REMAPS`] already.
+    pub fn set_remap(&mut self, remap: Remap) -> bool {
+        let same = |slot: &Option<Remap>| {
+            slot.is_some_and(|old| old.profile == remap.profile && old.input == remap.input)
+        };
+        let slot = match self.remaps.iter().position(same) {
+            Some(found) => Some(found),
+            None => self.remaps.iter().position(Option::is_none),
+        };
+        match slot {
+            Some(slot) => {
+                self.remaps[slot] = Some(remap);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Give a button of a profile back its binding, returns false if it
+    /// wasn't remapped.
+    pub fn clear_remap(&mut self, profile: u8, input: InputId) -> bool {
+        let found = self.remaps.iter().position(|slot| {
+            slot.is_some_and(|remap| remap.profile == profile && remap.input == input)
+        });
+        let Some(found) = found else {
+            return false;
+        };
+        // keep the remaps first
+        self.remaps[found..].rotate_left(1);
+        self.remaps[MAX_REMAPS - 1] = None;
+        true
+    }
+}
+
+/// The remaps set in `slots`, in order. An empty slot is skipped, the ones
+/// after it still count, wherever they are read.
+pub fn used_remaps(slots: &[Option<Remap>; MAX_REMAPS]) -> impl Iterator<Item = &Remap> + '_ {
+    slots.iter().flatten()
+}
@@ -0,0 +1,6 @@
+/* STM32F103C8, the last two 1 KB pages of the flash keep the settings */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 62K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
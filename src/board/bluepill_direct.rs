@@ -0,0 +1,173 @@
+//! BluePill with every button on its own pin, no matrix.
+
+use embassy_stm32::flash::Flash;
+use embassy_stm32::gpio::{AnyPin, Input, Level, Output, Pin, Pull, Speed};
+use embassy_stm32::peripherals::{TIM4, USB};
+use embassy_stm32::pwm::simple_pwm::{PwmPin, SimplePwm};
+use embassy_stm32::time::khz;
+use embassy_stm32::usb::Driver;
+use embassy_stm32::{interrupt, Peripherals};
+use embassy_time::{Duration, Timer};
+
+use em_usb_pad_core::analog::{AnalogCalibration, NoAnalog};
+use em_usb_pad_core::board::{BoardInfo, InputId, Layout};
+use em_usb_pad_core::chords::{chord, Chord};
+use em_usb_pad_core::debounce::Debounce;
+use em_usb_pad_core::ghosting::GhostPolicy;
+use em_usb_pad_core::mapping::{bind, hotkeys_overlap, Control, Profile};
+use em_usb_pad_core::shaping::{SticksShaping, TriggersShaping};
+use em_usb_pad_core::socd::SocdConfig;
+
+use super::{BoardScanner, DirectPin, GpioLeds, PwmMotors, SettingsFlash};
+
+pub const INFO: BoardInfo = BoardInfo {
+    name: "BluePill direct",
+    layout: Layout::new(0, 0, 12),
+    analog: &[],
+    player_leds: true,
+    rumble: true,
+    // no matrix at all
+    diodes: true,
+};
+
+/// No matrix
+pub type DirectScanner = BoardScanner<[Input<'static, AnyPin>; 0], 0, 0, 12>;
+
+pub struct Board {
+    pub usb: Driver<'static, USB>,
+    /// buttons to ground on PA1-PA8, PB0, PB1, PB10 and PB11
+    pub scanner: DirectScanner,
+    /// quadrants 1 to 4 on PB12-PB15
+    pub leds: GpioLeds,
+    /// TIM4, left (heavy) motor on PB6, right (light) one on PB7
+    pub motors: PwmMotors<TIM4>,
+    /// the last two pages of the flash, for the settings
+    pub flash: SettingsFlash,
+    pub analog: NoAnalog,
+}
+
+pub async fn init(mut p: Peripherals) -> Board {
+    {
+        // BluePill board has a pull-up resistor on the D+ line.
+        // Pull the D+ pin down to send a RESET condition to the USB bus.
+        // This forced reset is needed only for development, without it host
+        // will not reset your device when you upload new firmware.
+        let _dp = Output::new(&mut p.PA12, Level::Low, Speed::Low);
+        Timer::after(Duration::from_millis(10)).await;
+    }
+
+    let irq = interrupt::take!(USB_LP_CAN1_RX0);
+    let usb = Driver::new(p.USB, irq, p.PA12, p.PA11);
+
+    let scanner = BoardScanner::new(
+        [],
+        [],
+        [
+            DirectPin::ActiveLow(Input::new(p.PA1.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA2.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA3.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA4.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA5.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA6.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA7.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PA8.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PB0.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PB1.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PB10.degrade(), Pull::Up)),
+            DirectPin::ActiveLow(Input::new(p.PB11.degrade(), Pull::Up)),
+        ],
+    );
+
+    let leds = GpioLeds {
+        quadrants: [
+            Output::new(p.PB12.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB13.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB14.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB15.degrade(), Level::Low, Speed::Low),
+        ],
+    };
+
+    let motors = PwmMotors::new(SimplePwm::new(
+        p.TIM4,
+        Some(PwmPin::new_ch1(p.PB6)),
+        Some(PwmPin::new_ch2(p.PB7)),
+        None,
+        None,
+        khz(20),
+    ));
+
+    Board {
+        usb,
+        scanner,
+        leds,
+        motors,
+        analog: NoAnalog,
+        flash: SettingsFlash::new(Flash::new(p.FLASH)),
+    }
+}
+
+/// PA1 and PB1 would share an EXTI line, the buttons are polled every
+/// millisecond while none is held.
+pub async fn wait_for_press(_scanner: &mut DirectScanner) {
+    Timer::after(Duration::from_millis(1)).await;
+}
+
+/// Nothing to ghost
+pub const GHOSTING: GhostPolicy = GhostPolicy::Off;
+
+/// Arcade buttons bounce briefly, report the press at once
+pub const DEBOUNCE: Debounce = Debounce::Eager {
+    lockout: Duration::from_millis(5),
+};
+
+/// No analog inputs
+pub const CALIBRATION: AnalogCalibration = AnalogCalibration::FULL_RANGE;
+
+/// Up + down + X, clear of the chords
+const DPAD_HOTKEY: [InputId; 3] = [InputId::Direct(0), InputId::Direct(1), InputId::Direct(6)];
+
+/// Left + right, held with a button to switch its turbo
+const TURBO_HOTKEY: [InputId; 2] = [InputId::Direct(2), InputId::Direct(3)];
+
+/// LB + RB, held with a macro button to record it. None is bound, the
+/// settings may bind some.
+const MACRO_HOTKEY: [InputId; 2] = [InputId::Direct(8), InputId::Direct(9)];
+
+/// The buttons missing from the board
+const CHORDS: [Chord; 3] = [
+    chord(&[InputId::Direct(10), InputId::Direct(11)], Control::Guide),
+    chord(&[InputId::Direct(10), InputId::Direct(4)], Control::LS),
+    chord(&[InputId::Direct(10), InputId::Direct(5)], Control::RS),
+];
+
+const _: () = assert!(!hotkeys_overlap(
+    &[&DPAD_HOTKEY, &TURBO_HOTKEY, &MACRO_HOTKEY],
+    &CHORDS
+));
+
+/// No button left to switch profiles, the default one is used
+pub static PROFILES: [Profile; 1] = [Profile {
+    name: "default",
+    bindings: &[
+        bind(InputId::Direct(0), Control::DpadUp),
+        bind(InputId::Direct(1), Control::DpadDown),
+        bind(InputId::Direct(2), Control::DpadLeft),
+        bind(InputId::Direct(3), Control::DpadRight),
+        bind(InputId::Direct(4), Control::A),
+        bind(InputId::Direct(5), Control::B),
+        bind(InputId::Direct(6), Control::X),
+        bind(InputId::Direct(7), Control::Y),
+        bind(InputId::Direct(8), Control::LB),
+        bind(InputId::Direct(9), Control::RB),
+        bind(InputId::Direct(10), Control::View),
+        bind(InputId::Direct(11), Control::Menu),
+    ],
+    shaping: SticksShaping::NONE,
+    triggers: TriggersShaping::NONE,
+    socd: SocdConfig::HITBOX,
+    dpad_hotkey: &DPAD_HOTKEY,
+    turbo_hotkey: &TURBO_HOTKEY,
+    macro_hotkey: &MACRO_HOTKEY,
+    layer: None,
+    chords: &CHORDS,
+}];
@@ -0,0 +1,306 @@
+//! BluePill with a 4x3 keypad and a button on PA0, the first prototype.
+
+use embassy_futures::select::{select, select4};
+use embassy_stm32::adc::{Adc, SampleTime};
+use embassy_stm32::exti::{Channel as _, ExtiInput};
+use embassy_stm32::flash::Flash;
+use embassy_stm32::gpio::{AnyPin, Input, Level, Output, OutputOpenDrain, Pin, Pull, Speed};
+use embassy_stm32::peripherals::{ADC1, PB0, PB1, TIM4, USB};
+use embassy_stm32::pwm::simple_pwm::{PwmPin, SimplePwm};
+use embassy_stm32::time::khz;
+use embassy_stm32::usb::Driver;
+use embassy_stm32::{interrupt, pac, Peripherals};
+use embassy_time::{Delay, Duration, Timer};
+
+use em_usb_pad_core::analog::{
+    AnalogCalibration, AnalogSource, AxisCalibration, StickCalibration, SticksCalibration,
+    TriggersCalibration,
+};
+use em_usb_pad_core::board::{ActiveHigh, AnalogInput, BoardInfo, InputId, Layout, RowPort};
+use em_usb_pad_core::debounce::Debounce;
+use em_usb_pad_core::ghosting::GhostPolicy;
+use em_usb_pad_core::mapping::{
+    bind, hotkeys_overlap, Binding, Control, Direction, Layer, Profile,
+};
+use em_usb_pad_core::shaping::{
+    Deadzone, ResponseCurve, StickShaping, SticksShaping, TriggersShaping,
+};
+use em_usb_pad_core::socd::SocdConfig;
+
+use super::{BoardScanner, DirectPin, GpioLeds, PwmMotors, SettingsFlash};
+
+pub const INFO: BoardInfo = BoardInfo {
+    name: "BluePill keypad",
+    layout: Layout::new(4, 3, 1),
+    analog: &[AnalogInput::LeftStickX, AnalogInput::LeftStickY],
+    player_leds: true,
+    rumble: true,
+    // the usual membrane keypad
+    diodes: false,
+};
+
+pub type KeypadScanner = BoardScanner<KeypadRows, 4, 3, 1>;
+
+pub struct Board {
+    pub usb: Driver<'static, USB>,
+    /// rows on PA1-PA4, columns on PA5-PA7, PA0 to VCC
+    pub scanner: KeypadScanner,
+    /// quadrants 1 to 4 on PB12-PB15
+    pub leds: GpioLeds,
+    /// TIM4, left (heavy) motor on PB6, right (light) one on PB7
+    pub motors: PwmMotors<TIM4>,
+    /// the last two pages of the flash, for the settings
+    pub flash: SettingsFlash,
+    pub analog: AdcSticks,
+}
+
+/// The rows on PA1-PA4, read from the input register of GPIOA at once
+pub struct KeypadRows {
+    pins: [ExtiInput<'static, AnyPin>; 4],
+}
+
+impl RowPort for KeypadRows {
+    fn read_rows(&mut self) -> u32 {
+        // reading the input register has no side effect
+        let port = unsafe { pac::GPIOA.idr().read().0 };
+        !(port >> 1) & 0b1111
+    }
+}
+
+impl KeypadRows {
+    /// Wait for a row to go low, see [`Scanner::listen`].
+    ///
+    /// [`Scanner::listen`]: em_usb_pad_core::board::Scanner::listen
+    async fn wait_for_low(&mut self) {
+        let [a, b, c, d] = &mut self.pins;
+        select4(
+            a.wait_for_low(),
+            b.wait_for_low(),
+            c.wait_for_low(),
+            d.wait_for_low(),
+        )
+        .await;
+    }
+}
+
+/// The left stick on ADC1, X on PB0 and Y on PB1
+pub struct AdcSticks {
+    adc: Adc<'static, ADC1>,
+    x: PB0,
+    y: PB1,
+}
+
+impl AnalogSource for AdcSticks {
+    fn read(&mut self, input: AnalogInput) -> Option<u16> {
+        match input {
+            AnalogInput::LeftStickX => Some(self.adc.read(&mut self.x)),
+            AnalogInput::LeftStickY => Some(self.adc.read(&mut self.y)),
+            _ => None,
+        }
+    }
+}
+
+pub async fn init(mut p: Peripherals) -> Board {
+    {
+        // BluePill board has a pull-up resistor on the D+ line.
+        // Pull the D+ pin down to send a RESET condition to the USB bus.
+        // This forced reset is needed only for development, without it host
+        // will not reset your device when you upload new firmware.
+        let _dp = Output::new(&mut p.PA12, Level::Low, Speed::Low);
+        Timer::after(Duration::from_millis(10)).await;
+    }
+
+    let irq = interrupt::take!(USB_LP_CAN1_RX0);
+    let usb = Driver::new(p.USB, irq, p.PA12, p.PA11);
+
+    let rows = KeypadRows {
+        pins: [
+            ExtiInput::new(Input::new(p.PA1.degrade(), Pull::Up), p.EXTI1.degrade()),
+            ExtiInput::new(Input::new(p.PA2.degrade(), Pull::Up), p.EXTI2.degrade()),
+            ExtiInput::new(Input::new(p.PA3.degrade(), Pull::Up), p.EXTI3.degrade()),
+            ExtiInput::new(Input::new(p.PA4.degrade(), Pull::Up), p.EXTI4.degrade()),
+        ],
+    };
+    let scanner = BoardScanner::new(
+        rows,
+        [
+            OutputOpenDrain::new(p.PA5.degrade(), Level::High, Speed::VeryHigh, Pull::Down),
+            OutputOpenDrain::new(p.PA6.degrade(), Level::High, Speed::VeryHigh, Pull::Down),
+            OutputOpenDrain::new(p.PA7.degrade(), Level::High, Speed::VeryHigh, Pull::Down),
+        ],
+        // the function key
+        [DirectPin::ActiveHighExti(ActiveHigh(ExtiInput::new(
+            Input::new(p.PA0.degrade(), Pull::Down),
+            p.EXTI0.degrade(),
+        )))],
+    );
+
+    let leds = GpioLeds {
+        quadrants: [
+            Output::new(p.PB12.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB13.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB14.degrade(), Level::Low, Speed::Low),
+            Output::new(p.PB15.degrade(), Level::Low, Speed::Low),
+        ],
+    };
+
+    let motors = PwmMotors::new(SimplePwm::new(
+        p.TIM4,
+        Some(PwmPin::new_ch1(p.PB6)),
+        Some(PwmPin::new_ch2(p.PB7)),
+        None,
+        None,
+        khz(20),
+    ));
+
+    let mut adc = Adc::new(p.ADC1, &mut Delay);
+    // the pots are slow to charge the sampling capacitor
+    adc.set_sample_time(SampleTime::Cycles71_5);
+    let analog = AdcSticks {
+        adc,
+        x: p.PB0,
+        y: p.PB1,
+    };
+
+    Board {
+        usb,
+        scanner,
+        leds,
+        motors,
+        analog,
+        flash: SettingsFlash::new(Flash::new(p.FLASH)),
+    }
+}
+
+/// Sleep until a key of the matrix or PA0 is pressed, on an EXTI edge.
+pub async fn wait_for_press(scanner: &mut KeypadScanner) {
+    scanner.listen();
+    let (rows, [function]) = scanner.inputs_mut();
+    select(rows.wait_for_low(), function.wait_for_press()).await;
+}
+
+/// No diodes, a key held back until the rectangle goes away beats a phantom
+/// press
+pub const GHOSTING: GhostPolicy = GhostPolicy::HoldBack;
+
+/// Cheap tactile switches chatter, an integrator rides through it
+pub const DEBOUNCE: Debounce = Debounce::Integrator {
+    time: Duration::from_millis(5),
+};
+
+/// A thumbstick module wired so that Y goes down when pushed up, no analog triggers
+pub const CALIBRATION: AnalogCalibration = AnalogCalibration {
+    sticks: SticksCalibration {
+        left: StickCalibration {
+            x: AxisCalibration::FULL_RANGE,
+            y: AxisCalibration::FULL_RANGE.inverted(),
+        },
+        right: StickCalibration::FULL_RANGE,
+    },
+    triggers: TriggersCalibration::FULL_RANGE,
+};
+
+/// Cheap pots rest a bit off centre and don't reach the ends
+const SHAPING: SticksShaping = SticksShaping {
+    left: StickShaping {
+        deadzone: Deadzone::ScaledRadial,
+        inner: 2500,
+        outer: 31000,
+        anti_deadzone: 0,
+        curve: ResponseCurve::Linear,
+    },
+    right: StickShaping::NONE,
+};
+
+const fn key(row: u8, col: u8) -> InputId {
+    InputId::Matrix { row, col }
+}
+
+/// View + Menu, Guide is on the function layer
+const DPAD_HOTKEY: [InputId; 2] = [key(0, 2), key(1, 2)];
+
+/// Left + right, held with a button to switch its turbo
+const TURBO_HOTKEY: [InputId; 2] = [key(0, 0), key(2, 0)];
+
+/// The two bottom keys of the last column, held with a macro button to
+/// record it
+const MACRO_HOTKEY: [InputId; 2] = [key(2, 2), key(3, 2)];
+
+const _: () = assert!(!hotkeys_overlap(
+    &[&DPAD_HOTKEY, &TURBO_HOTKEY, &MACRO_HOTKEY],
+    &[]
+));
+
+/// Holding PA0 reaches the buttons missing from the keypad and the macros,
+/// tapping it switches between the profiles
+const FUNCTION: Layer = Layer {
+    key: InputId::Direct(0),
+    tap: Some(Control::NextProfile),
+    bindings: &FUNCTION_BINDINGS,
+};
+
+const FUNCTION_BINDINGS: [Binding; 7] = [
+    bind(key(1, 2), Control::Guide),
+    bind(key(2, 2), Control::LS),
+    bind(key(3, 2), Control::RS),
+    bind(key(0, 1), Control::Macro(0)),
+    bind(key(1, 1), Control::Macro(1)),
+    bind(key(2, 1), Control::Macro(2)),
+    bind(key(3, 1), Control::Macro(3)),
+];
+
+pub static PROFILES: [Profile; 2] = [
+    Profile {
+        name: "default",
+        bindings: &[
+            bind(key(0, 0), Control::DpadRight),
+            bind(key(1, 0), Control::DpadUp),
+            bind(key(2, 0), Control::DpadLeft),
+            bind(key(3, 0), Control::DpadDown),
+            bind(key(0, 1), Control::B),
+            bind(key(1, 1), Control::Y),
+            bind(key(2, 1), Control::X),
+            bind(key(3, 1), Control::A),
+            bind(key(0, 2), Control::View),
+            bind(key(1, 2), Control::Menu),
+            bind(key(2, 2), Control::LB),
+            bind(key(3, 2), Control::RB),
+        ],
+        shaping: SHAPING,
+        triggers: TriggersShaping::NONE,
+        socd: SocdConfig::HITBOX,
+        dpad_hotkey: &DPAD_HOTKEY,
+        turbo_hotkey: &TURBO_HOTKEY,
+        macro_hotkey: &MACRO_HOTKEY,
+        layer: Some(FUNCTION),
+        // Guide is on the function layer
+        chords: &[],
+    },
+    // the arrows move the left stick, the bumpers become triggers
+    Profile {
+        name: "stick",
+        bindings: &[
+            bind(key(0, 0), Control::LeftStick(Direction::Right)),
+            bind(key(1, 0), Control::LeftStick(Direction::Up)),
+            bind(key(2, 0), Control::LeftStick(Direction::Left)),
+            bind(key(3, 0), Control::LeftStick(Direction::Down)),
+            bind(key(0, 1), Control::B),
+            bind(key(1, 1), Control::Y),
+            bind(key(2, 1), Control::X),
+            bind(key(3, 1), Control::A),
+            bind(key(0, 2), Control::View),
+            bind(key(1, 2), Control::Menu),
+            bind(key(2, 2), Control::LT),
+            bind(key(3, 2), Control::RT),
+        ],
+        shaping: SHAPING,
+        triggers: TriggersShaping::NONE,
+        socd: SocdConfig::HITBOX,
+        dpad_hotkey: &DPAD_HOTKEY,
+        turbo_hotkey: &TURBO_HOTKEY,
+        macro_hotkey: &MACRO_HOTKEY,
+        layer: Some(FUNCTION),
+        // Guide is on the function layer
+        chords: &[],
+    },
+];
@@ -0,0 +1,156 @@
+//! The boards we build for, one cargo feature each.
+//!
+//! Every board module provides the same items:
+//! - `INFO`, what the board has
+//! - `Board` and `async fn init(p: Peripherals) -> Board`, the peripherals in
+//!   use, `flash` being [`SettingsFlash`]
+//! - `PROFILES`, the button mappings, the first one is used at start
+//! - `CALIBRATION`, the sticks and triggers at rest and at both ends
+//! - `DEBOUNCE`, how the switches are debounced
+//! - `GHOSTING`, what to do with ambiguous keys when the matrix has no diodes
+//! - `async fn wait_for_press(scanner: &mut Scanner)`, called when no button
+//!   is held, sleeps until one is pressed or polls slower when the board can't
+//!
+//! `main.rs` only uses those, adding a board doesn't touch it.
+
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::flash::{Error as FlashError, Flash, ERASE_SIZE, FLASH_SIZE, WRITE_SIZE};
+use embassy_stm32::gpio::{AnyPin, Input, Output, OutputOpenDrain};
+use embassy_stm32::pwm::simple_pwm::SimplePwm;
+use embassy_stm32::pwm::{CaptureCompare16bitInstance, Channel as PwmChannel};
+use embedded_hal::digital::v2::InputPin;
+
+use em_usb_pad_core::board::{ActiveHigh, Scanner};
+use em_usb_pad_core::led::{LedOutput, LedQuadrants};
+use em_usb_pad_core::rumble::RumbleOutput;
+use em_usb_pad_core::store::NorFlash;
+
+#[cfg(all(feature = "board-bluepill-keypad", feature = "board-bluepill-direct"))]
+compile_error!("only one board-* feature can be enabled");
+
+#[cfg(not(any(feature = "board-bluepill-keypad", feature = "board-bluepill-direct")))]
+compile_error!("enable one of the board-* features");
+
+#[cfg(feature = "board-bluepill-keypad")]
+mod bluepill_keypad;
+#[cfg(feature = "board-bluepill-keypad")]
+pub use bluepill_keypad::*;
+
+#[cfg(feature = "board-bluepill-direct")]
+mod bluepill_direct;
+#[cfg(feature = "board-bluepill-direct")]
+pub use bluepill_direct::*;
+
+/// Scanner with columns and direct buttons on pins of any port
+pub type BoardScanner<R, const ROWS: usize, const COLS: usize, const DIRECT: usize> =
+    Scanner<R, OutputOpenDrain<'static, AnyPin>, DirectPin, ROWS, COLS, DIRECT>;
+
+/// A direct-wired button, to ground or to VCC
+pub enum DirectPin {
+    ActiveLow(Input<'static, AnyPin>),
+    ActiveHigh(ActiveHigh<Input<'static, AnyPin>>),
+    /// To VCC, on an EXTI line to sleep on
+    ActiveHighExti(ActiveHigh<ExtiInput<'static, AnyPin>>),
+}
+
+impl DirectPin {
+    /// Wait for the button to be pressed, on the rising edge of its EXTI
+    /// line. Never returns for the pins without one, poll those.
+    pub async fn wait_for_press(&mut self) {
+        match self {
+            DirectPin::ActiveHighExti(pin) => pin.0.wait_for_high().await,
+            _ => core::future::pending().await,
+        }
+    }
+}
+
+impl InputPin for DirectPin {
+    type Error = core::convert::Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        match self {
+            DirectPin::ActiveLow(pin) => InputPin::is_high(pin),
+            DirectPin::ActiveHigh(pin) => pin.is_high(),
+            DirectPin::ActiveHighExti(pin) => pin.is_high(),
+        }
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+/// One GPIO per quadrant, high lights the LED
+pub struct GpioLeds {
+    pub quadrants: [Output<'static, AnyPin>; 4],
+}
+
+impl LedOutput for GpioLeds {
+    fn set_leds(&mut self, leds: LedQuadrants) {
+        for (index, pin) in self.quadrants.iter_mut().enumerate() {
+            if leds.is_on(index as u8 + 1) {
+                pin.set_high();
+            } else {
+                pin.set_low();
+            }
+        }
+    }
+}
+
+/// Two motors on channels 1 and 2 of the same timer
+pub struct PwmMotors<T: CaptureCompare16bitInstance> {
+    pwm: SimplePwm<'static, T>,
+}
+
+impl<T: CaptureCompare16bitInstance> PwmMotors<T> {
+    pub fn new(mut pwm: SimplePwm<'static, T>) -> Self {
+        pwm.enable(PwmChannel::Ch1);
+        pwm.enable(PwmChannel::Ch2);
+        PwmMotors { pwm }
+    }
+}
+
+impl<T: CaptureCompare16bitInstance> RumbleOutput for PwmMotors<T> {
+    fn set_motors(&mut self, left: u8, right: u8) {
+        let max = self.pwm.get_max_duty() as u32;
+        self.pwm
+            .set_duty(PwmChannel::Ch1, (max * left as u32 / 255) as u16);
+        self.pwm
+            .set_duty(PwmChannel::Ch2, (max * right as u32 / 255) as u16);
+    }
+}
+
+/// The last two pages of the internal flash, where the settings are kept.
+///
+/// `memory.x` keeps the firmware below them, in 62 KB on the F103C8.
+pub struct SettingsFlash {
+    flash: Flash<'static>,
+}
+
+impl SettingsFlash {
+    /// Offset of the first page from the start of the flash
+    const START: u32 = (FLASH_SIZE - 2 * ERASE_SIZE) as u32;
+
+    pub fn new(flash: Flash<'static>) -> Self {
+        SettingsFlash { flash }
+    }
+}
+
+impl NorFlash for SettingsFlash {
+    type Error = FlashError;
+    const PAGE_SIZE: usize = ERASE_SIZE;
+    const WRITE_SIZE: usize = WRITE_SIZE;
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+        self.flash.blocking_read(Self::START + offset, buf)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        self.flash.blocking_write(Self::START + offset, bytes)
+    }
+
+    fn erase(&mut self, offset: u32) -> Result<(), FlashError> {
+        let from = Self::START + offset;
+        self.flash.blocking_erase(from, from + ERASE_SIZE as u32)
+    }
+}
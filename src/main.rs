@@ -2,118 +2,142 @@
 #![no_main]
 #![feature(type_alias_impl_trait)]
 
+use core::cell::Cell;
+
+use cortex_m::peripheral::DWT;
+
 use defmt::*;
 
 use embassy_executor::Spawner;
-use embassy_futures::join::join4;
-use embassy_stm32::exti::ExtiInput;
-use embassy_stm32::gpio::{Input, Level, Output, OutputOpenDrain, Pull, Speed};
+use embassy_futures::join::{join3, join5};
+use embassy_futures::select::{select, select4, Either, Either4};
 use embassy_stm32::time::Hertz;
-use embassy_stm32::usb::Driver;
-use embassy_stm32::{interrupt, Config};
-use embassy_time::{Duration, Timer};
+use embassy_stm32::Config;
+use embassy_time::{Duration, Instant, Timer};
 use embassy_usb::control::OutResponse;
-use embassy_usb::Builder;
+use embassy_usb::{Builder, Handler};
 use {defmt_rtt as _, panic_probe as _};
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
-
-mod xinput;
-use crate::xinput::{
-    ReportId, RequestHandler, XinputControlReport, XinputReaderWriter, XinputState,
+use embassy_sync::signal::Signal;
+
+use em_usb_pad_core::analog::{Sticks, Triggers};
+use em_usb_pad_core::board::{InputStates, ScanStats};
+use em_usb_pad_core::debounce::Debouncer;
+use em_usb_pad_core::ghosting::AntiGhost;
+use em_usb_pad_core::led::LedRing;
+use em_usb_pad_core::macros::Macros;
+use em_usb_pad_core::mapping::{Mapper, Profile};
+use em_usb_pad_core::protocol::{ConfigHandler, CONFIG_MAX_DATA};
+use em_usb_pad_core::rumble::{Rumble, RumbleConfig};
+use em_usb_pad_core::settings::Settings;
+use em_usb_pad_core::store::SettingsStore;
+use em_usb_pad_core::turbo::{Turbo, TurboMode, DEFAULT_TURBO_RATE};
+use em_usb_pad_core::xinput::{
+    self, usb_config, ReportId, RequestHandler, XinputEventHandler, XinputLedPattern,
+    XinputRawPacket, XinputReaderWriter, XinputRumbleState, XinputState,
 };
 
-use core::convert::Infallible;
-use embassy_stm32::peripherals::{PA1, PA2, PA3, PA4, PA5, PA6, PA7};
-use keypad::{embedded_hal::digital::v2::InputPin, keypad_new, keypad_struct};
+mod board;
 
 const VENDOR_STRING: &'static str = "TEST";
 const PRODUCT_STRING: &'static str = "TEST CON";
 const SERIAL_NUMBER: &'static str = "157F8F9";
 
-keypad_struct! {
-    struct MyKeypad<Error = Infallible> {
-        rows: (
-            Input<'static, PA1>,
-            Input<'static, PA2>,
-            Input<'static, PA3>,
-            Input<'static, PA4>,
-        ),
-        columns: (
-            OutputOpenDrain<'static, PA5>,
-            OutputOpenDrain<'static, PA6>,
-            OutputOpenDrain<'static, PA7>,
-        ),
-    }
-}
+// VID and PID of the Xbox 360 controller, Windows knows them
+#[cfg(not(feature = "custom-ids"))]
+const USB_IDS: (u16, u16) = (xinput::USB_XINPUT_VID, xinput::USB_XINPUT_PID);
+// the pid.codes test IDs, put yours here
+#[cfg(feature = "custom-ids")]
+const USB_IDS: (u16, u16) = (0x1209, 0x0001);
+
+// the CPU clock, the scans are timed in its cycles
+const SYSCLK_MHZ: u32 = 48;
+
+// how often the buttons are scanned while any is held
+const SCAN_PERIOD: Duration = Duration::from_micros(250);
+
+// how many scans the timing statistics are logged after
+const SCAN_STATS_EVERY: u32 = 40_000;
+
+// how often the sticks are sampled
+const ANALOG_PERIOD: Duration = Duration::from_millis(4);
+
+// how often the rumble timeout is checked, when there is one
+const RUMBLE_TICK: Duration = Duration::from_millis(100);
+
+// how long the settings stay unchanged before they are saved, sparing the
+// flash while they are being toggled
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_secs(2);
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
     let mut config = Config::default();
     config.rcc.hse = Some(Hertz(8_000_000));
-    config.rcc.sys_ck = Some(Hertz(48_000_000));
+    config.rcc.sys_ck = Some(Hertz(SYSCLK_MHZ * 1_000_000));
     config.rcc.pclk1 = Some(Hertz(24_000_000));
-    let mut p = embassy_stm32::init(config);
-
-    {
-        // BluePill board has a pull-up resistor on the D+ line.
-        // Pull the D+ pin down to send a RESET condition to the USB bus.
-        // This forced reset is needed only for development, without it host
-        // will not reset your device when you upload new firmware.
-        let _dp = Output::new(&mut p.PA12, Level::Low, Speed::Low);
-        Timer::after(Duration::from_millis(10)).await;
-    }
+    let p = embassy_stm32::init(config);
+    // the cycle counter times the scans, the timer's ticks are too coarse
+    let mut core = unwrap!(cortex_m::Peripherals::take());
+    core.DCB.enable_trace();
+    core.DWT.enable_cycle_counter();
+    let board = board::init(p).await;
+
+    info!("STM32 Xinput example on {}", board::INFO.name);
+
+    let mut store = SettingsStore::new(board.flash);
+    let settings_control = SettingsControl {
+        current: Cell::new(store.load()),
+        commands: Channel::new(),
+    };
 
-    info!("STM32 Xinput example");
-
-    // Create the driver, from the HAL.
-    let irq = interrupt::take!(USB_LP_CAN1_RX0);
-    let driver = Driver::new(p.USB, irq, p.PA12, p.PA11);
-
-    // Create embassy-usb Config
-    let mut config = embassy_usb::Config::new(0x045e, 0x028e);
-    config.max_power = 500;
-    config.max_packet_size_0 = 8;
-    config.device_class = 0xff;
-    config.device_sub_class = 0xff;
-    config.device_protocol = 0xff;
-    config.device_release = 0x0114; // BCDDevice 1.14
-    config.supports_remote_wakeup = true;
-    config.manufacturer = Some(VENDOR_STRING);
-    config.product = Some(PRODUCT_STRING);
-    config.serial_number = Some(SERIAL_NUMBER);
-    config.self_powered = true;
+    // the Xinput class, its strings make the device's too
+    let request_handler = MyRequestHandler {};
+    let xinput_config = xinput::Config {
+        vendor_string: Some(VENDOR_STRING),
+        product_string: Some(PRODUCT_STRING),
+        serial_number_string: Some(SERIAL_NUMBER),
+        request_handler: Some(&request_handler),
+        config_handler: Some(&settings_control),
+        // Windows needs them to bind its Xinput driver to other IDs only
+        ms_os_vendor_code: cfg!(feature = "custom-ids")
+            .then_some(em_usb_pad_core::msos::DEFAULT_VENDOR_CODE),
+        ..Default::default()
+    };
+    // Create embassy-usb Config, the one the descriptor tests check
+    let config = usb_config(USB_IDS.0, USB_IDS.1, &xinput_config);
 
     // Create embassy-usb DeviceBuilder using the driver and config.
     // It needs some buffers for building the descriptors.
     let mut device_descriptor = [0; 256];
     let mut config_descriptor = [0; 256];
     let mut bos_descriptor = [0; 256];
-    let mut control_buf = [0; 64];
-    let request_handler = MyRequestHandler {};
+    // holds the longest configuration request
+    let mut control_buf = [0; CONFIG_MAX_DATA];
+    let suspended_signal = Signal::<NoopRawMutex, bool>::new();
+    let host_gone_signal = Signal::<NoopRawMutex, ()>::new();
+    let mut device_handler = MyDeviceHandler {
+        suspended: &suspended_signal,
+        host_gone: &host_gone_signal,
+    };
 
     let mut state = XinputState::new();
 
     // Note: We actually don't need BOS descriptor. It's easy to change. But I'll keep it.
+    // Only the MS OS 1.0 descriptors are answered, nothing goes to the BOS.
     let mut builder = Builder::new(
-        driver,
+        board.usb,
         config,
         &mut device_descriptor,
         &mut config_descriptor,
         &mut bos_descriptor,
         &mut control_buf,
     );
+    builder.handler(&mut device_handler);
 
     // Create classes on the builder.
-    let config = crate::xinput::Config {
-        vendor_string: Some(VENDOR_STRING),
-        product_string: Some(PRODUCT_STRING),
-        serial_number_string: Some(SERIAL_NUMBER),
-        request_handler: Some(&request_handler),
-        ..Default::default()
-    };
-    let xinput = XinputReaderWriter::<_>::new(&mut builder, &mut state, config);
+    let xinput = XinputReaderWriter::<_>::new(&mut builder, &mut state, xinput_config);
 
     // Build the builder.
     let mut usb = builder.build();
@@ -121,100 +145,325 @@ async fn main(_spawner: Spawner) {
     // Run the USB device. Well, here's only the future to run.
     let usb_fut = usb.run();
 
-    // previously I use a single button to test
-    // this might be developed to a button for special functions
-    // I need abstraction.
-    let mut _button = ExtiInput::new(Input::new(p.PA0, Pull::Down), p.EXTI0);
-
     let (reader, mut writer) = xinput.split();
 
-    // prepare the keypad
-    let keypad = keypad_new!(MyKeypad {
-        rows: (
-            Input::new(p.PA1, Pull::Up),
-            Input::new(p.PA2, Pull::Up),
-            Input::new(p.PA3, Pull::Up),
-            Input::new(p.PA4, Pull::Up),
-        ),
-        columns: (
-            OutputOpenDrain::new(p.PA5, Level::High, Speed::VeryHigh, Pull::Down),
-            OutputOpenDrain::new(p.PA6, Level::High, Speed::VeryHigh, Pull::Down),
-            OutputOpenDrain::new(p.PA7, Level::High, Speed::VeryHigh, Pull::Down),
-        ),
-    });
-
-    let keys = keypad.decompose();
-
     // communication between tasks
-    let channel = Channel::<NoopRawMutex, (bool, (usize, usize)), 24>::new();
+    let channel = Channel::<NoopRawMutex, InputStates, 24>::new();
     let sender = channel.sender();
     let receiver = channel.receiver();
 
-    // scan keys and generate key events
+    // scan and debounce the buttons, send their states when they change
+    let mut scanner = board.scanner;
+    assert_eq!(scanner.layout(), board::INFO.layout);
     let keypad_fut = async {
-        let mut button_states = [false; 12];
+        let mut anti_ghost = AntiGhost::new(&board::INFO, board::GHOSTING);
+        let mut debouncer = Debouncer::new(board::DEBOUNCE);
+        let mut stats = ScanStats::new();
+        let mut previous = InputStates::default();
         loop {
-            for (row_index, row) in keys.iter().enumerate() {
-                for (col_index, key) in row.iter().enumerate() {
-                    let current_state = if key.is_low().unwrap() { true } else { false };
-                    match (current_state, button_states[col_index * 4 + row_index]) {
-                        (true, false) => {
-                            info!("Key {} pressed", (row_index, col_index));
-                            button_states[col_index * 4 + row_index] = current_state;
-                            sender.send((current_state, (row_index, col_index))).await;
-                        }
-                        (false, true) => {
-                            info!("Key {} released", (row_index, col_index));
-                            button_states[col_index * 4 + row_index] = current_state;
-                            sender.send((current_state, (row_index, col_index))).await;
-                        }
-                        _ => {}
+            let start = Instant::now();
+            let start_cycles = DWT::cycle_count();
+            scanner.scan();
+            stats.record(start, DWT::cycle_count().wrapping_sub(start_cycles));
+            let raw = anti_ghost.filter(scanner.states());
+            let states = debouncer.update(raw, start);
+            if states != previous {
+                for (input, pressed) in states.changes(previous, board::INFO.layout) {
+                    if pressed {
+                        info!("Key {} pressed", input);
+                    } else {
+                        info!("Key {} released", input);
                     }
                 }
+                sender.send(states).await;
+                previous = states;
+            }
+            if stats.scans() >= SCAN_STATS_EVERY {
+                info!(
+                    "Scans: mean {} ns, longest {} ns, longest interval {} us",
+                    stats.mean_cycles() * 1000 / SYSCLK_MHZ,
+                    stats.longest_cycles() * 1000 / SYSCLK_MHZ,
+                    stats.longest_interval().as_micros()
+                );
+                stats.reset();
+            }
+
+            let idle = InputStates::default();
+            if scanner.states() == idle && states == idle {
+                // nothing held nor settling
+                board::wait_for_press(&mut scanner).await;
+                debouncer.resume();
+                stats.resume();
+            } else {
+                Timer::after(SCAN_PERIOD).await;
             }
-            Timer::after(Duration::from_hz(120)).await; // also debounce
         }
     };
 
-    // Process key events
+    // map the buttons and sample the analog inputs, send the report when it changes
+    let mut analog = board.analog;
+    // how many times the LEDs blink to acknowledge a setting
+    let notice_signal = Signal::<NoopRawMutex, u8>::new();
+    // the settings to write to flash
+    let save_signal = Signal::<NoopRawMutex, Settings>::new();
     let in_fut = async {
-        let mut controller = XinputControlReport::default();
+        let mut mapper = Mapper::new(board::INFO.layout, &board::PROFILES);
+        let mut sticks = Sticks::new(board::CALIBRATION.sticks);
+        let mut triggers = Triggers::new(board::CALIBRATION.triggers);
+        let mut turbo = Turbo::new(DEFAULT_TURBO_RATE);
+        let mut macros = Macros::new();
+        let mut states = InputStates::default();
+        let mut sent = None;
+        let mut next_frame = None;
+
+        let mut settings = settings_control.current.get();
+        apply_settings(&settings, &mut mapper, &mut turbo);
+        // when the settings last changed, until saved
+        let mut changed_at = None;
 
         loop {
-            let (status, button) = receiver.recv().await;
-
-            let _ = match button {
-                (0, 0) => controller.dpad_right = status,
-                (1, 0) => controller.dpad_up = status,
-                (2, 0) => controller.dpad_left = status,
-                (3, 0) => controller.dpad_down = status,
-                (0, 1) => controller.button_b = status,
-                (1, 1) => controller.button_y = status,
-                (2, 1) => controller.button_x = status,
-                (3, 1) => controller.button_a = status,
-                (0, 2) => controller.button_view = status,
-                (1, 2) => controller.button_menu = status,
-                (2, 2) => controller.shoulder_left = status,
-                (3, 2) => controller.shoulder_right = status,
-                _ => {}
+            // wake up for the next macro frame or turbo pulse if it comes
+            // before the next sample
+            let sample_at = Instant::now() + ANALOG_PERIOD;
+            let wake_at = next_frame.map_or(sample_at, |frame: Instant| frame.min(sample_at));
+            if let Either::First(new_states) = select(receiver.recv(), Timer::at(wake_at)).await {
+                states = new_states;
+            }
+            sticks.sample(&mut analog);
+            triggers.sample(&mut analog);
+            let now = Instant::now();
+            let mut save_now = false;
+            while let Ok(command) = settings_control.commands.try_recv() {
+                match command {
+                    SettingsCommand::Use(new) => {
+                        apply_settings(&new, &mut mapper, &mut turbo);
+                        settings = new;
+                        changed_at = Some(now);
+                    }
+                    SettingsCommand::Save => save_now = true,
+                }
+            }
+            let mut controller = mapper.update(states, now);
+            let profile = mapper.active_profile();
+            let shaping = profile.shaping.with_deadzones(settings.deadzones);
+            sticks.apply(&mut controller, &shaping);
+            triggers.apply(&mut controller, &profile.triggers);
+            let recording = macros.recording();
+            let next_macro_frame = macros.apply(
+                &mut controller,
+                mapper.macro_hotkey_held(),
+                mapper.macro_pressed(),
+                now,
+            );
+            if macros.recording() != recording {
+                // four blinks when recording starts or stops
+                notice_signal.signal(4);
+            }
+            let next_pulse = turbo.apply(&mut controller, mapper.turbo_hotkey_held(), now);
+            next_frame = match (next_macro_frame, next_pulse) {
+                (Some(frame), Some(pulse)) => Some(frame.min(pulse)),
+                (frame, pulse) => frame.or(pulse),
             };
+            if let Some((_, mode)) = turbo.take_toggled() {
+                // one blink for off, two for turbo, three for autofire
+                notice_signal.signal(match mode {
+                    TurboMode::Off => 1,
+                    TurboMode::Turbo => 2,
+                    TurboMode::Auto => 3,
+                });
+            }
+
+            let current = Settings {
+                profile: mapper.active() as u8,
+                dpad_mode: mapper.dpad_mode(),
+                turbo_rate: turbo.rate(),
+                ..settings
+            };
+            if current != settings {
+                settings = current;
+                changed_at = Some(now);
+            }
+            settings_control.current.set(settings);
+            // saving stalls everything, wait for the buttons to be released
+            let idle = states == InputStates::default();
+            let settled = changed_at.is_some_and(|at: Instant| now - at >= SETTINGS_SAVE_DELAY);
+            if save_now || (idle && settled) {
+                changed_at = None;
+                save_signal.signal(settings);
+            }
+            if sent.as_ref() == Some(&controller) {
+                continue;
+            }
 
             match writer.write_control(&controller).await {
                 Ok(()) => {}
                 Err(e) => warn!("Failed to send report: {:?}", e),
             };
+            sent = Some(controller);
         }
     };
 
-    // read report from USB host
+    // read messages from USB host
     // basically rumble and led status
+    let led_signal = Signal::<NoopRawMutex, XinputLedPattern>::new();
+    let rumble_signal = Signal::<NoopRawMutex, XinputRumbleState>::new();
+    let host_alive_signal = Signal::<NoopRawMutex, ()>::new();
     let out_fut = async {
-        reader.run(false, &request_handler).await;
+        let mut host_events = HostEvents {
+            led: &led_signal,
+            rumble: &rumble_signal,
+            host_alive: &host_alive_signal,
+        };
+        reader.run(&mut host_events).await;
     };
 
+    let rumble_fut = async {
+        let mut rumble = Rumble::new(board.motors, RumbleConfig::default());
+        loop {
+            match select4(
+                rumble_signal.wait(),
+                host_alive_signal.wait(),
+                suspended_signal.wait(),
+                select(host_gone_signal.wait(), Timer::after(RUMBLE_TICK)),
+            )
+            .await
+            {
+                Either4::First(state) => rumble.set(state, Instant::now()),
+                Either4::Second(()) => rumble.keep_alive(Instant::now()),
+                Either4::Third(suspended) => rumble.set_suspended(suspended),
+                Either4::Fourth(Either::First(())) => rumble.stop(),
+                Either4::Fourth(Either::Second(())) => rumble.tick(Instant::now()),
+            }
+        }
+    };
+
+    // write the settings to flash. Erasing a page stalls the CPU and the USB
+    // for tens of milliseconds, no report goes out meanwhile, so the saves
+    // wait for the buttons to be released unless the host asks for one.
+    let save_fut = async {
+        loop {
+            let settings = save_signal.wait().await;
+            match store.save(&settings) {
+                Ok(()) => info!("Settings saved: {:?}", settings),
+                Err(e) => warn!("Failed to save the settings: {:?}", e),
+            }
+        }
+    };
+
+    let mut led_ring = LedRing::new(board.leds);
+    let led_fut = led_ring.run(&led_signal, &notice_signal);
+
     // Run everything concurrently.
     // If we had made everything `'static` above instead, we could do this using separate tasks instead.
-    join4(usb_fut, in_fut, out_fut, keypad_fut).await;
+    join3(
+        usb_fut,
+        join5(in_fut, out_fut, keypad_fut, led_fut, rumble_fut),
+        save_fut,
+    )
+    .await;
+}
+
+/// Use `settings` from now on
+fn apply_settings(settings: &Settings, mapper: &mut Mapper, turbo: &mut Turbo) {
+    mapper.select(settings.profile as usize);
+    mapper.set_remaps(&settings.remaps);
+    mapper.set_dpad_mode(settings.dpad_mode);
+    turbo.set_rate(settings.turbo_rate);
+}
+
+/// What the host asks for the settings, carried out by the input future
+enum SettingsCommand {
+    Use(Settings),
+    Save,
+}
+
+/// Answers the configuration requests of the host
+struct SettingsControl {
+    /// The settings in use, kept up to date by the input future
+    current: Cell<Settings>,
+    commands: Channel<NoopRawMutex, SettingsCommand, 4>,
+}
+
+impl ConfigHandler for SettingsControl {
+    fn profiles(&self) -> &'static [Profile] {
+        &board::PROFILES
+    }
+
+    fn settings(&self) -> Settings {
+        self.current.get()
+    }
+
+    fn set_settings(&self, settings: Settings) -> bool {
+        let profiles = board::PROFILES.len();
+        let remaps_valid = settings
+            .remaps()
+            .all(|remap| (remap.profile as usize) < profiles);
+        if settings.profile as usize >= profiles || settings.turbo_rate == 0 || !remaps_valid {
+            return false;
+        }
+        let sent = self.commands.try_send(SettingsCommand::Use(settings));
+        if sent.is_ok() {
+            // read back as they are, before the input future gets them
+            self.current.set(settings);
+        }
+        sent.is_ok()
+    }
+
+    fn save(&self) -> bool {
+        self.commands.try_send(SettingsCommand::Save).is_ok()
+    }
+}
+
+/// Dispatches the messages from the host to the futures acting on them
+struct HostEvents<'a> {
+    led: &'a Signal<NoopRawMutex, XinputLedPattern>,
+    rumble: &'a Signal<NoopRawMutex, XinputRumbleState>,
+    host_alive: &'a Signal<NoopRawMutex, ()>,
+}
+
+impl<'a> XinputEventHandler for HostEvents<'a> {
+    fn on_rumble(&mut self, rumble: XinputRumbleState) {
+        debug!("Rumble {:?}", rumble);
+        self.rumble.signal(rumble);
+    }
+
+    fn on_led(&mut self, pattern: XinputLedPattern) {
+        debug!("LED pattern {:?}", pattern);
+        self.led.signal(pattern);
+        self.host_alive.signal(());
+    }
+
+    fn on_unknown(&mut self, packet: XinputRawPacket) {
+        debug!("Unhandled message from host: {=[u8]}", packet.as_bytes());
+        self.host_alive.signal(());
+    }
+}
+
+/// Tracks the USB bus state for the futures that care
+struct MyDeviceHandler<'a> {
+    suspended: &'a Signal<NoopRawMutex, bool>,
+    /// The bus is reset or the device deconfigured, the host no longer
+    /// drives it
+    host_gone: &'a Signal<NoopRawMutex, ()>,
+}
+
+impl<'a> Handler for MyDeviceHandler<'a> {
+    fn reset(&mut self) {
+        info!("USB reset");
+        self.host_gone.signal(());
+    }
+
+    fn configured(&mut self, configured: bool) {
+        info!("USB configured: {}", configured);
+        if !configured {
+            self.host_gone.signal(());
+        }
+    }
+
+    fn suspended(&mut self, suspended: bool) {
+        info!("USB suspended: {}", suspended);
+        self.suspended.signal(suspended);
+    }
 }
 
 struct MyRequestHandler {}